@@ -0,0 +1,67 @@
+import QtQuick 2.15
+import QtQuick.Controls 2.15
+import QtQuick.Layouts 1.15
+
+// HyprDM built-in greeter, used when the configured interface has no main.qml
+ApplicationWindow {
+    id: root
+    visible: true
+    width: 1280
+    height: 800
+    title: qsTr("HyprDM")
+    color: "#1e1e2e"
+
+    property string lastError: ""
+
+    // HyprDM waits for this line to know the greeter is up
+    Component.onCompleted: console.log("hyprdm:ready")
+
+    function login() {
+        if (typeof UserBackend === "undefined" || !UserBackend) {
+            lastError = qsTr("Backend not available")
+            return
+        }
+        if (!UserBackend.authenticate(passwordField.text)) {
+            lastError = qsTr("Authentication failed")
+            passwordField.text = ""
+            return
+        }
+        if (typeof SessionBackend !== "undefined" && SessionBackend)
+            SessionBackend.start()
+    }
+
+    ColumnLayout {
+        anchors.centerIn: parent
+        spacing: 12
+        width: 320
+
+        Label {
+            text: (typeof UserBackend !== "undefined" && UserBackend) ? UserBackend.username : ""
+            color: "#cdd6f4"
+            font.pixelSize: 24
+            Layout.alignment: Qt.AlignHCenter
+        }
+
+        TextField {
+            id: passwordField
+            echoMode: TextInput.Password
+            placeholderText: qsTr("Password")
+            focus: true
+            Layout.fillWidth: true
+            onAccepted: root.login()
+        }
+
+        Button {
+            text: qsTr("Log in")
+            Layout.fillWidth: true
+            onClicked: root.login()
+        }
+
+        Label {
+            text: root.lastError
+            color: "#f38ba8"
+            visible: text.length > 0
+            Layout.alignment: Qt.AlignHCenter
+        }
+    }
+}
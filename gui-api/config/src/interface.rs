@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+use crate::parser::quickshell_dirs;
+
+/// Greeter interface API version provided by this hdm_api build
+pub const INTERFACE_API_VERSION: u32 = 1;
+
+/// Manifest file name inside an interface directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// System-wide interface root, used for installs with --system
+pub const SYSTEM_INTERFACE_ROOT: &str = "/usr/share/hyprdm/quickshell";
+
+/// Per-user interface root, the first directory searched
+pub fn user_interface_root() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("hyprdm/quickshell"))
+}
+
+/// `manifest.json` of a Quickshell greeter interface
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct InterfaceManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Preview image, relative to the interface directory
+    #[serde(default)]
+    pub preview: Option<String>,
+    /// Minimum INTERFACE_API_VERSION the interface needs
+    #[serde(default = "default_api_version")]
+    pub api_version: u32,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+fn default_api_version() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InterfaceStatus {
+    Ok,
+    /// Usable, but the directory carries no manifest
+    NoManifest,
+    InvalidManifest(String),
+    MissingMainQml,
+    RequiresNewerApi { required: u32 },
+}
+
+impl InterfaceStatus {
+    pub fn is_usable(&self) -> bool {
+        matches!(self, InterfaceStatus::Ok | InterfaceStatus::NoManifest)
+    }
+}
+
+/// An interface directory found in one of the search roots
+#[derive(Clone, Debug)]
+pub struct InstalledInterface {
+    /// Directory name, the value used for greeter_interface
+    pub id: String,
+    pub dir: PathBuf,
+    pub manifest: Option<InterfaceManifest>,
+    pub status: InterfaceStatus,
+}
+
+impl InstalledInterface {
+    pub fn main_qml(&self) -> PathBuf {
+        self.dir.join("main.qml")
+    }
+
+    /// The manifest's preview image, None when it is missing or points outside the
+    /// interface directory
+    pub fn preview_path(&self) -> Option<PathBuf> {
+        let preview = Path::new(self.manifest.as_ref()?.preview.as_ref()?);
+        if !preview.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return None;
+        }
+        // Links inside the interface may still point elsewhere
+        let dir = self.dir.canonicalize().ok()?;
+        let path = dir.join(preview).canonicalize().ok()?;
+        path.starts_with(&dir).then_some(path)
+    }
+}
+
+/// Read `manifest.json` from an interface directory, Ok(None) if it has none
+pub fn load_manifest(dir: &Path) -> Result<Option<InterfaceManifest>, String> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let manifest: InterfaceManifest = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid manifest {:?}: {}", path, e))?;
+    if manifest.name.trim().is_empty() {
+        return Err(format!("Invalid manifest {:?}: name is empty", path));
+    }
+    Ok(Some(manifest))
+}
+
+/// Check a single interface directory
+pub fn inspect_interface(dir: &Path) -> InstalledInterface {
+    let id = dir.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let (manifest, status) = match load_manifest(dir) {
+        Err(e) => (None, InterfaceStatus::InvalidManifest(e)),
+        Ok(None) => (None, InterfaceStatus::NoManifest),
+        Ok(Some(m)) if m.api_version > INTERFACE_API_VERSION => {
+            let required = m.api_version;
+            (Some(m), InterfaceStatus::RequiresNewerApi { required })
+        }
+        Ok(Some(m)) => (Some(m), InterfaceStatus::Ok),
+    };
+
+    let status = if dir.join("main.qml").is_file() {
+        status
+    } else {
+        InterfaceStatus::MissingMainQml
+    };
+
+    InstalledInterface { id, dir: dir.to_path_buf(), manifest, status }
+}
+
+/// Every interface in the quickshell search roots; when the same name
+/// exists in several roots only the one that takes precedence is listed
+pub fn discover_interfaces() -> Vec<InstalledInterface> {
+    discover_interfaces_in(&quickshell_dirs())
+}
+
+pub fn discover_interfaces_in(roots: &[PathBuf]) -> Vec<InstalledInterface> {
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+
+    for root in roots {
+        let mut dirs: Vec<PathBuf> = match fs::read_dir(root) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect(),
+            Err(_) => continue,
+        };
+        dirs.sort();
+
+        for dir in dirs {
+            let interface = inspect_interface(&dir);
+            if interface.id.starts_with('.') || !seen.insert(interface.id.clone()) {
+                continue;
+            }
+            found.push(interface);
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    const MAIN: &str = "import Quickshell\n";
+
+    fn manifest(preview: &str, api_version: u32) -> String {
+        format!(
+            r#"{{"name": "Hyproman", "version": "1.2.0", "author": "hdm", "preview": "{}", "api_version": {}, "features": ["clock"]}}"#,
+            preview, api_version
+        )
+    }
+
+    #[test]
+    fn a_complete_interface_is_usable() {
+        let root = TempDir::new("iface-ok");
+        root.write("Hyproman/main.qml", MAIN);
+        root.write("Hyproman/manifest.json", &manifest("shot.png", INTERFACE_API_VERSION));
+
+        let iface = inspect_interface(&root.join("Hyproman"));
+        assert_eq!(iface.id, "Hyproman");
+        assert_eq!(iface.status, InterfaceStatus::Ok);
+        let manifest = iface.manifest.unwrap();
+        assert_eq!((manifest.version.as_str(), manifest.features), ("1.2.0", vec!["clock".to_string()]));
+    }
+
+    #[test]
+    fn statuses_for_broken_interfaces() {
+        let root = TempDir::new("iface-status");
+        root.write("Bare/main.qml", MAIN);
+        root.write("Newer/main.qml", MAIN);
+        root.write("Newer/manifest.json", &manifest("", INTERFACE_API_VERSION + 1));
+        root.write("Broken/main.qml", MAIN);
+        root.write("Broken/manifest.json", "{ not json");
+        root.write("Nameless/main.qml", MAIN);
+        root.write("Nameless/manifest.json", r#"{"name": " ", "version": "1"}"#);
+        root.write("NoQml/manifest.json", &manifest("", 1));
+
+        let status = |id: &str| inspect_interface(&root.join(id)).status;
+        assert_eq!(status("Bare"), InterfaceStatus::NoManifest);
+        assert!(status("Bare").is_usable());
+        assert_eq!(status("Newer"), InterfaceStatus::RequiresNewerApi { required: INTERFACE_API_VERSION + 1 });
+        assert!(matches!(status("Broken"), InterfaceStatus::InvalidManifest(_)));
+        assert!(matches!(status("Nameless"), InterfaceStatus::InvalidManifest(e) if e.contains("name is empty")));
+        assert_eq!(status("NoQml"), InterfaceStatus::MissingMainQml);
+        assert!(!status("NoQml").is_usable());
+    }
+
+    #[test]
+    fn earlier_roots_shadow_later_ones_and_hidden_dirs_are_skipped() {
+        let (user, system) = (TempDir::new("iface-user"), TempDir::new("iface-system"));
+        user.write("Hyproman/main.qml", MAIN);
+        user.write(".cache/main.qml", MAIN);
+        system.write("Hyproman/main.qml", MAIN);
+        system.write("Alpha/main.qml", MAIN);
+        system.write("stray.qml", MAIN);
+
+        let found = discover_interfaces_in(&[user.path().to_path_buf(), system.path().to_path_buf()]);
+        let ids: Vec<_> = found.iter().map(|i| (i.id.as_str(), i.dir.starts_with(user.path()))).collect();
+        assert_eq!(ids, [("Hyproman", true), ("Alpha", false)]);
+    }
+
+    #[test]
+    fn preview_stays_inside_the_interface() {
+        let root = TempDir::new("iface-preview");
+        root.write("secret.png", "secret");
+        root.write("Theme/shots/preview.png", "png");
+        std::os::unix::fs::symlink(root.join("secret.png"), root.join("Theme/link.png")).unwrap();
+
+        let preview = |path: &str| {
+            root.write("Theme/manifest.json", &manifest(path, 1));
+            inspect_interface(&root.join("Theme")).preview_path()
+        };
+        let inside = preview("shots/preview.png").unwrap();
+        assert_eq!(inside, root.path().canonicalize().unwrap().join("Theme/shots/preview.png"));
+        assert!(preview("./shots/preview.png").is_some());
+        assert_eq!(preview("../secret.png"), None);
+        assert_eq!(preview("shots/../../secret.png"), None);
+        assert_eq!(preview(&root.join("secret.png").to_string_lossy()), None);
+        assert_eq!(preview("link.png"), None);
+        assert_eq!(preview("missing.png"), None);
+    }
+}
@@ -1,2 +1,7 @@
 pub mod parser;
-pub use parser::{HDMConfig, save_config, load_config, load_or_create_config};
+pub mod interface;
+#[cfg(test)]
+mod test_util;
+pub use parser::{HDMConfig, save_config, load_config, load_or_create_config, quickshell_dirs, builtin_quickshell_ui};
+pub use interface::{InterfaceManifest, InstalledInterface, InterfaceStatus, discover_interfaces, INTERFACE_API_VERSION};
+pub use interface::{inspect_interface, user_interface_root, SYSTEM_INTERFACE_ROOT};
@@ -1,18 +1,117 @@
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 
+use crate::interface::{inspect_interface, InterfaceStatus};
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct HDMConfig {
     pub theme: String,
     pub tiling: bool,
     pub default_session: String,
+    /// Tried in order when default_session is not installed
+    pub default_session_fallbacks: Vec<String>,
+    /// Quickshell interface directory shown by the greeter
+    pub greeter_interface: String,
+    /// Tried in order when greeter_interface has no main.qml
+    pub greeter_interface_fallbacks: Vec<String>,
+    /// Private Wayland socket the greeter compositor listens on
+    pub greeter_wayland_display: String,
+    pub greeter_log_path: Option<String>,
+    /// Consecutive greeter crashes tolerated before giving up
+    pub greeter_max_restarts: u32,
+    /// Consecutive greeter crashes before switching to the built-in interface
+    pub greeter_fallback_after: u32,
+    /// Unprivileged account the greeter UI runs as
+    pub greeter_user: String,
+    /// Socket the greeter uses to reach the root daemon
+    pub daemon_socket: String,
+    /// Compositor hosting the greeter: "builtin" or "hyprland"
+    pub greeter_compositor: String,
+    /// Hyprland binary used when greeter_compositor is "hyprland"
+    pub greeter_hyprland: String,
+    pub greeter_kb_layout: String,
+    pub greeter_kb_variant: Option<String>,
+    pub greeter_kb_options: Option<String>,
+    /// Monitor layout of the greeter: "last_user", "none" or a hyprland.conf path
+    pub greeter_monitors: String,
     pub autologin: bool,
     pub two_factor_enabled: bool,
     pub two_factor_method: Option<String>,
     pub two_factor_secret: Option<String>,
     pub systemctl_usedefine: bool,
+    pub session_log_path: Option<String>,
+    pub session_log_max_size: u64,
+    pub session_log_journald: bool,
+    pub crash_policy: String,
+    pub crash_window_secs: u64,
+    pub crash_max_retries: u32,
+    pub session_stop_grace_secs: u64,
+    pub session_launch_mode: String,
+    pub uwsm_wrap: String,
+    pub session_wrapper: Option<String>,
+    pub greeter_setup_script: Option<String>,
+    pub session_setup_script: Option<String>,
+    pub session_cleanup_script: Option<String>,
+    pub hook_timeout_secs: u64,
+    /// Snapshot the Hyprland workspace layout when a session stops, off unless enabled
+    pub workspace_snapshot: bool,
+    /// group -> session IDs/names the group may use, "*" for all
+    pub session_allow: HashMap<String, Vec<String>>,
+    pub session_hide: Vec<String>,
+    /// session ID/name -> displayed name
+    pub session_aliases: HashMap<String, String>,
+}
+
+impl Default for HDMConfig {
+    fn default() -> Self {
+        Self {
+            theme: "Adwaita-dark".into(),
+            tiling: true,
+            default_session: "Hyprland".into(),
+            default_session_fallbacks: Vec::new(),
+            greeter_interface: "Hyproman".into(),
+            greeter_interface_fallbacks: Vec::new(),
+            greeter_wayland_display: "wayland-hyprdm".into(),
+            greeter_log_path: None,
+            greeter_max_restarts: 5,
+            greeter_fallback_after: 3,
+            greeter_user: "hyprdm".into(),
+            daemon_socket: "/run/hyprdm/greeter.sock".into(),
+            greeter_compositor: "builtin".into(),
+            greeter_hyprland: "Hyprland".into(),
+            greeter_kb_layout: "us".into(),
+            greeter_kb_variant: None,
+            greeter_kb_options: None,
+            greeter_monitors: "last_user".into(),
+            autologin: true,
+            two_factor_enabled: false,
+            two_factor_method: None,
+            two_factor_secret: None,
+            systemctl_usedefine: false,
+            session_log_path: None,
+            session_log_max_size: 1024 * 1024,
+            session_log_journald: false,
+            crash_policy: "return_to_greeter".into(),
+            crash_window_secs: 10,
+            crash_max_retries: 3,
+            session_stop_grace_secs: 5,
+            session_launch_mode: "direct".into(),
+            uwsm_wrap: "false".into(),
+            session_wrapper: None,
+            greeter_setup_script: None,
+            session_setup_script: None,
+            session_cleanup_script: None,
+            hook_timeout_secs: 30,
+            workspace_snapshot: false,
+            session_allow: HashMap::new(),
+            session_hide: Vec::new(),
+            session_aliases: HashMap::new(),
+        }
+    }
 }
 
 impl HDMConfig {
@@ -23,6 +122,42 @@ impl HDMConfig {
         if self.default_session.is_empty() {
             self.default_session = "Hyprland".to_string();
         }
+        if self.greeter_interface.is_empty() {
+            self.greeter_interface = "Hyproman".to_string();
+        }
+        if self.greeter_wayland_display.is_empty() {
+            self.greeter_wayland_display = "wayland-hyprdm".to_string();
+        }
+        if self.greeter_user.is_empty() {
+            self.greeter_user = "hyprdm".to_string();
+        }
+        if self.daemon_socket.is_empty() {
+            self.daemon_socket = "/run/hyprdm/greeter.sock".to_string();
+        }
+        if self.greeter_hyprland.is_empty() {
+            self.greeter_hyprland = "Hyprland".to_string();
+        }
+        if self.greeter_kb_layout.is_empty() {
+            self.greeter_kb_layout = "us".to_string();
+        }
+        if self.greeter_monitors.is_empty() {
+            self.greeter_monitors = "last_user".to_string();
+        }
+
+        if !matches!(self.greeter_compositor.as_str(), "builtin" | "hyprland") {
+            eprintln!("Unknown greeter_compositor '{}', using builtin", self.greeter_compositor);
+            self.greeter_compositor = "builtin".to_string();
+        }
+
+        if !matches!(self.crash_policy.as_str(), "return_to_greeter" | "retry" | "fallback") {
+            eprintln!("Unknown crash_policy '{}', using return_to_greeter", self.crash_policy);
+            self.crash_policy = "return_to_greeter".to_string();
+        }
+
+        if !matches!(self.session_launch_mode.as_str(), "direct" | "scope" | "user-scope") {
+            eprintln!("Unknown session_launch_mode '{}', using direct", self.session_launch_mode);
+            self.session_launch_mode = "direct".to_string();
+        }
 
         if self.autologin && self.systemctl_usedefine {
             panic!("autologin and systemctl_usedefine cannot both be true!");
@@ -31,41 +166,77 @@ impl HDMConfig {
         self
     }
 
-    /// Kullanıcı tarafından seçilen arayüzün main.qml yolunu bulur
+    /// Kullanıcı tarafından seçilen arayüzün main.qml yolunu bulur,
+    /// bulunamazsa greeter_interface_fallbacks sırayla denenir
     pub fn find_quickshell_ui(&self) -> Option<PathBuf> {
-        let interface_name = &self.default_session;
-
-        let dirs = vec![
-            dirs::config_dir().map(|d| d.join("hyprdm/quickshell")), // ~/.config/hyprdm/quickshell
-            dirs::data_local_dir().map(|d| d.join("quickshell")),     // ~/.local/share/quickshell
-            Some(PathBuf::from("/usr/share/hyprdm/quickshell")),      // /usr/share/hyprdm/quickshell
-        ];
-
-        for dir_opt in dirs {
-            if let Some(dir) = dir_opt {
-                let path = dir.join(interface_name).join("main.qml");
-                if path.exists() && path.is_file() {
-                    return Some(path);
+        let mut names = vec![self.greeter_interface.clone()];
+        names.extend(self.greeter_interface_fallbacks.iter().cloned());
+
+        for interface_name in &names {
+            for dir in quickshell_dirs() {
+                let interface_dir = dir.join(interface_name);
+                if !interface_dir.is_dir() {
+                    continue;
+                }
+                let interface = inspect_interface(&interface_dir);
+                match interface.status {
+                    InterfaceStatus::Ok | InterfaceStatus::NoManifest => return Some(interface.main_qml()),
+                    InterfaceStatus::MissingMainQml => {}
+                    status => eprintln!("Skipping interface {:?}: {:?}", interface_dir, status),
                 }
             }
         }
 
         None
     }
+
+    /// Arayüz bulunamazsa yerleşik varsayılan greeter'a düşer
+    pub fn resolve_quickshell_ui(&self) -> Result<PathBuf, String> {
+        match self.find_quickshell_ui() {
+            Some(path) => Ok(path),
+            None => {
+                eprintln!(
+                    "No main.qml for interface '{}' or its fallbacks, using the built-in greeter",
+                    self.greeter_interface
+                );
+                builtin_quickshell_ui()
+            }
+        }
+    }
+
+    /// uwsm_wrap: "true" tüm oturumlar, "false" hiçbiri, aksi halde virgülle ayrılmış oturum ID/isim listesi
+    pub fn uwsm_wraps(&self, desktop_id: Option<&str>, name: &str) -> bool {
+        match self.uwsm_wrap.trim() {
+            "true" => true,
+            "false" | "" => false,
+            list => list
+                .split(',')
+                .map(|s| s.trim())
+                .any(|s| s == name || Some(s) == desktop_id),
+        }
+    }
+
+    /// Session log dosyasının yolunu verilen home dizinine göre çözer
+    pub fn session_log_file(&self, home: &Path) -> PathBuf {
+        match &self.session_log_path {
+            Some(p) if p.starts_with("~/") => home.join(&p[2..]),
+            Some(p) => PathBuf::from(p),
+            None => home.join(".local/state/hyprdm/session.log"),
+        }
+    }
+
+    /// Log file for the greeter's Quickshell output
+    pub fn greeter_log_file(&self) -> PathBuf {
+        self.greeter_log_path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/var/log/hyprdm/greeter.log"))
+    }
 }
 
 pub fn load_config(path: &Path) -> Result<HDMConfig, String> {
     if !path.exists() {
-        return Ok(HDMConfig {
-            theme: "Adwaita-dark".into(),
-            tiling: true,
-            default_session: "Hyprland".into(),
-            autologin: true,
-            two_factor_enabled: false,
-            two_factor_method: None,
-            two_factor_secret: None,
-            systemctl_usedefine: false,
-        });
+        return Ok(HDMConfig::default());
     }
 
     let content = fs::read_to_string(path)
@@ -86,21 +257,152 @@ pub fn load_config(path: &Path) -> Result<HDMConfig, String> {
         theme: map.get("theme").cloned().unwrap_or_else(|| "Adwaita-dark".into()),
         tiling: map.get("tiling").map(|v| v == "true").unwrap_or(true),
         default_session: map.get("default_session").cloned().unwrap_or_else(|| "Hyprland".into()),
+        default_session_fallbacks: map.get("default_session_fallbacks").map(|v| split_list(v)).unwrap_or_default(),
+        greeter_interface: map.get("greeter_interface").cloned().unwrap_or_else(|| "Hyproman".into()),
+        greeter_interface_fallbacks: map.get("greeter_interface_fallbacks").map(|v| split_list(v)).unwrap_or_default(),
+        greeter_wayland_display: map.get("greeter_wayland_display").cloned().unwrap_or_else(|| "wayland-hyprdm".into()),
+        greeter_log_path: map.get("greeter_log_path").cloned(),
+        greeter_max_restarts: map.get("greeter_max_restarts").and_then(|v| v.parse().ok()).unwrap_or(5),
+        greeter_fallback_after: map.get("greeter_fallback_after").and_then(|v| v.parse().ok()).unwrap_or(3),
+        greeter_user: map.get("greeter_user").cloned().unwrap_or_else(|| "hyprdm".into()),
+        daemon_socket: map.get("daemon_socket").cloned().unwrap_or_else(|| "/run/hyprdm/greeter.sock".into()),
+        greeter_compositor: map.get("greeter_compositor").cloned().unwrap_or_else(|| "builtin".into()),
+        greeter_hyprland: map.get("greeter_hyprland").cloned().unwrap_or_else(|| "Hyprland".into()),
+        greeter_kb_layout: map.get("greeter_kb_layout").cloned().unwrap_or_else(|| "us".into()),
+        greeter_kb_variant: map.get("greeter_kb_variant").cloned(),
+        greeter_kb_options: map.get("greeter_kb_options").cloned(),
+        greeter_monitors: map.get("greeter_monitors").cloned().unwrap_or_else(|| "last_user".into()),
         autologin: map.get("autologin").map(|v| v == "true").unwrap_or(true),
         two_factor_enabled: map.get("two_factor_enabled").map(|v| v == "true").unwrap_or(false),
         two_factor_method: map.get("two_factor_method").cloned(),
         two_factor_secret: map.get("two_factor_secret").cloned(),
         systemctl_usedefine: map.get("systemctl_usedefine").map(|v| v == "true").unwrap_or(false),
+        session_log_path: map.get("session_log_path").cloned(),
+        session_log_max_size: map.get("session_log_max_size").and_then(|v| v.parse().ok()).unwrap_or(1024 * 1024),
+        session_log_journald: map.get("session_log_journald").map(|v| v == "true").unwrap_or(false),
+        crash_policy: map.get("crash_policy").cloned().unwrap_or_else(|| "return_to_greeter".into()),
+        crash_window_secs: map.get("crash_window_secs").and_then(|v| v.parse().ok()).unwrap_or(10),
+        crash_max_retries: map.get("crash_max_retries").and_then(|v| v.parse().ok()).unwrap_or(3),
+        session_stop_grace_secs: map.get("session_stop_grace_secs").and_then(|v| v.parse().ok()).unwrap_or(5),
+        session_launch_mode: map.get("session_launch_mode").cloned().unwrap_or_else(|| "direct".into()),
+        uwsm_wrap: map.get("uwsm_wrap").cloned().unwrap_or_else(|| "false".into()),
+        session_wrapper: map.get("session_wrapper").cloned(),
+        greeter_setup_script: map.get("greeter_setup_script").cloned(),
+        session_setup_script: map.get("session_setup_script").cloned(),
+        session_cleanup_script: map.get("session_cleanup_script").cloned(),
+        hook_timeout_secs: map.get("hook_timeout_secs").and_then(|v| v.parse().ok()).unwrap_or(30),
+        workspace_snapshot: map.get("workspace_snapshot").map(|v| v == "true").unwrap_or(false),
+        session_allow: map.iter()
+            .filter_map(|(k, v)| k.strip_prefix("session_allow.").map(|g| (g.to_string(), split_list(v))))
+            .collect(),
+        session_hide: map.get("session_hide").map(|v| split_list(v)).unwrap_or_default(),
+        session_aliases: map.iter()
+            .filter_map(|(k, v)| k.strip_prefix("session_alias.").map(|id| (id.to_string(), v.clone())))
+            .collect(),
     };
 
     Ok(config.validate())
 }
 
+/// Quickshell arayüzlerinin arandığı dizinler, öncelik sırasıyla
+pub fn quickshell_dirs() -> Vec<PathBuf> {
+    vec![
+        dirs::config_dir().map(|d| d.join("hyprdm/quickshell")), // ~/.config/hyprdm/quickshell
+        dirs::data_local_dir().map(|d| d.join("quickshell")),     // ~/.local/share/quickshell
+        Some(PathBuf::from("/usr/share/hyprdm/quickshell")),      // /usr/share/hyprdm/quickshell
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Root yerleşik greeter'ı buraya yazar; greeter kullanıcısı okuyabilir ama yazamaz
+const BUILTIN_GREETER_DIR: &str = "/run/hyprdm/builtin-greeter";
+
+/// Yerleşik greeter arayüzünü çalışma dizinine yazar ve main.qml yolunu döner
+pub fn builtin_quickshell_ui() -> Result<PathBuf, String> {
+    let dir = if unsafe { libc::geteuid() } == 0 {
+        PathBuf::from(BUILTIN_GREETER_DIR)
+    } else {
+        dirs::runtime_dir()
+            .ok_or("XDG_RUNTIME_DIR is not set, cannot write the built-in greeter")?
+            .join("hyprdm/builtin-greeter")
+    };
+    write_builtin_ui(&dir)
+}
+
+/// main.qml'i `dir` içine yazar; dizin bizim olmalı ve hiçbir bağlantı izlenmez
+fn write_builtin_ui(dir: &Path) -> Result<PathBuf, String> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o755)
+        .create(dir)
+        .map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+
+    // Başka birinin dizini veya bir symlink ise yazma
+    let euid = unsafe { libc::geteuid() };
+    for d in [Some(dir), dir.parent()].into_iter().flatten() {
+        let meta = fs::symlink_metadata(d).map_err(|e| format!("Failed to inspect {:?}: {}", d, e))?;
+        if !meta.is_dir() || meta.uid() != euid || meta.mode() & 0o022 != 0 {
+            return Err(format!("Refusing to write the built-in greeter into {:?}", d));
+        }
+    }
+
+    let path = dir.join("main.qml");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o644)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&path)
+        .map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    file.set_permissions(fs::Permissions::from_mode(0o644))
+        .and_then(|_| file.write_all(BUILTIN_GREETER_QML.as_bytes()))
+        .map_err(|e| format!("Failed to write built-in greeter: {}", e))?;
+    Ok(path)
+}
+
+const BUILTIN_GREETER_QML: &str = include_str!("builtin_greeter.qml");
+
+/// Virgülle ayrılmış değer listesini böler
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
 pub fn save_config(path: &Path, config: &HDMConfig) -> Result<(), String> {
     let mut lines = vec![];
     lines.push(format!("theme={}", config.theme));
     lines.push(format!("tiling={}", config.tiling));
     lines.push(format!("default_session={}", config.default_session));
+    if !config.default_session_fallbacks.is_empty() {
+        lines.push(format!("default_session_fallbacks={}", config.default_session_fallbacks.join(",")));
+    }
+    lines.push(format!("greeter_interface={}", config.greeter_interface));
+    if !config.greeter_interface_fallbacks.is_empty() {
+        lines.push(format!("greeter_interface_fallbacks={}", config.greeter_interface_fallbacks.join(",")));
+    }
+    lines.push(format!("greeter_wayland_display={}", config.greeter_wayland_display));
+    if let Some(log_path) = &config.greeter_log_path {
+        lines.push(format!("greeter_log_path={}", log_path));
+    }
+    lines.push(format!("greeter_max_restarts={}", config.greeter_max_restarts));
+    lines.push(format!("greeter_fallback_after={}", config.greeter_fallback_after));
+    lines.push(format!("greeter_user={}", config.greeter_user));
+    lines.push(format!("daemon_socket={}", config.daemon_socket));
+    lines.push(format!("greeter_compositor={}", config.greeter_compositor));
+    lines.push(format!("greeter_hyprland={}", config.greeter_hyprland));
+    lines.push(format!("greeter_kb_layout={}", config.greeter_kb_layout));
+    if let Some(variant) = &config.greeter_kb_variant {
+        lines.push(format!("greeter_kb_variant={}", variant));
+    }
+    if let Some(options) = &config.greeter_kb_options {
+        lines.push(format!("greeter_kb_options={}", options));
+    }
+    lines.push(format!("greeter_monitors={}", config.greeter_monitors));
     lines.push(format!("autologin={}", config.autologin));
     lines.push(format!("two_factor_enabled={}", config.two_factor_enabled));
     if let Some(method) = &config.two_factor_method {
@@ -116,6 +418,44 @@ pub fn save_config(path: &Path, config: &HDMConfig) -> Result<(), String> {
         lines.push("# systemctl_usedefine=true".to_string());
     }
 
+    if let Some(log_path) = &config.session_log_path {
+        lines.push(format!("session_log_path={}", log_path));
+    }
+    lines.push(format!("session_log_max_size={}", config.session_log_max_size));
+    lines.push(format!("session_log_journald={}", config.session_log_journald));
+    lines.push(format!("crash_policy={}", config.crash_policy));
+    lines.push(format!("crash_window_secs={}", config.crash_window_secs));
+    lines.push(format!("crash_max_retries={}", config.crash_max_retries));
+    lines.push(format!("session_stop_grace_secs={}", config.session_stop_grace_secs));
+    lines.push(format!("session_launch_mode={}", config.session_launch_mode));
+    lines.push(format!("uwsm_wrap={}", config.uwsm_wrap));
+    for (key, value) in [
+        ("session_wrapper", &config.session_wrapper),
+        ("greeter_setup_script", &config.greeter_setup_script),
+        ("session_setup_script", &config.session_setup_script),
+        ("session_cleanup_script", &config.session_cleanup_script),
+    ] {
+        if let Some(script) = value {
+            lines.push(format!("{}={}", key, script));
+        }
+    }
+    lines.push(format!("hook_timeout_secs={}", config.hook_timeout_secs));
+    lines.push(format!("workspace_snapshot={}", config.workspace_snapshot));
+
+    let mut groups: Vec<_> = config.session_allow.iter().collect();
+    groups.sort();
+    for (group, sessions) in groups {
+        lines.push(format!("session_allow.{}={}", group, sessions.join(",")));
+    }
+    if !config.session_hide.is_empty() {
+        lines.push(format!("session_hide={}", config.session_hide.join(",")));
+    }
+    let mut aliases: Vec<_> = config.session_aliases.iter().collect();
+    aliases.sort();
+    for (id, alias) in aliases {
+        lines.push(format!("session_alias.{}={}", id, alias));
+    }
+
     fs::write(path, lines.join("\n")).map_err(|e| format!("Failed to save config: {}", e))?;
 
     Ok(())
@@ -125,17 +465,80 @@ pub fn load_or_create_config(path: &Path) -> Result<HDMConfig, String> {
     if path.exists() {
         load_config(path)
     } else {
-        let default = HDMConfig {
-            theme: "Adwaita-dark".into(),
-            tiling: true,
-            default_session: "Hyprland".into(),
-            autologin: true,
-            two_factor_enabled: false,
-            two_factor_method: None,
-            two_factor_secret: None,
-            systemctl_usedefine: false,
-        };
+        let default = HDMConfig::default();
         save_config(path, &default)?;
         Ok(default)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn builtin_ui_is_written_world_readable() {
+        let dir = TempDir::new("builtin");
+        let path = write_builtin_ui(&dir.join("builtin-greeter")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), BUILTIN_GREETER_QML);
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o644);
+        assert_eq!(fs::metadata(path.parent().unwrap()).unwrap().mode() & 0o777, 0o755);
+
+        // Rewriting fixes the mode of an existing file
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        write_builtin_ui(&dir.join("builtin-greeter")).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o644);
+    }
+
+    #[test]
+    fn builtin_ui_never_follows_links() {
+        let dir = TempDir::new("builtin-links");
+        let target = dir.join("target");
+        fs::write(&target, "keep").unwrap();
+
+        let ui = dir.join("ui");
+        fs::create_dir(&ui).unwrap();
+        std::os::unix::fs::symlink(&target, ui.join("main.qml")).unwrap();
+        assert!(write_builtin_ui(&ui).is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "keep");
+
+        let elsewhere = dir.join("elsewhere");
+        fs::create_dir(&elsewhere).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, dir.join("linked")).unwrap();
+        assert!(write_builtin_ui(&dir.join("linked")).is_err());
+        assert!(!elsewhere.join("main.qml").exists());
+    }
+
+    #[test]
+    fn builtin_ui_refuses_a_writable_directory() {
+        let dir = TempDir::new("builtin-writable");
+        let ui = dir.join("ui");
+        fs::create_dir(&ui).unwrap();
+        fs::set_permissions(&ui, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(write_builtin_ui(&ui).is_err());
+    }
+
+    #[test]
+    fn workspace_snapshot_is_opt_in() {
+        assert!(!HDMConfig::default().workspace_snapshot);
+
+        let dir = TempDir::new("snapshot-default");
+        dir.write("hyprdm.conf", "greeter_user=greeter\n");
+        assert!(!load_config(&dir.join("hyprdm.conf")).unwrap().workspace_snapshot);
+
+        dir.write("hyprdm.conf", "workspace_snapshot=true\n");
+        assert!(load_config(&dir.join("hyprdm.conf")).unwrap().workspace_snapshot);
+    }
+
+    #[test]
+    fn workspace_snapshot_survives_a_save() {
+        let dir = TempDir::new("snapshot-save");
+        let path = dir.join("hyprdm.conf");
+        let config = HDMConfig { workspace_snapshot: true, ..Default::default() };
+        save_config(&path, &config).unwrap();
+        assert!(load_config(&path).unwrap().workspace_snapshot);
+
+        save_config(&path, &HDMConfig::default()).unwrap();
+        assert!(!load_config(&path).unwrap().workspace_snapshot);
+    }
+}
@@ -5,6 +5,8 @@ use std::env;
 use std::time::UNIX_EPOCH;
 use config::parser::load_or_create_config;
 
+mod interface_cmd;
+
 pub struct ConfigManager;
 
 impl ConfigManager {
@@ -87,11 +89,20 @@ impl ConfigManager {
             println!("2FA secret: {}", secret);
         }
         println!("Systemctl usedefine: {}", config.systemctl_usedefine);
+        if config.session_log_journald {
+            println!("Session log: journald");
+        } else if let Some(log_path) = &config.session_log_path {
+            println!("Session log: {} (max {} bytes)", log_path, config.session_log_max_size);
+        }
 
+        println!("Greeter interface: {}", config.greeter_interface);
         if let Some(ui_path) = config.find_quickshell_ui() {
             println!("Selected QuickShell UI main.qml: {:?}", ui_path);
         } else {
-            eprintln!("Could not find main.qml for session '{}'", config.default_session);
+            eprintln!(
+                "Could not find main.qml for interface '{}', the built-in greeter will be used",
+                config.greeter_interface
+            );
         }
 
         let service_path = "/etc/systemd/system/hdm.service";
@@ -164,9 +175,21 @@ fn print_help() {
 Usage:
     config_manager [OPTIONS]
 
+    config_manager interface <COMMAND>
+
 Options:
     --reload        Reload configuration if it has changed and restart HyprDM.
     --help          Show this help message.
+
+Interface commands:
+    install <archive|dir> [--system] [--activate]
+                    --activate (with --system) also makes it the configured greeter_interface.
+                    --activate also makes it the configured greeter_interface.
+    remove <name> [--system]
+                    Remove an installed interface.
+    list            List installed interfaces and their status.
+    preview-info <name>
+                    Show manifest details and the preview image of an interface.
 "#
     );
 }
@@ -179,6 +202,14 @@ fn main() {
         return;
     }
 
+    if args.get(1).map(|a| a.as_str()) == Some("interface") {
+        if let Err(e) = interface_cmd::run(&args[2..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let reload = args.iter().any(|a| a == "--reload");
     ConfigManager::run(reload);
 }
@@ -0,0 +1,426 @@
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use config::interface::{discover_interfaces, inspect_interface, user_interface_root, InstalledInterface, SYSTEM_INTERFACE_ROOT};
+use config::parser::{load_config, load_or_create_config, save_config};
+
+const CONFIG_PATH: &str = "/etc/hyprdm/hyprdm.conf";
+
+/// Flags each interface command accepts
+fn allowed_flags(command: &str) -> &'static [&'static str] {
+    match command {
+        "install" => &["--system", "--activate"],
+        "remove" => &["--system"],
+        _ => &[],
+    }
+}
+
+/// Positional arguments and flags, refusing flags the command does not know
+fn parse_args(args: &[String]) -> Result<(Vec<&String>, bool, bool), String> {
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let command = positional.first().map(|s| s.as_str()).unwrap_or("");
+    let allowed = allowed_flags(command);
+    if let Some(flag) = args.iter().find(|a| a.starts_with("--") && !allowed.contains(&a.as_str())) {
+        return Err(format!("Unknown option {} for interface {}", flag, command));
+    }
+
+    let system = args.iter().any(|a| a == "--system");
+    let activate = args.iter().any(|a| a == "--activate");
+    // The greeter only reads the system config and can't see a user's own interfaces
+    if activate && !system {
+        return Err("--activate needs --system, the greeter does not see per-user interfaces".into());
+    }
+    Ok((positional, system, activate))
+}
+
+/// `configmanager interface <install|remove|list|preview-info> ...`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (positional, system, activate) = parse_args(args)?;
+
+    match positional.first().map(|s| s.as_str()) {
+        Some("install") => {
+            let source = positional.get(1).ok_or("Usage: interface install <archive|dir>")?;
+            let name = install(Path::new(source.as_str()), &install_root(system)?)?;
+            if activate {
+                set_active(&name)?;
+            }
+            Ok(())
+        }
+        Some("remove") => {
+            let name = positional.get(1).ok_or("Usage: interface remove <name>")?;
+            remove(name, &install_root(system)?)
+        }
+        Some("list") => {
+            list();
+            Ok(())
+        }
+        Some("preview-info") => {
+            let name = positional.get(1).ok_or("Usage: interface preview-info <name>")?;
+            preview_info(name)
+        }
+        _ => Err("Unknown interface command, see --help".into()),
+    }
+}
+
+fn install_root(system: bool) -> Result<PathBuf, String> {
+    if system {
+        Ok(PathBuf::from(SYSTEM_INTERFACE_ROOT))
+    } else {
+        user_interface_root().ok_or_else(|| "Could not determine the user config directory".into())
+    }
+}
+
+/// Install from a directory or a tar archive, returns the interface name
+fn install(source: &Path, root: &Path) -> Result<String, String> {
+    if source.is_dir() {
+        return install_dir(source, root);
+    }
+    if !source.is_file() {
+        return Err(format!("{:?} does not exist", source));
+    }
+
+    check_archive(source)?;
+
+    let staging = staging_dir(&std::env::temp_dir())?;
+
+    let result = Command::new("tar")
+        .arg("-xf")
+        .arg(source)
+        .arg("-C")
+        .arg(&staging)
+        .arg("--no-same-owner")
+        .arg("--no-same-permissions")
+        .status()
+        .map_err(|e| format!("Failed to run tar: {}", e))
+        .and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("tar exited with {}", status))
+            }
+        })
+        .and_then(|_| find_interface_dir(&staging))
+        .and_then(|dir| install_dir(&dir, root));
+
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Fresh private directory under `base`, like mkdtemp: never reuses an existing path
+fn staging_dir(base: &Path) -> Result<PathBuf, String> {
+    for attempt in 0..100u32 {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let dir = base.join(format!("hyprdm-interface-{}-{:08x}{:02}", std::process::id(), nanos, attempt));
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to create {:?}: {}", dir, e)),
+        }
+    }
+    Err(format!("Failed to create a staging directory in {:?}", base))
+}
+
+/// Reject archives with absolute paths, `..` components or links
+fn check_archive(archive: &Path) -> Result<(), String> {
+    let output = Command::new("tar")
+        .arg("-tvf")
+        .arg(archive)
+        .output()
+        .map_err(|e| format!("Failed to run tar: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to read archive: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let names = Command::new("tar")
+        .arg("-tf")
+        .arg(archive)
+        .output()
+        .map_err(|e| format!("Failed to run tar: {}", e))?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if line.starts_with('l') || line.starts_with('h') {
+            return Err(format!("Archive contains a link, refusing: {}", line));
+        }
+    }
+    for entry in String::from_utf8_lossy(&names.stdout).lines() {
+        if !is_safe_relative(Path::new(entry)) {
+            return Err(format!("Archive entry escapes the target directory: {}", entry));
+        }
+    }
+    Ok(())
+}
+
+fn is_safe_relative(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// An interface name becomes a directory right under the install root, so it has to be
+/// one plain component: no "." or "..", no hidden names, nothing empty
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    chars.next().is_some_and(allowed) && chars.all(|c| allowed(c) || c == '.')
+}
+
+/// The unpacked interface is either the archive root or its single top-level directory
+fn find_interface_dir(staging: &Path) -> Result<PathBuf, String> {
+    if staging.join("main.qml").is_file() {
+        return Ok(staging.to_path_buf());
+    }
+    let dirs: Vec<PathBuf> = fs::read_dir(staging)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    match dirs.as_slice() {
+        [dir] if dir.join("main.qml").is_file() => Ok(dir.clone()),
+        _ => Err("Archive does not contain an interface (main.qml not found)".into()),
+    }
+}
+
+fn install_dir(source: &Path, root: &Path) -> Result<String, String> {
+    let interface = inspect_interface(source);
+    let manifest = match (&interface.manifest, &interface.status) {
+        (Some(m), status) if status.is_usable() => m.clone(),
+        (None, status) if status.is_usable() => {
+            return Err("Interface has no manifest.json, refusing to install".into());
+        }
+        (_, status) => return Err(format!("Invalid interface: {:?}", status)),
+    };
+
+    let name = manifest.name.trim().to_string();
+    if !is_valid_name(&name) {
+        return Err(format!("Invalid interface name: {}", name));
+    }
+
+    let target = root.join(&name);
+    if target.exists() {
+        fs::remove_dir_all(&target).map_err(|e| format!("Failed to replace {:?}: {}", target, e))?;
+    }
+    copy_dir(source, &target)?;
+
+    println!("Installed interface {} {} to {:?}", name, manifest.version, target);
+    Ok(name)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create {:?}: {}", to, e))?;
+    for entry in fs::read_dir(from).map_err(|e| e.to_string())?.flatten() {
+        let file_type = entry.file_type().map_err(|e| e.to_string())?;
+        let target = to.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), &target)
+                .map_err(|e| format!("Failed to copy {:?}: {}", entry.path(), e))?;
+        }
+    }
+    Ok(())
+}
+
+fn remove(name: &str, root: &Path) -> Result<(), String> {
+    if !is_valid_name(name) {
+        return Err(format!("Invalid interface name: {}", name));
+    }
+    let target = root.join(name);
+    if !target.is_dir() {
+        return Err(format!("Interface {} is not installed in {:?}", name, root));
+    }
+    fs::remove_dir_all(&target).map_err(|e| format!("Failed to remove {:?}: {}", target, e))?;
+    println!("Removed interface {} from {:?}", name, root);
+
+    if let Ok(config) = load_config(Path::new(CONFIG_PATH)) {
+        if config.greeter_interface == name {
+            eprintln!("Warning: {} was the active greeter interface", name);
+        }
+    }
+    Ok(())
+}
+
+fn list() {
+    let interfaces = discover_interfaces();
+    if interfaces.is_empty() {
+        println!("No interfaces installed.");
+        return;
+    }
+    for interface in interfaces {
+        let (version, author) = match &interface.manifest {
+            Some(m) => (m.version.clone(), m.author.clone().unwrap_or_default()),
+            None => ("-".to_string(), String::new()),
+        };
+        println!(
+            "{}\t{}\t{}\t{:?}\t{}",
+            interface.id,
+            version,
+            author,
+            interface.status,
+            interface.dir.display()
+        );
+    }
+}
+
+fn find(name: &str) -> Result<InstalledInterface, String> {
+    discover_interfaces()
+        .into_iter()
+        .find(|i| i.id == name)
+        .ok_or_else(|| format!("Interface {} is not installed", name))
+}
+
+fn preview_info(name: &str) -> Result<(), String> {
+    let interface = find(name)?;
+    println!("Interface: {}", interface.id);
+    println!("Path: {}", interface.dir.display());
+    println!("Status: {:?}", interface.status);
+    if let Some(m) = &interface.manifest {
+        println!("Name: {}", m.name);
+        println!("Version: {}", m.version);
+        if let Some(author) = &m.author {
+            println!("Author: {}", author);
+        }
+        println!("API version: {}", m.api_version);
+        if !m.features.is_empty() {
+            println!("Features: {}", m.features.join(", "));
+        }
+    }
+    match (interface.preview_path(), interface.manifest.as_ref().and_then(|m| m.preview.as_ref())) {
+        (Some(p), _) => println!("Preview: {}", p.display()),
+        (None, Some(p)) => println!("Preview: {} (missing or outside the interface)", p),
+        (None, None) => println!("Preview: none"),
+    }
+    Ok(())
+}
+
+fn set_active(name: &str) -> Result<(), String> {
+    let path = Path::new(CONFIG_PATH);
+    let mut config = load_or_create_config(path)?;
+    config.greeter_interface = name.to_string();
+    save_config(path, &config)?;
+    println!("Greeter interface set to {}", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = staging_dir(&std::env::temp_dir()).unwrap().join(name);
+            fs::create_dir(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    const MANIFEST: &str = r#"{"name": "Neon", "version": "0.3"}"#;
+
+    #[test]
+    fn unknown_flags_and_user_activation_are_refused() {
+        assert!(parse_args(&args(&["install", "x.tar", "--system", "--activate"])).is_ok());
+        assert!(parse_args(&args(&["remove", "Neon", "--system"])).is_ok());
+
+        let err = parse_args(&args(&["install", "x.tar", "--sytem"])).unwrap_err();
+        assert!(err.contains("--sytem"), "{}", err);
+        assert!(parse_args(&args(&["remove", "Neon", "--activate"])).is_err());
+        assert!(parse_args(&args(&["list", "--all"])).is_err());
+        assert!(parse_args(&args(&["install", "x.tar", "--activate"])).unwrap_err().contains("--system"));
+    }
+
+    #[test]
+    fn staging_dirs_are_private_and_never_reused() {
+        let base = TempDir::new("staging");
+        let first = staging_dir(&base.0).unwrap();
+        let second = staging_dir(&base.0).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o700);
+    }
+
+    #[test]
+    fn install_from_an_archive_and_remove() {
+        let src = TempDir::new("archive-src");
+        src.write("Neon/main.qml", "import Quickshell\n");
+        src.write("Neon/manifest.json", MANIFEST);
+        let archive = src.0.join("neon.tar");
+        let status = Command::new("tar").arg("-cf").arg(&archive).arg("-C").arg(&src.0).arg("Neon").status().unwrap();
+        assert!(status.success());
+
+        let root = TempDir::new("archive-root");
+        assert_eq!(install(&archive, &root.0).unwrap(), "Neon");
+        assert!(root.0.join("Neon/main.qml").is_file());
+
+        assert!(remove("../Neon", &root.0).is_err());
+        remove("Neon", &root.0).unwrap();
+        assert!(!root.0.join("Neon").exists());
+    }
+
+    #[test]
+    fn archives_with_links_or_escaping_paths_are_refused() {
+        let src = TempDir::new("archive-bad");
+        src.write("Bad/main.qml", "");
+        std::os::unix::fs::symlink("/etc/passwd", src.0.join("Bad/passwd")).unwrap();
+        let linked = src.0.join("linked.tar");
+        Command::new("tar").arg("-cf").arg(&linked).arg("-C").arg(&src.0).arg("Bad").status().unwrap();
+        assert!(check_archive(&linked).unwrap_err().contains("link"));
+
+        let escaping = src.0.join("escaping.tar");
+        Command::new("tar").arg("-cf").arg(&escaping).arg("-C").arg(src.0.join("Bad"))
+            .arg("--transform=s,^,../,").arg("main.qml").status().unwrap();
+        assert!(check_archive(&escaping).unwrap_err().contains("escapes"));
+    }
+
+    #[test]
+    fn interfaces_without_a_manifest_or_with_a_bad_name_are_not_installed() {
+        let src = TempDir::new("dir-src");
+        src.write("Plain/main.qml", "");
+        src.write("Sneaky/main.qml", "");
+        src.write("Sneaky/manifest.json", r#"{"name": "../../etc", "version": "1"}"#);
+        let root = TempDir::new("dir-root");
+
+        assert!(install(&src.0.join("Plain"), &root.0).unwrap_err().contains("manifest"));
+        assert!(install(&src.0.join("Sneaky"), &root.0).unwrap_err().contains("Invalid interface name"));
+        assert_eq!(fs::read_dir(&root.0).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn the_install_root_itself_is_never_a_target() {
+        let src = TempDir::new("dot-src");
+        src.write("Dot/main.qml", "");
+        src.write("Dot/manifest.json", r#"{"name": ".", "version": "1"}"#);
+        let root = TempDir::new("dot-root");
+        root.write("Neon/main.qml", "");
+
+        assert!(install(&src.0.join("Dot"), &root.0).unwrap_err().contains("Invalid interface name"));
+        for name in [".", "", "..", ".hidden", "a/b"] {
+            assert!(remove(name, &root.0).unwrap_err().contains("Invalid interface name"), "{:?}", name);
+        }
+        assert!(root.0.join("Neon/main.qml").is_file());
+        assert!(is_valid_name("Neon-2.1_beta"));
+    }
+}
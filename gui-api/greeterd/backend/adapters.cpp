@@ -3,7 +3,9 @@
 
 // =============== CompositorAdapter ===============
 CompositorAdapter::CompositorAdapter(Compositor* raw, QObject* parent)
-    : QObject(parent), m_comp(raw ? CompositorPtr(raw) : CompositorPtr(compositor_new())) {}
+    : QObject(parent), m_comp(raw ? CompositorPtr(raw) : CompositorPtr(compositor_new())) {
+    if (m_comp) m_stop.reset(compositor_stop_handle(m_comp.get()));
+}
 
 bool CompositorAdapter::runWithIpc(QObject* ipcAdapterObj) {
     if (!m_comp) return false;
@@ -14,7 +16,7 @@ bool CompositorAdapter::runWithIpc(QObject* ipcAdapterObj) {
 }
 
 void CompositorAdapter::stop() {
-    if (m_comp) compositor_stop(m_comp.get());
+    if (m_stop) stop_handle_stop(m_stop.get());
 }
 
 // =============== IpcAdapter ===============
@@ -66,8 +68,7 @@ QVariantMap LayoutAdapter::panelRect(uint screenW, uint screenH) {
 
 // =============== SessionAdapter ===============
 SessionAdapter::SessionAdapter(Session* raw, QObject* parent)
-    : QObject(parent), m_session(raw ? SessionPtr(raw) : SessionPtr(session_new("DefaultSession", "/usr/share/wayland-sessions"))),
-      m_baseExecDir("/usr/share/wayland-sessions") {}
+    : QObject(parent), m_session(raw) {}
 
 bool SessionAdapter::restart() {
     if (!m_session) return false;
@@ -84,18 +85,10 @@ bool SessionAdapter::stop() {
     return session_stop(m_session.get()) != 0;
 }
 
-bool SessionAdapter::switchAndStart(const QString& sessionName, const QString& customExecPath) {
+bool SessionAdapter::switchAndStart(const QString& desktopId) {
     if (!m_session) return false;
-    QString execPath = customExecPath;
-    if (execPath.isEmpty()) {
-        execPath = m_baseExecDir.endsWith("/") ? (m_baseExecDir + sessionName)
-                                               : (m_baseExecDir + "/" + sessionName);
-    }
-    int okSwitch = session_switch(m_session.get(),
-                                  sessionName.toUtf8().constData(),
-                                  execPath.toUtf8().constData());
-    if (okSwitch == 0) return false;
-    return session_start(m_session.get()) != 0;
+    // What runs comes from the desktop entry, session_switch also starts it
+    return session_switch(m_session.get(), desktopId.toUtf8().constData()) == 1;
 }
 
 // =============== ThemeAdapter ===============
@@ -131,6 +124,13 @@ UserAdapter::UserAdapter(User* raw, const QString& username, int twofactorMethod
       m_username(username),
       m_twofactorMethod(twofactorMethod)
 {
+    // Greeter hyprdm kullanıcısı olarak çalışıyorsa PAM root daemon'da
+    if (qEnvironmentVariableIsSet("HYPRDM_SOCKET")) {
+        m_daemon.reset(daemon_client_connect(nullptr));
+        if (!m_daemon)
+            qWarning() << "HYPRDM_SOCKET is set but the daemon is not reachable";
+    }
+
     // Eğer raw gelmediyse, yeni User oluştur
     if (!m_user) {
         if (!m_username.isEmpty()) {
@@ -152,12 +152,31 @@ UserAdapter::UserAdapter(User* raw, const QString& username, int twofactorMethod
 }
 
 bool UserAdapter::authenticate(const QString& password) {
+    if (m_daemon) {
+        int r = daemon_client_authenticate(m_daemon.get(),
+                                           m_username.toUtf8().constData(),
+                                           password.toUtf8().constData());
+        m_needsTwoFactor = (r == 2);
+        return r != 0;
+    }
     if (!m_user) return false;
     return user_authenticate(m_user.get(), password.toUtf8().constData()) != 0;
 }
 
 bool UserAdapter::verifyTwoFactor(const QString& code) { 
+    if (m_daemon) {
+        if (!m_needsTwoFactor) return true;
+        bool ok = daemon_client_verify_2fa(m_daemon.get(), code.toUtf8().constData()) != 0;
+        if (ok) m_needsTwoFactor = false;
+        return ok;
+    }
     if (!m_user) return m_twofactorMethod == 0;
     if (m_twofactorMethod == 0) return true;
     return user_verify_2fa(m_user.get(), code.toUtf8().constData()) != 0;
 }
+
+bool UserAdapter::startSession(const QString& sessionName, bool restoreLayout) {
+    if (!m_daemon) return false;
+    return daemon_client_start_session(m_daemon.get(), sessionName.toUtf8().constData(),
+                                       restoreLayout ? 1 : 0) >= 0;
+}
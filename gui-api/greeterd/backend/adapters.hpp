@@ -12,18 +12,23 @@
 extern "C" {
     // Types
     struct Compositor;
+    struct StopHandle;
     struct HyprlandIPC;
     struct LayoutManager;
     struct Session;
     struct ThemeManager;
     struct UnidataGenerator;
     struct User;
+    struct DaemonClient;
 
     // Compositor
     Compositor* compositor_new();
     int         compositor_run_with_ipc(Compositor*, HyprlandIPC*); // c_int
     void        compositor_stop(Compositor*);
     void        compositor_free(Compositor*);
+    StopHandle* compositor_stop_handle(Compositor*);
+    void        stop_handle_stop(StopHandle*);               // thread-safe
+    void        stop_handle_free(StopHandle*);
 
     // IPC
     HyprlandIPC* ipc_new();
@@ -40,11 +45,11 @@ extern "C" {
     int            layout_manager_get_panel_rect(LayoutManager*, PanelRect* out, unsigned screen_w, unsigned screen_h);
 
     // Session
-    Session* session_new(const char* name, const char* exec_dir);
+    Session* session_new(const char* user, const char* desktop_id);
     int      session_start(Session*);
     int      session_stop(Session*);
     int      session_restart(Session*);
-    int      session_switch(Session*, const char* new_name, const char* new_exec);
+    int      session_switch(Session*, const char* desktop_id);
     void     session_free(Session*);
 
     // ThemeManager
@@ -66,6 +71,14 @@ extern "C" {
     int   user_verify_2fa(User*, const char* code);       // 0/1
     void  user_free(User*);
 
+    // DaemonClient (greeter running as the unprivileged hyprdm user)
+    DaemonClient* daemon_client_connect(const char* path);     // NULL = $HYPRDM_SOCKET
+    int   daemon_client_authenticate(DaemonClient*, const char* username, const char* password); // 0 fail, 1 ok, 2 needs 2FA
+    int   daemon_client_verify_2fa(DaemonClient*, const char* code);
+    int   daemon_client_start_session(DaemonClient*, const char* session, int restore_layout); // id or -1
+    int   daemon_client_cancel(DaemonClient*);
+    void  daemon_client_free(DaemonClient*);
+
     // misc
     void  string_free(char*); // CString::into_raw ile dönen char* için
 }
@@ -77,12 +90,14 @@ struct FfiDeleter {
 };
 
 using CompositorPtr   = std::unique_ptr<Compositor,   FfiDeleter<Compositor,   compositor_free>>;
+using StopHandlePtr   = std::unique_ptr<StopHandle,   FfiDeleter<StopHandle,   stop_handle_free>>;
 using IpcPtr          = std::unique_ptr<HyprlandIPC,  FfiDeleter<HyprlandIPC,  ipc_free>>;
 using LayoutMgrPtr    = std::unique_ptr<LayoutManager,FfiDeleter<LayoutManager,layout_manager_free>>;
 using SessionPtr      = std::unique_ptr<Session,      FfiDeleter<Session,      session_free>>;
 using ThemeMgrPtr     = std::unique_ptr<ThemeManager, FfiDeleter<ThemeManager, theme_manager_free>>;
 using UnidataPtr      = std::unique_ptr<UnidataGenerator, FfiDeleter<UnidataGenerator, unidata_free>>;
 using UserPtr         = std::unique_ptr<User,         FfiDeleter<User,         user_free>>;
+using DaemonClientPtr = std::unique_ptr<DaemonClient, FfiDeleter<DaemonClient, daemon_client_free>>;
 
 // ===================== ADAPTERS (QObjects) =====================
 
@@ -91,10 +106,11 @@ class CompositorAdapter : public QObject {
 public:
     explicit CompositorAdapter(Compositor* raw = nullptr, QObject* parent=nullptr);
     Q_INVOKABLE bool runWithIpc(QObject* ipcAdapter); // alıcı: IpcAdapter*
-    Q_INVOKABLE void stop();
+    Q_INVOKABLE void stop();        // may be called while runWithIpc blocks another thread
 
 private:
     CompositorPtr m_comp;
+    StopHandlePtr m_stop;
 };
 
 class IpcAdapter : public QObject {
@@ -130,24 +146,16 @@ private:
 
 class SessionAdapter : public QObject {
     Q_OBJECT
-    Q_PROPERTY(QString baseExecDir READ baseExecDir WRITE setBaseExecDir NOTIFY baseExecDirChanged)
 public:
     explicit SessionAdapter(Session* raw=nullptr, QObject* parent=nullptr);
 
-    QString baseExecDir() const { return m_baseExecDir; }
-    void setBaseExecDir(const QString& d) { if (m_baseExecDir==d) return; m_baseExecDir=d; emit baseExecDirChanged(); }
-
     Q_INVOKABLE bool restart();
     Q_INVOKABLE bool start();
     Q_INVOKABLE bool stop();
-    Q_INVOKABLE bool switchAndStart(const QString& sessionName, const QString& customExecPath = QString());
-
-signals:
-    void baseExecDirChanged();
+    Q_INVOKABLE bool switchAndStart(const QString& desktopId);
 
 private:
     SessionPtr m_session;
-    QString    m_baseExecDir; // /usr/share/wayland-sessions
 };
 
 class ThemeAdapter : public QObject {
@@ -187,9 +195,13 @@ public:
 
     Q_INVOKABLE bool authenticate(const QString& password);
     Q_INVOKABLE bool verifyTwoFactor(const QString& code);
+    // Only through the daemon; returns false when running without one
+    Q_INVOKABLE bool startSession(const QString& sessionName, bool restoreLayout = false);
 
 private:
     UserPtr  m_user;
+    DaemonClientPtr m_daemon;       // set when HYPRDM_SOCKET is present
+    bool     m_needsTwoFactor = false;
     QString  m_username;
     int      m_twofactorMethod = 0;
 };
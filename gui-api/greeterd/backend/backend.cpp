@@ -59,8 +59,11 @@ int main(int argc, char *argv[])
     HyprlandIPC*   ipc            = ipc_new();
     LayoutManager* layoutManager  = layout_manager_new(panelName.c_str());
     ThemeManager*  themeManager   = theme_manager_new();
-    Session*       session        = session_new("DefaultSession", "/usr/share/wayland-sessions");
     User*          userManager    = user_new("", "system-login", 0 /*TwoFactorMethod::None*/, nullptr);
+    // The session policy is checked for the user logging in
+    const char*    userName       = user_get_username(userManager);
+    Session*       session        = userName ? session_new(userName, "Hyprland") : nullptr;
+    string_free((char*)userName);
     if (layoutManager) {
         const char* name = layout_manager_get_panel_name(layoutManager);
         if (name) {
@@ -6,9 +6,15 @@ extern "C" {
 
 // Forward declaration
 typedef struct Compositor Compositor;
+typedef struct StopHandle StopHandle;
 typedef struct HyprlandIPC HyprlandIPC;
+typedef struct EventSubscriber EventSubscriber;
 typedef struct LayoutManager LayoutManager;
 typedef struct Session Session;
+typedef struct SessionSupervisor SessionSupervisor;
+typedef struct SessionRegistry SessionRegistry;
+typedef struct GreeterLauncher GreeterLauncher;
+typedef struct DaemonClient DaemonClient;
 typedef struct ThemeManager ThemeManager;
 typedef struct UnidataGenerator UnidataGenerator;
 typedef struct User User;
@@ -23,15 +29,36 @@ enum Layout {
 // -------------------- Compositor --------------------
 Compositor* compositor_new();
 int compositor_run_with_ipc(Compositor* c, HyprlandIPC* ipc);
-void compositor_stop(Compositor* c);
+void compositor_stop(Compositor* c);                      // compositor thread only
+StopHandle* compositor_stop_handle(Compositor* c);        // take before running
+void stop_handle_stop(StopHandle* h);                     // any thread
+void stop_handle_free(StopHandle* h);
+void compositor_handle_signals(Compositor* c, int enable); // stop on SIGINT/SIGTERM
+int compositor_import_monitors(Compositor* c, const char* path); // NULL = greeter_monitors from the config; rules read or -1
+int compositor_output_count(Compositor* c);               // one per enabled monitor rule, -1 on NULL
 void compositor_free(Compositor* c);
 
 // -------------------- IPC --------------------
 HyprlandIPC* ipc_new();
+HyprlandIPC* ipc_new_with_socket(const char* path);
+HyprlandIPC* ipc_new_for_instance(const char* signature); // NULL if not running
 int ipc_send_command(HyprlandIPC* ipc, const char* cmd);
+int ipc_dispatch(HyprlandIPC* ipc, const char* dispatcher, const char* args); // args may be NULL
+int ipc_keyword(HyprlandIPC* ipc, const char* key, const char* value);
 char* ipc_get_status(HyprlandIPC* ipc);
 void ipc_string_free(char* s);
 void ipc_free(HyprlandIPC* ipc);
+struct MonitorInfo { int x, y; unsigned int width, height; float scale; unsigned int transform; int focused; };
+int ipc_get_monitors(HyprlandIPC* ipc, struct MonitorInfo* out, unsigned int max); // monitor count or -1
+
+// Hyprland .socket2.sock events, reconnects on its own
+typedef void (*HyprlandEventCallback)(const char* name, const char* data, void* user_data);
+EventSubscriber* ipc_events_new(const char* path);          // NULL = current instance
+int ipc_events_start(EventSubscriber* sub, HyprlandEventCallback cb, void* user_data);
+void ipc_events_stop(EventSubscriber* sub);
+void ipc_events_free(EventSubscriber* sub);
+// Same events, delivered on the compositor thread while compositor_run_with_ipc runs with an IPC
+int compositor_on_hyprland_event(Compositor* c, HyprlandEventCallback cb, void* user_data);
 
 // -------------------- LayoutManager --------------------
 LayoutManager* layout_manager_new(const char* panel_name);
@@ -45,13 +72,65 @@ const char* layout_manager_get_panel_name(const LayoutManager* lm);
 void string_free(char* s);
 
 // -------------------- Session --------------------
-Session* session_new(const char* name, const char* exec);
+// Session desktop_id for the user logging in; NULL when the policy does not allow it
+Session* session_new(const char* user, const char* desktop_id);
 int session_start(Session* s);
 int session_stop(Session* s);
+struct SessionStopCounts { unsigned int terminated, killed, survivors; };
+int session_stop_graceful(Session* s, unsigned int grace_ms, struct SessionStopCounts* out);
 int session_restart(Session* s);
-int session_switch(Session* s, const char* new_name, const char* new_exec);
+int session_switch(Session* s, const char* desktop_id); // same user, starts it
+void session_set_vt(Session* s, unsigned int vt); // 0 = none
+Session* session_find_for_user(const char* user, const char* name); // NULL if hidden for user
+int session_available_count(const char* user);
+char* session_available_name_at(const char* user, unsigned int index); // free with string_free
 void session_free(Session* s);
 
+// -------------------- VT --------------------
+int vt_find_free();
+int vt_switch_to(unsigned int vt);
+
+// -------------------- SessionSupervisor --------------------
+// kind: 0 exited, 1 exited with code, 2 killed by signal, 3 crashed,
+//       4 restarted, 5 fell back to default, 6 return to greeter
+typedef void (*SessionEventCallback)(int kind, int value, void* user_data);
+
+// Takes ownership of the session. policy: 0 greeter, 1 retry, 2 default session
+SessionSupervisor* session_supervisor_new(Session* s, int policy, unsigned int crash_window_secs, unsigned int max_retries);
+int session_supervisor_start(SessionSupervisor* sv, SessionEventCallback cb, void* user_data);
+int session_supervisor_stop(SessionSupervisor* sv);
+void session_supervisor_free(SessionSupervisor* sv);
+
+// -------------------- GreeterLauncher --------------------
+// kind: 0 started (value = pid), 1 ready, 2 crashed (value = code or signal),
+//       3 fell back to built-in, 4 gave up, 5 exited, 6 stopped
+typedef void (*GreeterEventCallback)(int kind, int value, void* user_data);
+
+GreeterLauncher* greeter_launcher_new(unsigned int vt);    // vt 0 = none
+int greeter_launcher_start(GreeterLauncher* gl, GreeterEventCallback cb, void* user_data);
+void greeter_launcher_stop(GreeterLauncher* gl);
+void greeter_launcher_free(GreeterLauncher* gl);
+
+// -------------------- DaemonClient --------------------
+// Used by the unprivileged greeter to reach the root daemon
+DaemonClient* daemon_client_connect(const char* path);   // NULL = $HYPRDM_SOCKET
+int daemon_client_authenticate(DaemonClient* c, const char* username, const char* password); // 0 fail, 1 ok, 2 needs 2FA
+int daemon_client_verify_2fa(DaemonClient* c, const char* code);
+int daemon_client_start_session(DaemonClient* c, const char* session, int restore_layout); // session id or -1
+int daemon_client_cancel(DaemonClient* c);
+void daemon_client_free(DaemonClient* c);
+
+// -------------------- SessionRegistry --------------------
+SessionRegistry* session_registry_new();
+int session_registry_start(SessionRegistry* reg, const User* u, const char* password, Session* s); // takes ownership of s, returns id or -1
+int session_registry_open_greeter(SessionRegistry* reg);    // greeter VT or -1
+int session_registry_switch_to(SessionRegistry* reg, const User* u, const char* password); // session id or -1
+int session_registry_stop(SessionRegistry* reg, unsigned int id);
+int session_registry_count(SessionRegistry* reg);
+char* session_registry_user_at(const SessionRegistry* reg, unsigned int index); // free with string_free
+int session_registry_vt_at(const SessionRegistry* reg, unsigned int index);
+void session_registry_free(SessionRegistry* reg);
+
 // -------------------- ThemeManager --------------------
 ThemeManager* theme_manager_new();
 int theme_manager_set_theme(ThemeManager* tm, const char* name);
@@ -66,6 +145,7 @@ void unidata_free(UnidataGenerator* ud);
 
 // -------------------- User --------------------
 User* user_new(const char* username, const char* pam_service, int method, const char* secret);
+const char* user_get_username(const User* u); // free with string_free
 int user_authenticate(User* u, const char* password);
 int user_verify_2fa(User* u, const char* code);
 void user_free(User* u);
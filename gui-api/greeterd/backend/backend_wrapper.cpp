@@ -7,8 +7,11 @@ GreeterBackend::GreeterBackend(QObject* parent)
     ipc           = ipc_new();
     layoutManager = layout_manager_new("DefaultPanel");
     themeManager  = theme_manager_new();
-    session       = session_new("DefaultSession", "/usr/bin/wayland-session");
     userManager   = user_new("user", "login_service", 2, nullptr); // 2 = TwoFactorMethod::None
+    // The session policy is checked for the user logging in
+    const char* userName = user_get_username(userManager);
+    session       = userName ? session_new(userName, "Hyprland") : nullptr;
+    string_free((char*)userName);
 }
 
 GreeterBackend::~GreeterBackend()
@@ -1,68 +1,175 @@
+use std::fs;
+use std::os::unix::io::IntoRawFd;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::time::Duration;
 
-use smithay::reexports::wayland_server::DispatchData;
+use smithay::reexports::wayland_server::protocol::wl_output::WlOutput;
+use smithay::reexports::wayland_server::{Display, DispatchData, Global};
 use smithay::wayland::seat::Seat;
 use smithay::wayland::shell::xdg::{xdg_shell_init, XdgRequest};
-use smithay::wayland::output::{Output, PhysicalProperties};
-use smithay::utils::Size;
-use smithay::reexports::wayland_server::protocol::wl_output::Subpixel;
+use smithay::wayland::output::{Mode, Output, PhysicalProperties};
+use smithay::utils::{Point, Size};
+use smithay::reexports::wayland_server::protocol::wl_output::{Subpixel, Transform};
 
-use crate::ipc::HyprlandIPC;
+use crate::event_loop::{EventLoop, StopHandle};
+use crate::ipc::{EventSubscriber, HyprlandEvent, HyprlandIPC, EVENT_SOCKET};
+use crate::monitor_config::{output_layout, MonitorRule};
 use slog;
 
 pub struct Compositor {
-    pub display: smithay::reexports::wayland_server::Display,
+    pub display: Display,
     pub seat: Seat,
-    pub output: Output,
-    running: bool,
+    /// One per enabled monitor rule, a single default output until rules are applied
+    pub outputs: Vec<Output>,
+    output_globals: Vec<Global<WlOutput>>,
+    pub event_loop: EventLoop,
+    event_hooks: Vec<Box<dyn FnMut(&HyprlandEvent)>>,
+}
+
+fn create_output(display: &mut Display, name: &str, size: (i32, i32)) -> (Output, Global<WlOutput>) {
+    let physical_properties = PhysicalProperties {
+        size: Size::from(size),
+        subpixel: Subpixel::Unknown,
+        make: "HDM".into(),
+        model: "Output".into(),
+    };
+    Output::new(display, name.into(), physical_properties, None::<slog::Logger>)
 }
 
 impl Compositor {
     pub fn new() -> Result<Self, String> {
-        let mut display = smithay::reexports::wayland_server::Display::new();
+        let mut display = Display::new();
 
         let logger: Option<slog::Logger> = None;
 
-        let physical_properties = PhysicalProperties {
-            size: Size::from((1920, 1080)),
-            subpixel: Subpixel::Unknown,
-            make: "HDM".into(),
-            model: "Output".into(),
-        };
-
         let (seat, _seat_global) = Seat::new(&mut display, "HDM Seat".into(), logger.clone());
-        let (output, _output_global) =
-            Output::new(&mut display, "HDM Output".into(), physical_properties, logger.clone());
+        let (output, output_global) = create_output(&mut display, "HDM Output", (1920, 1080));
 
         xdg_shell_init(&mut display, |_req: XdgRequest, _dispatch: DispatchData| {}, logger);
 
         Ok(Self {
             display,
             seat,
-            output,
-            running: false,
+            outputs: vec![output],
+            output_globals: vec![output_global],
+            event_loop: EventLoop::new()?,
+            event_hooks: Vec::new(),
         })
     }
 
+    /// Serve clients on the socket at `path`, e.g. `wayland-hyprdm` in the greeter's runtime
+    /// dir, handed to `owner` (uid, gid) so the greeter can connect
+    pub fn listen_on(&mut self, path: &Path, owner: Option<(u32, u32)>) -> Result<(), String> {
+        // A stale socket from the last greeter; a link is removed, not followed
+        if fs::symlink_metadata(path).is_ok() {
+            fs::remove_file(path).map_err(|e| format!("Failed to remove {:?}: {}", path, e))?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| format!("Failed to bind {:?}: {}", path, e))?;
+        if let Some((uid, gid)) = owner {
+            std::os::unix::fs::lchown(path, Some(uid), Some(gid))
+                .map_err(|e| format!("Failed to chown {:?}: {}", path, e))?;
+        }
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure {:?}: {}", path, e))?;
+
+        // libwayland takes over the fd and accepts on it from the loop
+        unsafe { self.display.add_socket_fd(listener.into_raw_fd()) }
+            .map_err(|e| format!("Failed to serve {:?}: {}", path, e))
+    }
+
+    /// Stops `run_with_ipc` from any thread
+    pub fn stop_handle(&self) -> StopHandle {
+        self.event_loop.stop_handle()
+    }
+
+    /// Called on the loop thread for every event of the Hyprland given to `run_with_ipc`
+    pub fn on_hyprland_event<F: FnMut(&HyprlandEvent) + 'static>(&mut self, hook: F) {
+        self.event_hooks.push(Box::new(hook));
+    }
+
+    /// Serve clients until stopped. With `ipc` the hooks get that instance's events,
+    /// read on a background thread and handed to the loop
     pub fn run_with_ipc(&mut self, ipc: Option<Arc<HyprlandIPC>>) -> Result<(), String> {
-        self.running = true;
-        while self.running {
-            self.display.dispatch(Duration::from_millis(16), &mut ()).unwrap();
-            self.display.flush_clients(&mut ());
-
-            if let Some(ipc_ref) = &ipc {
-                if let Ok(active) = ipc_ref.get_status() {
-                    println!("Active window: {}", active);
+        let (tx, rx) = mpsc::channel();
+        let mut subscriber = match &ipc {
+            Some(ipc) if !self.event_hooks.is_empty() => {
+                let socket = ipc.socket_path()?.with_file_name(EVENT_SOCKET);
+                let mut subscriber = EventSubscriber::with_socket(&socket);
+                let waker = self.event_loop.waker();
+                subscriber.on_event(move |event| {
+                    if tx.send(event.clone()).is_ok() {
+                        waker.wake();
+                    }
+                });
+                // Events arrive through the callback, the subscriber's own channel is unused
+                drop(subscriber.spawn()?);
+                Some(subscriber)
+            }
+            _ => None,
+        };
+
+        let display_fd = self.display.get_poll_fd();
+        let display = &mut self.display;
+        let hooks = &mut self.event_hooks;
+        let result = self.event_loop.run(&[display_fd], |ready| {
+            if ready[0] {
+                display
+                    .dispatch(Duration::ZERO, &mut ())
+                    .map_err(|e| format!("Failed to dispatch Wayland clients: {}", e))?;
+            }
+            for event in rx.try_iter() {
+                for hook in hooks.iter_mut() {
+                    hook(&event);
                 }
             }
+            display.flush_clients(&mut ());
+            Ok(())
+        });
+
+        if let Some(subscriber) = subscriber.as_mut() {
+            subscriber.stop();
+        }
+        result
+    }
+
+    /// Replace the outputs with one per enabled rule, laid out like Hyprland would, e.g.
+    /// the monitors of the last user. Without enabled rules the outputs stay as they are
+    pub fn apply_monitor_rules(&mut self, rules: &[MonitorRule]) {
+        let layout = output_layout(rules);
+        if layout.is_empty() {
+            return;
+        }
+        // Clients see the old outputs go away and the new ones appear
+        for global in self.output_globals.drain(..) {
+            global.destroy();
+        }
+        self.outputs.clear();
 
-            std::thread::sleep(Duration::from_millis(16));
+        for out in layout {
+            let (output, global) = create_output(&mut self.display, &out.name, (out.width as i32, out.height as i32));
+            let mode = Mode {
+                size: Size::from((out.width as i32, out.height as i32)),
+                refresh: out.refresh,
+            };
+            output.add_mode(mode);
+            output.set_preferred(mode);
+            output.change_current_state(
+                Some(mode),
+                Transform::from_raw(out.transform as u32),
+                Some(out.scale),
+                Some(Point::from((out.x, out.y))),
+            );
+            self.outputs.push(output);
+            self.output_globals.push(global);
         }
-        Ok(())
     }
 
+    /// Same as `stop_handle().stop()`
     pub fn stop(&mut self) {
-        self.running = false;
+        self.event_loop.stop_handle().stop();
     }
 }
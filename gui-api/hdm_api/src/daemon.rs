@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use config::HDMConfig;
+use crate::session_policy;
+use crate::session_registry::SessionRegistry;
+use crate::user_manager::{PamHandle, TwoFactorMethod, User};
+use crate::workspace_snapshot::{self, LayoutRecorder};
+
+/// How long a new session's Hyprland may take before its layout is given up on
+const HYPRLAND_STARTUP: Duration = Duration::from_secs(60);
+
+/// Who logged in last, the greeter borrows their monitor layout
+const LAST_USER_PATH: &str = "/var/lib/hyprdm/last_user";
+
+/// One file per user with the next HOTP counter
+const HOTP_COUNTER_DIR: &str = "/var/lib/hyprdm/hotp";
+
+/// One request per line, JSON encoded, from the greeter to the daemon
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    /// Check the password; on success the login stays pending until StartSession or Cancel
+    Authenticate { username: String, password: String },
+    /// Second factor for the pending login
+    VerifyTwoFactor { code: String },
+    /// Sessions the user may pick, default first
+    ListSessions { username: String },
+    /// Start a session for the pending login, optionally replaying the saved workspace layout
+    StartSession {
+        session: String,
+        #[serde(default)]
+        restore_layout: bool,
+    },
+    /// Forget the pending login
+    Cancel,
+}
+
+/// One response line for every request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    /// Password accepted, a code is needed before the session can start
+    TwoFactorRequired,
+    Sessions { sessions: Vec<String> },
+    Started { id: u32 },
+    Error { message: String },
+}
+
+impl Response {
+    fn error(message: impl Into<String>) -> Self {
+        Response::Error { message: message.into() }
+    }
+}
+
+/// Reported to the caller of `Daemon::run`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DaemonEvent {
+    /// A greeter connected, with its uid
+    GreeterConnected { uid: u32 },
+    /// A connection from another uid was refused
+    PeerRejected { uid: u32 },
+    SessionStarted { id: u32, user: String },
+}
+
+/// Uid of the process on the other end of a Unix socket (SO_PEERCRED)
+pub fn peer_uid(stream: &UnixStream) -> Result<u32, String> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(format!("SO_PEERCRED failed: {}", std::io::Error::last_os_error()));
+    }
+    Ok(cred.uid)
+}
+
+/// Uid and primary gid of a system account
+pub fn lookup_user(name: &str) -> Result<(u32, u32), String> {
+    let c_name = CString::new(name).map_err(|_| format!("Invalid user name: {}", name))?;
+    unsafe {
+        let pw = libc::getpwnam(c_name.as_ptr());
+        if pw.is_null() {
+            return Err(format!("User {} does not exist", name));
+        }
+        Ok(((*pw).pw_uid, (*pw).pw_gid))
+    }
+}
+
+/// The user of the most recently started session
+pub fn last_user() -> Option<String> {
+    let user = fs::read_to_string(LAST_USER_PATH).ok()?;
+    let user = user.trim();
+    if user.is_empty() { None } else { Some(user.to_string()) }
+}
+
+fn record_last_user(user: &str) -> Result<(), String> {
+    let path = Path::new(LAST_USER_PATH);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    }
+    fs::write(path, format!("{}\n", user)).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// HOTP counters kept across logins, so a used code is never accepted again
+#[derive(Debug, Clone)]
+pub struct HotpCounters {
+    pub dir: PathBuf,
+}
+
+impl Default for HotpCounters {
+    fn default() -> Self {
+        Self { dir: PathBuf::from(HOTP_COUNTER_DIR) }
+    }
+}
+
+impl HotpCounters {
+    fn path(&self, user: &str) -> Result<PathBuf, String> {
+        if user.is_empty() || user.starts_with('.') || user.contains('/') {
+            return Err(format!("Invalid user name: {}", user));
+        }
+        Ok(self.dir.join(user))
+    }
+
+    /// Next counter for `user`, 0 before their first code
+    pub fn load(&self, user: &str) -> Result<u64, String> {
+        let path = self.path(user)?;
+        match fs::read_to_string(&path) {
+            Ok(text) => text
+                .trim()
+                .parse()
+                .map_err(|_| format!("Corrupt HOTP counter in {:?}", path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(format!("Failed to read {:?}: {}", path, e)),
+        }
+    }
+
+    /// Replace the counter through a private temporary file, never following links
+    pub fn store(&self, user: &str, counter: u64) -> Result<(), String> {
+        let path = self.path(user)?;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)
+            .map_err(|e| format!("Failed to create {:?}: {}", self.dir, e))?;
+
+        let tmp = self.dir.join(format!(".{}.tmp", user));
+        let _ = fs::remove_file(&tmp);
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&tmp)
+            .map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+        writeln!(file, "{}", counter)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, &path).map_err(|e| format!("Failed to replace {:?}: {}", path, e))
+    }
+}
+
+struct PendingLogin {
+    user: User,
+    /// Authenticated context, the session's worker opens the PAM session on it
+    pam: PamHandle,
+    needs_two_factor: bool,
+}
+
+/// Root side of the greeter: owns PAM, the session registry and the config
+pub struct Daemon {
+    pub socket_path: PathBuf,
+    /// The only uid allowed to connect
+    pub greeter_uid: u32,
+    greeter_gid: u32,
+    config: HDMConfig,
+    registry: SessionRegistry,
+    hotp: HotpCounters,
+    pending: Option<PendingLogin>,
+    /// Layout recorder per user; each one ends by itself with the user's Hyprland
+    recorders: HashMap<String, LayoutRecorder>,
+}
+
+impl Daemon {
+    /// Look up `greeter_user`; the daemon refuses to run without it
+    pub fn from_config(config: HDMConfig) -> Result<Self, String> {
+        let (greeter_uid, greeter_gid) = lookup_user(&config.greeter_user)?;
+        Ok(Self {
+            socket_path: PathBuf::from(&config.daemon_socket),
+            greeter_uid,
+            greeter_gid,
+            config,
+            registry: SessionRegistry::new(),
+            hotp: HotpCounters::default(),
+            pending: None,
+            recorders: HashMap::new(),
+        })
+    }
+
+    pub fn registry(&mut self) -> &mut SessionRegistry {
+        &mut self.registry
+    }
+
+    /// Create the socket, owned by the greeter user with mode 0600
+    pub fn bind(&self) -> Result<UnixListener, String> {
+        if let Some(parent) = self.socket_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+        }
+        if self.socket_path.exists() {
+            let _ = fs::remove_file(&self.socket_path);
+        }
+
+        let listener = UnixListener::bind(&self.socket_path)
+            .map_err(|e| format!("Failed to bind {:?}: {}", self.socket_path, e))?;
+        fs::set_permissions(&self.socket_path, fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to chmod {:?}: {}", self.socket_path, e))?;
+        chown(&self.socket_path, self.greeter_uid, self.greeter_gid)?;
+        Ok(listener)
+    }
+
+    /// Serve greeter connections until a session has been started
+    pub fn run<F: FnMut(&DaemonEvent)>(&mut self, listener: &UnixListener, mut on_event: F) -> Result<u32, String> {
+        loop {
+            let (stream, _) = listener
+                .accept()
+                .map_err(|e| format!("Failed to accept on {:?}: {}", self.socket_path, e))?;
+
+            let uid = match peer_uid(&stream) {
+                Ok(uid) => uid,
+                Err(e) => {
+                    eprintln!("[HyprDM] {}", e);
+                    continue;
+                }
+            };
+            if uid != self.greeter_uid {
+                eprintln!("[HyprDM] Refusing connection from uid {}", uid);
+                on_event(&DaemonEvent::PeerRejected { uid });
+                continue;
+            }
+            on_event(&DaemonEvent::GreeterConnected { uid });
+
+            if let Some(id) = self.serve(stream, &mut on_event) {
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Handle one connection; returns the session id once one was started
+    fn serve<F: FnMut(&DaemonEvent)>(&mut self, stream: UnixStream, on_event: &mut F) -> Option<u32> {
+        let mut writer = match stream.try_clone() {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("[HyprDM] {}", e);
+                return None;
+            }
+        };
+        let reader = BufReader::new(stream);
+
+        for line in reader.lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => self.handle(request),
+                Err(e) => Response::error(format!("Invalid request: {}", e)),
+            };
+
+            if write_message(&mut writer, &response).is_err() {
+                break;
+            }
+            if let Response::Started { id } = response {
+                let user = self.registry.get(id).map(|s| s.user.clone()).unwrap_or_default();
+                on_event(&DaemonEvent::SessionStarted { id, user });
+                return Some(id);
+            }
+        }
+
+        // The greeter went away, a half finished login must not survive it
+        self.pending = None;
+        None
+    }
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::Ping => Response::Ok,
+            Request::Authenticate { username, password } => self.authenticate(&username, password),
+            Request::VerifyTwoFactor { code } => {
+                let pending = match self.pending.as_mut() {
+                    Some(p) => p,
+                    None => return Response::error("No login in progress"),
+                };
+                if !pending.needs_two_factor {
+                    return Response::Ok;
+                }
+                if !pending.user.verify_2fa(&code, Path::new("/etc/hyprdm/hyprdm.conf")) {
+                    // One guess per password, otherwise the code could be brute-forced
+                    self.pending = None;
+                    return Response::error("Invalid code, log in again");
+                }
+                // verify_2fa moved the counter past the code; a login that can't save that
+                // would leave the code valid for the next attempt
+                if let TwoFactorMethod::HOTP { counter } = pending.user.twofactor_method {
+                    if let Err(e) = self.hotp.store(&pending.user.username, counter) {
+                        eprintln!("[HyprDM] {}", e);
+                        self.pending = None;
+                        return Response::error("Two-factor state could not be saved");
+                    }
+                }
+                pending.needs_two_factor = false;
+                Response::Ok
+            }
+            Request::ListSessions { username } => Response::Sessions {
+                sessions: session_policy::sessions_for_user(&self.config, &username)
+                    .into_iter()
+                    .map(|s| s.name)
+                    .collect(),
+            },
+            Request::StartSession { session, restore_layout } => self.start_session(&session, restore_layout),
+            Request::Cancel => {
+                self.pending = None;
+                Response::Ok
+            }
+        }
+    }
+
+    fn authenticate(&mut self, username: &str, password: String) -> Response {
+        self.pending = None;
+        if username.is_empty() {
+            return Response::error("No user name given");
+        }
+
+        let (method, secret) = if self.config.two_factor_enabled {
+            let method = match self.config.two_factor_method.as_deref() {
+                Some("hotp") => match self.hotp.load(username) {
+                    Ok(counter) => TwoFactorMethod::HOTP { counter },
+                    Err(e) => {
+                        eprintln!("[HyprDM] {}", e);
+                        return Response::error("Authentication failed");
+                    }
+                },
+                _ => TwoFactorMethod::TOTP,
+            };
+            (method, self.config.two_factor_secret.clone())
+        } else {
+            (TwoFactorMethod::None, None)
+        };
+        let needs_two_factor = !matches!(method, TwoFactorMethod::None);
+        let user = User::new(username, "system-login", method, secret);
+
+        // The one authentication of this login; its context carries on into the session
+        let pam = match user.open_pam_handle(&password) {
+            Ok(pam) => pam,
+            Err(e) => {
+                eprintln!("[HyprDM] {}", e);
+                return Response::error("Authentication failed");
+            }
+        };
+
+        self.pending = Some(PendingLogin { user, pam, needs_two_factor });
+        if needs_two_factor {
+            Response::TwoFactorRequired
+        } else {
+            Response::Ok
+        }
+    }
+
+    fn start_session(&mut self, name: &str, restore_layout: bool) -> Response {
+        match self.pending.as_ref() {
+            None => return Response::error("No login in progress"),
+            Some(p) if p.needs_two_factor => return Response::error("Two-factor code required"),
+            Some(_) => {}
+        }
+        let pending = match self.pending.take() {
+            Some(p) => p,
+            None => return Response::error("No login in progress"),
+        };
+        let username = pending.user.username.clone();
+
+        let session = match session_policy::find_session_for_user(&self.config, &username, name) {
+            Ok(s) => s,
+            Err(e) => return Response::error(e),
+        };
+        let home = session_policy::user_home(&username).unwrap_or_else(|| PathBuf::from("/"));
+        let session = session.with_config(&self.config, &home);
+
+        let id = match self.registry.start(pending.pam, session) {
+            Ok(id) => id,
+            Err(e) => return Response::error(e),
+        };
+        if let Err(e) = record_last_user(&username) {
+            eprintln!("[HyprDM] {}", e);
+        }
+
+        if self.config.workspace_snapshot {
+            match LayoutRecorder::spawn(&username, HYPRLAND_STARTUP) {
+                Ok(recorder) => {
+                    self.recorders.insert(username.clone(), recorder);
+                }
+                Err(e) => eprintln!("[HyprDM] {}", e),
+            }
+        }
+        // The greeter asks, but only a config with snapshots turned on restores anything
+        if self.config.workspace_snapshot && restore_layout {
+            thread::spawn(move || match workspace_snapshot::restore_for_user(&username, HYPRLAND_STARTUP) {
+                Ok(report) => {
+                    for (command, error) in report.errors() {
+                        eprintln!("[HyprDM] Layout restore: {:?}: {}", command, error);
+                    }
+                }
+                Err(e) => eprintln!("[HyprDM] Layout restore failed: {}", e),
+            });
+        }
+        Response::Started { id }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+fn chown(path: &Path, uid: u32, gid: u32) -> Result<(), String> {
+    let c_path = CString::new(path.as_os_str().to_string_lossy().as_bytes())
+        .map_err(|_| format!("Invalid path {:?}", path))?;
+    if unsafe { libc::chown(c_path.as_ptr(), uid, gid) } != 0 {
+        return Err(format!("Failed to chown {:?}: {}", path, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+fn write_message<T: Serialize>(stream: &mut UnixStream, message: &T) -> Result<(), String> {
+    let mut line = serde_json::to_string(message).map_err(|e| e.to_string())?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// Greeter side of the socket
+pub struct DaemonClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl DaemonClient {
+    pub fn connect(path: &Path) -> Result<Self, String> {
+        let stream = UnixStream::connect(path)
+            .map_err(|e| format!("Failed to connect to {:?}: {}", path, e))?;
+        // PAM may take a while (faillock delays), but a dead daemon must not hang the UI
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(60)));
+        let writer = stream.try_clone().map_err(|e| e.to_string())?;
+        Ok(Self { reader: BufReader::new(stream), writer })
+    }
+
+    pub fn request(&mut self, request: &Request) -> Result<Response, String> {
+        write_message(&mut self.writer, request)?;
+        let mut line = String::new();
+        let n = self.reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read from daemon: {}", e))?;
+        if n == 0 {
+            return Err("Daemon closed the connection".into());
+        }
+        serde_json::from_str(&line).map_err(|e| format!("Invalid response from daemon: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn daemon() -> Daemon {
+        Daemon {
+            socket_path: PathBuf::from("/nonexistent/hyprdm-test.sock"),
+            greeter_uid: 0,
+            greeter_gid: 0,
+            config: HDMConfig::default(),
+            registry: SessionRegistry::new(),
+            hotp: HotpCounters { dir: PathBuf::from("/nonexistent/hotp") },
+            pending: None,
+            recorders: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn hotp_counter_starts_at_zero_and_persists() {
+        let dir = TempDir::new("hotp");
+        let counters = HotpCounters { dir: dir.join("hotp") };
+        assert_eq!(counters.load("alice"), Ok(0));
+
+        counters.store("alice", 7).unwrap();
+        assert_eq!(counters.load("alice"), Ok(7));
+        counters.store("alice", 8).unwrap();
+        assert_eq!(counters.load("alice"), Ok(8));
+        assert_eq!(counters.load("bob"), Ok(0));
+    }
+
+    #[test]
+    fn hotp_counter_refuses_path_like_names() {
+        let dir = TempDir::new("hotp-names");
+        let counters = HotpCounters { dir: dir.path().to_path_buf() };
+        for name in ["", "../etc/passwd", ".hidden", "a/b"] {
+            assert!(counters.store(name, 1).is_err(), "{:?} accepted", name);
+        }
+    }
+
+    #[test]
+    fn hotp_store_does_not_write_through_a_planted_link() {
+        let dir = TempDir::new("hotp-link");
+        let target = dir.write("target", "untouched");
+        let counters = HotpCounters { dir: dir.join("hotp") };
+        fs::create_dir_all(&counters.dir).unwrap();
+        std::os::unix::fs::symlink(&target, counters.dir.join(".alice.tmp")).unwrap();
+
+        counters.store("alice", 3).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+        assert_eq!(counters.load("alice"), Ok(3));
+    }
+
+    #[test]
+    fn requests_without_a_pending_login_are_refused() {
+        let mut daemon = daemon();
+        assert_eq!(daemon.handle(Request::Ping), Response::Ok);
+        assert_eq!(
+            daemon.handle(Request::VerifyTwoFactor { code: "123456".into() }),
+            Response::error("No login in progress")
+        );
+        assert_eq!(
+            daemon.handle(Request::StartSession { session: "hyprland".into(), restore_layout: false }),
+            Response::error("No login in progress")
+        );
+        assert_eq!(daemon.handle(Request::Cancel), Response::Ok);
+        assert_eq!(
+            daemon.handle(Request::Authenticate { username: String::new(), password: "x".into() }),
+            Response::error("No user name given")
+        );
+    }
+
+    #[test]
+    fn a_wrong_code_ends_the_login() {
+        let mut daemon = daemon();
+        daemon.pending = Some(PendingLogin {
+            user: User::new("alice", "hyprdm", TwoFactorMethod::TOTP, None),
+            pam: PamHandle::for_greeter("alice").unwrap(),
+            needs_two_factor: true,
+        });
+
+        assert_eq!(
+            daemon.handle(Request::VerifyTwoFactor { code: "000000".into() }),
+            Response::error("Invalid code, log in again")
+        );
+        assert!(daemon.pending.is_none());
+        assert_eq!(
+            daemon.handle(Request::VerifyTwoFactor { code: "000000".into() }),
+            Response::error("No login in progress")
+        );
+    }
+
+    #[test]
+    fn requests_use_tagged_json() {
+        let request: Request = serde_json::from_str(r#"{"type":"start_session","session":"hyprland"}"#).unwrap();
+        assert_eq!(request, Request::StartSession { session: "hyprland".into(), restore_layout: false });
+        assert_eq!(
+            serde_json::to_string(&Response::Started { id: 2 }).unwrap(),
+            r#"{"type":"started","id":2}"#
+        );
+    }
+
+    #[test]
+    fn serve_answers_every_line_and_reports_bad_json() {
+        let mut daemon = daemon();
+        let (server, client) = UnixStream::pair().unwrap();
+        let mut writer = client.try_clone().unwrap();
+        writer.write_all(b"{\"type\":\"ping\"}\n\nnot json\n").unwrap();
+        writer.shutdown(std::net::Shutdown::Write).unwrap();
+
+        assert_eq!(daemon.serve(server, &mut |_| {}), None);
+        let responses: Vec<Response> = BufReader::new(client)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0], Response::Ok);
+        assert!(matches!(&responses[1], Response::Error { message } if message.starts_with("Invalid request")));
+    }
+
+    #[test]
+    fn client_and_daemon_talk_over_the_socket() {
+        let dir = TempDir::new("daemon-socket");
+        let path = dir.join("daemon.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut daemon = daemon();
+            daemon.serve(stream, &mut |_| {})
+        });
+
+        let mut client = DaemonClient::connect(&path).unwrap();
+        assert_eq!(client.request(&Request::Ping), Ok(Response::Ok));
+        assert_eq!(client.request(&Request::Cancel), Ok(Response::Ok));
+        drop(client);
+        assert_eq!(server.join().unwrap(), None);
+    }
+}
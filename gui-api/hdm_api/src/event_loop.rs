@@ -0,0 +1,397 @@
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Loops that can handle signals at the same time
+const SIGNAL_SLOTS: usize = 8;
+
+/// Write ends of the loops a signal handler wakes, -1 for a free slot
+static SIGNAL_WAKE_FDS: [AtomicI32; SIGNAL_SLOTS] = [const { AtomicI32::new(-1) }; SIGNAL_SLOTS];
+/// SIGINT/SIGTERM received so far; a loop stops once this moves past what it saw at start
+static SIGNAL_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Loops with handlers installed and the handlers from before the first of them
+static SIGNAL_USERS: Mutex<(usize, Vec<(libc::c_int, libc::sigaction)>)> = Mutex::new((0, Vec::new()));
+
+/// Self-pipe: writing a byte makes the loop's `poll` return
+struct WakePipe {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl WakePipe {
+    fn new() -> Result<Self, String> {
+        let mut fds = [0 as RawFd; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+            return Err(format!("Failed to create wake pipe: {}", std::io::Error::last_os_error()));
+        }
+        Ok(Self { read: fds[0], write: fds[1] })
+    }
+
+    fn wake(&self) {
+        // A full pipe already wakes the loop, so EAGAIN is fine
+        unsafe { libc::write(self.write, [1u8].as_ptr() as *const libc::c_void, 1) };
+    }
+
+    fn drain(&self) {
+        let mut buf = [0u8; 64];
+        while unsafe { libc::read(self.read, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+    }
+}
+
+impl Drop for WakePipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// Wakes the loop from any thread, e.g. after queueing work for it
+#[derive(Clone)]
+pub struct Waker {
+    pipe: Arc<WakePipe>,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        self.pipe.wake();
+    }
+}
+
+/// Stops a running loop from any thread
+#[derive(Clone)]
+pub struct StopHandle {
+    stopped: Arc<AtomicBool>,
+    pipe: Arc<WakePipe>,
+}
+
+impl StopHandle {
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.pipe.wake();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+struct Timer {
+    every: Duration,
+    next: Instant,
+    callback: Box<dyn FnMut()>,
+}
+
+/// Sleeps in `poll` until a watched fd is readable, a timer is due, a signal arrives
+/// or another thread wakes or stops it
+pub struct EventLoop {
+    pipe: Arc<WakePipe>,
+    stopped: Arc<AtomicBool>,
+    timers: Vec<Timer>,
+    /// Stop on SIGINT and SIGTERM while running
+    pub handle_signals: bool,
+}
+
+impl EventLoop {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self {
+            pipe: Arc::new(WakePipe::new()?),
+            stopped: Arc::new(AtomicBool::new(false)),
+            timers: Vec::new(),
+            handle_signals: false,
+        })
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle { stopped: self.stopped.clone(), pipe: self.pipe.clone() }
+    }
+
+    pub fn waker(&self) -> Waker {
+        Waker { pipe: self.pipe.clone() }
+    }
+
+    /// Run `callback` on the loop thread every `every`
+    pub fn add_timer<F: FnMut() + 'static>(&mut self, every: Duration, callback: F) {
+        self.timers.push(Timer { every, next: Instant::now() + every, callback: Box::new(callback) });
+    }
+
+    /// Poll `fds` until stopped; `tick` runs after every wakeup with the readable state
+    /// of each fd. A stop requested before `run` still applies.
+    pub fn run<F>(&mut self, fds: &[RawFd], mut tick: F) -> Result<(), String>
+    where
+        F: FnMut(&[bool]) -> Result<(), String>,
+    {
+        let signals = if self.handle_signals { Some(SignalGuard::install(self.pipe.write)?) } else { None };
+
+        let mut pollfds: Vec<libc::pollfd> = fds
+            .iter()
+            .chain(std::iter::once(&self.pipe.read))
+            .map(|fd| libc::pollfd { fd: *fd, events: libc::POLLIN, revents: 0 })
+            .collect();
+        let mut ready = vec![false; fds.len()];
+
+        while !self.stopped.load(Ordering::SeqCst) {
+            let n = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, self.poll_timeout()) };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(format!("poll failed: {}", err));
+            }
+
+            if pollfds[fds.len()].revents != 0 {
+                self.pipe.drain();
+            }
+            if signals.as_ref().map(|s| s.received()).unwrap_or(false) {
+                println!("[HyprDM] Signal received, stopping the event loop");
+                self.stopped.store(true, Ordering::SeqCst);
+            }
+            for (i, pfd) in pollfds[..fds.len()].iter_mut().enumerate() {
+                if pfd.revents & (libc::POLLERR | libc::POLLNVAL) != 0 {
+                    return Err(format!("fd {} failed while polling", pfd.fd));
+                }
+                ready[i] = pfd.revents & (libc::POLLIN | libc::POLLHUP) != 0;
+                pfd.revents = 0;
+            }
+
+            tick(&ready)?;
+            self.run_due_timers();
+        }
+
+        // The next run starts fresh
+        self.stopped.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Milliseconds until the next timer, -1 (forever) without timers
+    fn poll_timeout(&self) -> i32 {
+        match self.timers.iter().map(|t| t.next).min() {
+            Some(next) => {
+                let wait = next.saturating_duration_since(Instant::now());
+                // Round up so the timer is due when poll returns
+                let millis = wait.as_millis() + (wait.subsec_nanos() % 1_000_000 != 0) as u128;
+                millis.min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        }
+    }
+
+    fn run_due_timers(&mut self) {
+        let now = Instant::now();
+        for timer in self.timers.iter_mut().filter(|t| t.next <= now) {
+            (timer.callback)();
+            // Skip missed ticks instead of firing them all at once
+            while timer.next <= now {
+                timer.next += timer.every.max(Duration::from_millis(1));
+            }
+        }
+    }
+}
+
+/// SIGINT/SIGTERM handlers for the duration of a run. Every running loop gets its own
+/// wake slot; the first installs the handlers and the last puts the previous ones back
+struct SignalGuard {
+    slot: usize,
+    seen: u64,
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    SIGNAL_COUNT.fetch_add(1, Ordering::SeqCst);
+    for slot in &SIGNAL_WAKE_FDS {
+        let fd = slot.load(Ordering::SeqCst);
+        if fd >= 0 {
+            // write(2) is async-signal-safe
+            unsafe { libc::write(fd, [1u8].as_ptr() as *const libc::c_void, 1) };
+        }
+    }
+}
+
+impl SignalGuard {
+    fn install(wake_fd: RawFd) -> Result<Self, String> {
+        let mut users = SIGNAL_USERS.lock().map_err(|_| "Signal handler state is poisoned".to_string())?;
+        let slot = SIGNAL_WAKE_FDS
+            .iter()
+            .position(|s| s.compare_exchange(-1, wake_fd, Ordering::SeqCst, Ordering::SeqCst).is_ok())
+            .ok_or_else(|| format!("At most {} event loops can handle signals", SIGNAL_SLOTS))?;
+        let guard = SignalGuard { slot, seen: SIGNAL_COUNT.load(Ordering::SeqCst) };
+
+        if users.0 == 0 {
+            for signal in [libc::SIGINT, libc::SIGTERM] {
+                unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                    action.sa_flags = libc::SA_RESTART;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    let mut previous: libc::sigaction = std::mem::zeroed();
+                    if libc::sigaction(signal, &action, &mut previous) != 0 {
+                        let err = std::io::Error::last_os_error();
+                        restore(&users.1);
+                        users.1.clear();
+                        SIGNAL_WAKE_FDS[slot].store(-1, Ordering::SeqCst);
+                        return Err(format!("Failed to install signal handler: {}", err));
+                    }
+                    users.1.push((signal, previous));
+                }
+            }
+        }
+        users.0 += 1;
+        Ok(guard)
+    }
+
+    /// A signal arrived since this run started
+    fn received(&self) -> bool {
+        SIGNAL_COUNT.load(Ordering::SeqCst) != self.seen
+    }
+}
+
+fn restore(previous: &[(libc::c_int, libc::sigaction)]) {
+    for (signal, action) in previous {
+        unsafe { libc::sigaction(*signal, action, std::ptr::null_mut()) };
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        SIGNAL_WAKE_FDS[self.slot].store(-1, Ordering::SeqCst);
+        let mut users = match SIGNAL_USERS.lock() {
+            Ok(users) => users,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        users.0 -= 1;
+        if users.0 == 0 {
+            restore(&users.1);
+            users.1.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn a_stop_before_run_still_applies_and_is_reset() {
+        let mut event_loop = EventLoop::new().unwrap();
+        let stop = event_loop.stop_handle();
+        stop.stop();
+        assert!(stop.is_stopped());
+        event_loop.run(&[], |_| Ok(())).unwrap();
+        assert!(!stop.is_stopped());
+    }
+
+    #[test]
+    fn stop_handle_works_from_another_thread() {
+        let mut event_loop = EventLoop::new().unwrap();
+        let stop = event_loop.stop_handle();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stop.stop();
+        });
+        let started = Instant::now();
+        event_loop.run(&[], |_| Ok(())).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        stopper.join().unwrap();
+    }
+
+    #[test]
+    fn ticks_report_readable_fds_and_errors_end_the_run() {
+        let pipe = WakePipe::new().unwrap();
+        let mut event_loop = EventLoop::new().unwrap();
+        let waker = event_loop.waker();
+        let mut seen = Vec::new();
+
+        waker.wake();
+        let result = event_loop.run(&[pipe.read], |ready| {
+            seen.push(ready[0]);
+            match seen.len() {
+                1 => {
+                    pipe.wake();
+                    Ok(())
+                }
+                _ => Err("done".to_string()),
+            }
+        });
+        assert_eq!(result, Err("done".to_string()));
+        assert_eq!(seen, [false, true]);
+    }
+
+    #[test]
+    fn timers_fire_on_time_and_skip_missed_ticks() {
+        let mut event_loop = EventLoop::new().unwrap();
+        let stop = event_loop.stop_handle();
+        let fired = Rc::new(Cell::new(0));
+        let count = fired.clone();
+        event_loop.add_timer(Duration::from_millis(20), move || {
+            count.set(count.get() + 1);
+            if count.get() == 3 {
+                stop.stop();
+            }
+        });
+
+        let started = Instant::now();
+        event_loop.run(&[], |_| Ok(())).unwrap();
+        assert_eq!(fired.get(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(60));
+
+        // A long block counts as one missed tick, not many
+        thread::sleep(Duration::from_millis(100));
+        event_loop.run_due_timers();
+        assert_eq!(fired.get(), 4);
+        assert!(event_loop.timers[0].next > Instant::now());
+    }
+
+    #[test]
+    fn poll_timeout_rounds_up_and_saturates() {
+        let mut event_loop = EventLoop::new().unwrap();
+        assert_eq!(event_loop.poll_timeout(), -1);
+
+        event_loop.add_timer(Duration::from_millis(i32::MAX as u64 + 5_000), || {});
+        assert_eq!(event_loop.poll_timeout(), i32::MAX);
+
+        event_loop.add_timer(Duration::from_micros(2_500), || {});
+        let timeout = event_loop.poll_timeout();
+        assert!((1..=3).contains(&timeout), "{}", timeout);
+    }
+
+    #[test]
+    fn a_signal_stops_every_loop_that_handles_signals() {
+        let (tx, rx) = mpsc::channel();
+        let loops: Vec<_> = (0..2)
+            .map(|_| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    let mut event_loop = EventLoop::new().unwrap();
+                    event_loop.handle_signals = true;
+                    // Handlers are installed once the first tick runs
+                    event_loop.waker().wake();
+                    let mut announced = false;
+                    event_loop
+                        .run(&[], |_| {
+                            if !announced {
+                                announced = true;
+                                tx.send(()).unwrap();
+                            }
+                            Ok(())
+                        })
+                        .unwrap();
+                })
+            })
+            .collect();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        unsafe { libc::raise(libc::SIGTERM) };
+        for handle in loops {
+            handle.join().unwrap();
+        }
+        assert!(SIGNAL_WAKE_FDS.iter().all(|s| s.load(Ordering::SeqCst) == -1));
+        assert_eq!(SIGNAL_USERS.lock().unwrap().0, 0);
+    }
+}
@@ -0,0 +1,705 @@
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use config::{builtin_quickshell_ui, HDMConfig};
+use crate::daemon::lookup_user;
+use crate::greeter_hyprland::{HyprlandGreeter, UI_CRASH_MARKER};
+use crate::hooks::{HookEnv, Hooks};
+use crate::process_group;
+use crate::session_log::RotatingLog;
+use crate::session_policy::{user_home, UserIdentity};
+use crate::user_manager::PamHandle;
+
+/// Printed by the interface once its window is up, see `Component.onCompleted` in main.qml
+pub const READY_MARKER: &str = "hyprdm:ready";
+
+/// Variables copied from the daemon's environment, everything else is dropped
+const PASSTHROUGH_ENV: &[&str] = &["LANG", "LC_ALL", "LC_MESSAGES", "XCURSOR_THEME", "XCURSOR_SIZE"];
+
+/// XDG_RUNTIME_DIR of the greeter, the builtin compositor puts its socket there
+pub const GREETER_RUNTIME_DIR: &str = "/run/hyprdm/greeter/runtime";
+
+/// A greeter that stays up this long is considered stable and its crash count is reset
+const STABLE_AFTER: Duration = Duration::from_secs(30);
+
+/// What happened to the greeter process
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreeterEvent {
+    /// Quickshell was spawned; `attempt` is 0 for the first start
+    Started { pid: u32, attempt: u32, ui: PathBuf },
+    /// The interface printed the ready marker
+    Ready,
+    /// Quickshell died with a non-zero status or a signal; both are None when only
+    /// the interface failed and the hosting Hyprland exited cleanly
+    Crashed { code: Option<i32>, signal: Option<i32> },
+    /// Switched to the built-in interface after repeated crashes
+    FellBackToBuiltin,
+    /// Too many crashes, the greeter is not restarted again
+    GaveUp,
+    /// Quickshell exited with status 0
+    Exited,
+    /// Stopped through `GreeterLauncher::stop`
+    Stopped,
+}
+
+type EventCallback = Box<dyn Fn(&GreeterEvent) + Send>;
+
+/// What the interface runs on
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreeterCompositor {
+    /// Quickshell connects to `wayland_display`, served by hyprdm's own compositor
+    Builtin,
+    /// Hyprland is started instead and runs Quickshell from its generated config
+    Hyprland(HyprlandGreeter),
+}
+
+#[derive(Clone)]
+struct Emitter {
+    tx: Sender<GreeterEvent>,
+    callback: Arc<Mutex<Option<EventCallback>>>,
+}
+
+impl Emitter {
+    fn emit(&self, event: GreeterEvent) {
+        if let Ok(cb) = self.callback.lock() {
+            if let Some(cb) = cb.as_ref() {
+                cb(&event);
+            }
+        }
+        let _ = self.tx.send(event);
+    }
+}
+
+/// Account the greeter process is switched to before exec
+#[derive(Debug, Clone)]
+pub struct GreeterUser {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups, e.g. video and input for Hyprland
+    pub groups: Vec<u32>,
+    pub home: PathBuf,
+}
+
+impl GreeterUser {
+    pub fn lookup(name: &str) -> Result<Self, String> {
+        let (uid, gid) = lookup_user(name)?;
+        let home = user_home(name).unwrap_or_else(|| PathBuf::from("/var/lib/hyprdm"));
+        let groups = UserIdentity::lookup(name).map(|id| id.groups).unwrap_or_else(|_| vec![gid]);
+        Ok(Self { name: name.to_string(), uid, gid, groups, home })
+    }
+
+    fn identity(&self) -> UserIdentity {
+        UserIdentity {
+            name: self.name.clone(),
+            uid: self.uid,
+            gid: self.gid,
+            groups: self.groups.clone(),
+            home: self.home.clone(),
+            shell: PathBuf::from("/bin/sh"),
+        }
+    }
+}
+
+/// Create the greeter's runtime dir with mode 0700 and hand it to `owner` (uid, gid);
+/// a link in its place is refused rather than followed
+pub fn prepare_runtime_dir(dir: &Path, owner: Option<(u32, u32)>) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    let meta = fs::symlink_metadata(dir).map_err(|e| format!("Failed to inspect {:?}: {}", dir, e))?;
+    if !meta.is_dir() {
+        return Err(format!("{:?} is not a directory", dir));
+    }
+    if let Some((uid, gid)) = owner {
+        if meta.uid() != uid || meta.gid() != gid {
+            std::os::unix::fs::lchown(dir, Some(uid), Some(gid))
+                .map_err(|e| format!("Failed to chown {:?}: {}", dir, e))?;
+        }
+    }
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .map_err(|e| format!("Failed to chmod {:?}: {}", dir, e))
+}
+
+/// Starts the Quickshell greeter and keeps it running
+pub struct GreeterLauncher {
+    /// main.qml of the configured interface
+    pub ui: PathBuf,
+    pub quickshell: String,
+    /// Private socket of the greeter compositor, e.g. `wayland-hyprdm`
+    pub wayland_display: String,
+    /// XDG_RUNTIME_DIR of the interface under the builtin compositor, owned by `user`
+    pub runtime_dir: PathBuf,
+    pub log_path: PathBuf,
+    pub log_max_size: u64,
+    pub max_restarts: u32,
+    pub fallback_after: u32,
+    pub vt: Option<u32>,
+    /// Run as this user instead of the daemon's own uid
+    pub user: Option<GreeterUser>,
+    /// Daemon socket, passed to the interface as HYPRDM_SOCKET
+    pub daemon_socket: Option<PathBuf>,
+    pub hooks: Hooks,
+    pub compositor: GreeterCompositor,
+    callback: Arc<Mutex<Option<EventCallback>>>,
+    running: Arc<AtomicBool>,
+    pid: Arc<AtomicU32>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl GreeterLauncher {
+    pub fn new(ui: PathBuf, wayland_display: &str) -> Self {
+        Self {
+            ui,
+            quickshell: "quickshell".into(),
+            wayland_display: wayland_display.to_string(),
+            runtime_dir: PathBuf::from(GREETER_RUNTIME_DIR),
+            log_path: PathBuf::from("/var/log/hyprdm/greeter.log"),
+            log_max_size: 1024 * 1024,
+            max_restarts: 5,
+            fallback_after: 3,
+            vt: None,
+            user: None,
+            daemon_socket: None,
+            hooks: Hooks::default(),
+            compositor: GreeterCompositor::Builtin,
+            callback: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(false)),
+            pid: Arc::new(AtomicU32::new(0)),
+            handle: None,
+        }
+    }
+
+    /// Resolve the interface from `greeter_interface` and take the limits from the config
+    pub fn from_config(config: &HDMConfig) -> Result<Self, String> {
+        let mut launcher = Self::new(config.resolve_quickshell_ui()?, &config.greeter_wayland_display);
+        launcher.log_path = config.greeter_log_file();
+        launcher.log_max_size = config.session_log_max_size;
+        launcher.max_restarts = config.greeter_max_restarts;
+        launcher.fallback_after = config.greeter_fallback_after;
+        launcher.hooks = Hooks::from_config(config);
+        launcher.daemon_socket = Some(PathBuf::from(&config.daemon_socket));
+        if config.greeter_compositor == "hyprland" {
+            launcher.compositor = GreeterCompositor::Hyprland(HyprlandGreeter::from_config(config));
+        }
+
+        match GreeterUser::lookup(&config.greeter_user) {
+            Ok(user) => launcher.user = Some(user),
+            // Running the UI as root would defeat the privilege separation
+            Err(e) if unsafe { libc::geteuid() } == 0 => return Err(e),
+            Err(e) => eprintln!("[HyprDM] {}, the greeter runs as the current user", e),
+        }
+        // Without root there is no /run/hyprdm, stay inside our own runtime dir
+        if unsafe { libc::geteuid() } != 0 {
+            if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+                launcher.runtime_dir = PathBuf::from(dir).join("hyprdm-greeter");
+            }
+        }
+        Ok(launcher)
+    }
+
+    pub fn with_vt(mut self, vt: u32) -> Self {
+        self.vt = Some(vt);
+        self
+    }
+
+    /// Also deliver every event to `callback`, e.g. an FFI function pointer
+    pub fn on_event<F: Fn(&GreeterEvent) + Send + 'static>(&self, callback: F) {
+        if let Ok(mut cb) = self.callback.lock() {
+            *cb = Some(Box::new(callback));
+        }
+    }
+
+    /// Pid of the running greeter: Quickshell, or the Hyprland hosting it
+    pub fn pid(&self) -> Option<u32> {
+        match self.pid.load(Ordering::SeqCst) {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
+
+    /// Run the greeter_setup hook and start the greeter, events are delivered on the returned channel
+    pub fn spawn(&mut self) -> Result<Receiver<GreeterEvent>, String> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err("Greeter is already running".into());
+        }
+
+        let env = HookEnv { vt: self.vt, ..Default::default() };
+        if let Err(e) = self.hooks.run_greeter_setup(&env) {
+            // A broken hook must not leave the machine without a login screen
+            eprintln!("[HyprDM] {}", e);
+        }
+
+        let log = match RotatingLog::open(&self.log_path, self.log_max_size) {
+            Ok(log) => Some(Arc::new(Mutex::new(log))),
+            Err(e) => {
+                eprintln!("[HyprDM] Greeter output is not logged: {}", e);
+                None
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let emitter = Emitter { tx, callback: self.callback.clone() };
+        let worker = Worker {
+            ui: self.ui.clone(),
+            quickshell: self.quickshell.clone(),
+            wayland_display: self.wayland_display.clone(),
+            runtime_dir: self.runtime_dir.clone(),
+            vt: self.vt,
+            user: self.user.clone(),
+            daemon_socket: self.daemon_socket.clone(),
+            compositor: self.compositor.clone(),
+            max_restarts: self.max_restarts,
+            fallback_after: self.fallback_after,
+            log,
+            running: self.running.clone(),
+            pid: self.pid.clone(),
+            ui_crashed: Arc::new(AtomicBool::new(false)),
+        };
+
+        self.handle = Some(thread::spawn(move || worker.supervise(&emitter)));
+        Ok(rx)
+    }
+
+    /// Terminate the greeter and stop restarting it
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for GreeterLauncher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct Worker {
+    ui: PathBuf,
+    quickshell: String,
+    wayland_display: String,
+    runtime_dir: PathBuf,
+    vt: Option<u32>,
+    user: Option<GreeterUser>,
+    daemon_socket: Option<PathBuf>,
+    compositor: GreeterCompositor,
+    max_restarts: u32,
+    fallback_after: u32,
+    log: Option<Arc<Mutex<RotatingLog>>>,
+    running: Arc<AtomicBool>,
+    pid: Arc<AtomicU32>,
+    /// Set when the interface inside Hyprland printed UI_CRASH_MARKER
+    ui_crashed: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn supervise(mut self, emitter: &Emitter) {
+        let mut crashes = 0u32;
+        let mut attempt = 0u32;
+        let mut builtin = false;
+
+        while self.running.load(Ordering::SeqCst) {
+            let started_at = Instant::now();
+            let outcome = match self.start(emitter) {
+                // The greeter's logind session ends with the process
+                Ok(mut child) => {
+                    emitter.emit(GreeterEvent::Started { pid: child.id(), attempt, ui: self.ui.clone() });
+                    self.wait(&mut child)
+                }
+                Err(e) => {
+                    eprintln!("[HyprDM] {}", e);
+                    Some((Some(127), None))
+                }
+            };
+            self.pid.store(0, Ordering::SeqCst);
+
+            let (code, signal) = match outcome {
+                None => {
+                    emitter.emit(GreeterEvent::Stopped);
+                    return;
+                }
+                Some((Some(0), _)) if self.ui_crashed.load(Ordering::SeqCst) => (None, None),
+                Some((Some(0), _)) => {
+                    emitter.emit(GreeterEvent::Exited);
+                    break;
+                }
+                Some(status) => status,
+            };
+
+            if started_at.elapsed() >= STABLE_AFTER {
+                crashes = 0;
+            }
+            crashes += 1;
+            attempt += 1;
+            emitter.emit(GreeterEvent::Crashed { code, signal });
+            eprintln!("[HyprDM] Greeter crashed ({} in a row)", crashes);
+
+            if crashes > self.max_restarts {
+                emitter.emit(GreeterEvent::GaveUp);
+                break;
+            }
+            if !builtin && crashes >= self.fallback_after {
+                match builtin_quickshell_ui() {
+                    Ok(path) => {
+                        eprintln!("[HyprDM] Falling back to the built-in greeter at {:?}", path);
+                        self.ui = path;
+                        builtin = true;
+                        emitter.emit(GreeterEvent::FellBackToBuiltin);
+                    }
+                    Err(e) => eprintln!("[HyprDM] {}", e),
+                }
+            }
+
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    fn start(&self, emitter: &Emitter) -> Result<Child, String> {
+        let owner = self.user.as_ref().map(|u| (u.uid, u.gid));
+        let mut cmd = match &self.compositor {
+            GreeterCompositor::Builtin => {
+                prepare_runtime_dir(&self.runtime_dir, owner)?;
+                let mut cmd = Command::new(&self.quickshell);
+                cmd.arg("-p").arg(&self.ui);
+                cmd
+            }
+            GreeterCompositor::Hyprland(hyprland) => {
+                // Regenerated on every start, the interface changes on fallback
+                let config = hyprland.write_config(&self.quickshell, &self.ui, owner)?;
+                let mut cmd = Command::new(&hyprland.binary);
+                cmd.arg("--config").arg(config);
+                cmd
+            }
+        };
+        cmd.env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+            .env("XDG_SESSION_CLASS", "greeter")
+            .env("HYPRDM_GREETER", "1")
+            .current_dir(self.ui.parent().unwrap_or(Path::new("/")))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0);
+        for key in PASSTHROUGH_ENV {
+            if let Ok(value) = std::env::var(key) {
+                cmd.env(key, value);
+            }
+        }
+        match &self.compositor {
+            GreeterCompositor::Builtin => {
+                cmd.env("XDG_RUNTIME_DIR", &self.runtime_dir)
+                    .env("WAYLAND_DISPLAY", &self.wayland_display)
+                    .env("QT_QPA_PLATFORM", "wayland");
+            }
+            // Hyprland picks its own Wayland socket and hands it to the interface
+            GreeterCompositor::Hyprland(hyprland) => {
+                cmd.env("XDG_RUNTIME_DIR", &hyprland.runtime_dir)
+                    .env("XDG_SEAT", "seat0")
+                    .env("XDG_SESSION_TYPE", "wayland");
+            }
+        }
+        if let Some(vt) = self.vt {
+            cmd.env("XDG_VTNR", vt.to_string());
+        }
+        if let Some(socket) = &self.daemon_socket {
+            cmd.env("HYPRDM_SOCKET", socket);
+        }
+        if let Some(user) = &self.user {
+            cmd.env("USER", &user.name)
+                .env("LOGNAME", &user.name)
+                .env("HOME", &user.home);
+            // std would drop the supplementary groups, so switch by hand; without root
+            // there is nobody to switch to
+            if unsafe { libc::geteuid() } == 0 {
+                match &self.compositor {
+                    GreeterCompositor::Hyprland(_) => {
+                        let exec = exec_in_greeter_session(&cmd, user, self.vt)?;
+                        unsafe { cmd.pre_exec(exec) };
+                    }
+                    GreeterCompositor::Builtin => {
+                        let identity = user.identity();
+                        unsafe { cmd.pre_exec(move || identity.switch_process()) };
+                    }
+                }
+            }
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("Failed to start {:?}: {}", cmd.get_program(), e))?;
+        self.pid.store(child.id(), Ordering::SeqCst);
+        self.ui_crashed.store(false, Ordering::SeqCst);
+
+        let ready = Arc::new(AtomicBool::new(false));
+        if let Some(stdout) = child.stdout.take() {
+            watch_output(stdout, self.log.clone(), ready.clone(), self.ui_crashed.clone(), emitter.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            watch_output(stderr, self.log.clone(), ready, self.ui_crashed.clone(), emitter.clone());
+        }
+        Ok(child)
+    }
+
+    /// Wait for the child; None when stopped through `running`
+    fn wait(&self, child: &mut Child) -> Option<(Option<i32>, Option<i32>)> {
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => return Some((status.code(), status.signal())),
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("[HyprDM] Failed to wait for the greeter: {}", e);
+                    return Some((None, None));
+                }
+            }
+
+            if !self.running.load(Ordering::SeqCst) {
+                terminate(child);
+                return None;
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+/// The `pre_exec` step for Hyprland: open a logind session of class greeter, so it gets
+/// the seat's devices, then drop to `user` and exec `cmd` with the session's variables.
+/// This runs in the forked child, which makes the greeter and not the daemon the session
+/// leader. Without a session Hyprland may still start through seatd, so a failure is
+/// only logged
+fn exec_in_greeter_session(
+    cmd: &Command,
+    user: &GreeterUser,
+    vt: Option<u32>,
+) -> Result<impl FnMut() -> io::Result<()> + Send + Sync + 'static, String> {
+    let c_string = |bytes: &[u8]| {
+        CString::new(bytes).map_err(|_| format!("{:?} contains a NUL byte", String::from_utf8_lossy(bytes)))
+    };
+    let program = c_string(cmd.get_program().as_bytes())?;
+    let argv = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| c_string(arg.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let env: Vec<(OsString, OsString)> = cmd
+        .get_envs()
+        .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+        .collect();
+    let name = user.name.clone();
+    let identity = user.identity();
+
+    Ok(move || {
+        let mut env = env.clone();
+        match PamHandle::for_greeter(&name).and_then(|mut pam| pam.open_session("greeter", vt).map(|()| pam)) {
+            Ok(pam) => {
+                // What we set wins, XDG_RUNTIME_DIR is where hyprdm looks for Hyprland's sockets
+                for (key, value) in pam.env() {
+                    if !env.iter().any(|(k, _)| k == key) {
+                        env.push((key.clone(), value.clone()));
+                    }
+                }
+                // The exec keeps the session open, it ends with the greeter
+                std::mem::forget(pam);
+            }
+            Err(e) => eprintln!("[HyprDM] Greeter runs without a logind session: {}", e),
+        }
+        identity.switch_process()?;
+
+        let envp = env
+            .iter()
+            .map(|(key, value)| CString::new([key.as_bytes(), b"=", value.as_bytes()].concat()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let argv_ptrs: Vec<*const libc::c_char> =
+            argv.iter().map(|a| a.as_ptr()).chain(std::iter::once(std::ptr::null())).collect();
+        let envp_ptrs: Vec<*const libc::c_char> =
+            envp.iter().map(|e| e.as_ptr()).chain(std::iter::once(std::ptr::null())).collect();
+        unsafe { libc::execvpe(program.as_ptr(), argv_ptrs.as_ptr(), envp_ptrs.as_ptr()) };
+        Err(io::Error::last_os_error())
+    })
+}
+
+/// SIGTERM the greeter's process group, SIGKILL whatever is left after two seconds
+fn terminate(child: &mut Child) {
+    let pgid = child.id();
+    let _ = process_group::signal(pgid, libc::SIGTERM);
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let _ = process_group::signal(pgid, libc::SIGKILL);
+    let _ = child.wait();
+}
+
+/// Copy the output into the log, report the ready marker once and note a crashed interface
+fn watch_output<R: Read + Send + 'static>(
+    reader: R,
+    log: Option<Arc<Mutex<RotatingLog>>>,
+    ready: Arc<AtomicBool>,
+    ui_crashed: Arc<AtomicBool>,
+    emitter: Emitter,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if let Some(log) = &log {
+                if let Ok(mut log) = log.lock() {
+                    let _ = log.write_all(&line);
+                }
+            }
+            let text = String::from_utf8_lossy(&line);
+            if text.contains(READY_MARKER) && !ready.swap(true, Ordering::SeqCst) {
+                emitter.emit(GreeterEvent::Ready);
+            }
+            if text.contains(UI_CRASH_MARKER) {
+                ui_crashed.store(true, Ordering::SeqCst);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn script(dir: &TempDir, body: &str) -> String {
+        let path = dir.write("quickshell", &format!("#!/bin/sh\n{}\n", body));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn launcher(dir: &TempDir, body: &str) -> GreeterLauncher {
+        let mut launcher = GreeterLauncher::new(dir.write("ui/main.qml", ""), "wayland-test");
+        launcher.quickshell = script(dir, body);
+        launcher.runtime_dir = dir.join("runtime");
+        launcher.log_path = dir.join("greeter.log");
+        launcher
+    }
+
+    fn events_until(rx: &Receiver<GreeterEvent>, last: &GreeterEvent) -> Vec<GreeterEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = rx.recv_timeout(Duration::from_secs(20)) {
+            let done = event == *last;
+            events.push(event);
+            if done {
+                break;
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn runtime_dir_is_private_to_the_greeter_user() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, chown needs root");
+            return;
+        }
+        let dir = TempDir::new("greeter-runtime");
+        fs::create_dir(dir.join("runtime")).unwrap();
+        std::os::unix::fs::symlink("/tmp", dir.join("link")).unwrap();
+
+        prepare_runtime_dir(&dir.join("runtime"), Some((65534, 65534))).unwrap();
+        let meta = fs::metadata(dir.join("runtime")).unwrap();
+        assert_eq!((meta.uid(), meta.gid(), meta.mode() & 0o777), (65534, 65534, 0o700));
+
+        assert!(prepare_runtime_dir(&dir.join("link"), Some((65534, 65534))).is_err());
+        assert_eq!(fs::metadata("/tmp").unwrap().uid(), 0);
+    }
+
+    #[test]
+    fn builtin_greeter_runs_as_its_user_in_its_own_runtime_dir() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, switching users needs root");
+            return;
+        }
+        let dir = TempDir::new("greeter-user");
+        let mut launcher = launcher(&dir, "echo \"$(id -u) $XDG_RUNTIME_DIR $WAYLAND_DISPLAY $USER\"; echo hyprdm:ready");
+        launcher.user = Some(GreeterUser {
+            name: "nobody".into(),
+            uid: 65534,
+            gid: 65534,
+            groups: vec![65534],
+            home: "/nonexistent".into(),
+        });
+        let rx = launcher.spawn().unwrap();
+
+        let events = events_until(&rx, &GreeterEvent::Exited);
+        assert!(matches!(events[0], GreeterEvent::Started { attempt: 0, .. }));
+        assert!(events.contains(&GreeterEvent::Ready));
+        assert_eq!(events.last(), Some(&GreeterEvent::Exited));
+
+        let log = fs::read_to_string(dir.join("greeter.log")).unwrap();
+        let runtime = dir.join("runtime");
+        assert!(log.contains(&format!("65534 {} wayland-test nobody", runtime.display())), "{}", log);
+        assert_eq!(fs::metadata(runtime).unwrap().uid(), 65534);
+    }
+
+    #[test]
+    fn the_daemons_runtime_dir_is_not_passed_on() {
+        assert!(!PASSTHROUGH_ENV.contains(&"XDG_RUNTIME_DIR"));
+    }
+
+    #[test]
+    fn repeated_crashes_give_up() {
+        let dir = TempDir::new("greeter-crash");
+        let mut launcher = launcher(&dir, "exit 2");
+        launcher.max_restarts = 1;
+        launcher.fallback_after = 10;
+        let rx = launcher.spawn().unwrap();
+
+        let events = events_until(&rx, &GreeterEvent::GaveUp);
+        let crashes = events.iter().filter(|e| **e == GreeterEvent::Crashed { code: Some(2), signal: None }).count();
+        assert_eq!(crashes, 2);
+        assert_eq!(events.last(), Some(&GreeterEvent::GaveUp));
+    }
+
+    #[test]
+    fn hyprland_greeter_gets_its_groups_and_seat() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, switching users needs root");
+            return;
+        }
+        let dir = TempDir::new("greeter-hyprland");
+        let mut launcher = launcher(&dir, "");
+        let mut hyprland = HyprlandGreeter::new();
+        hyprland.binary = script(&dir, "echo \"$(id -u) $(id -G) $XDG_SEAT $XDG_SESSION_TYPE $XDG_SESSION_CLASS $XDG_RUNTIME_DIR\"");
+        hyprland.config_path = dir.join("hyprland.conf");
+        hyprland.runtime_dir = dir.join("hypr-runtime");
+        launcher.compositor = GreeterCompositor::Hyprland(hyprland);
+        launcher.user = Some(GreeterUser {
+            name: "nobody".into(),
+            uid: 65534,
+            gid: 65534,
+            groups: vec![65534, 100],
+            home: "/nonexistent".into(),
+        });
+        let rx = launcher.spawn().unwrap();
+        assert_eq!(events_until(&rx, &GreeterEvent::Exited).last(), Some(&GreeterEvent::Exited));
+
+        let log = fs::read_to_string(dir.join("greeter.log")).unwrap();
+        let expected = format!("65534 65534 100 seat0 wayland greeter {}", dir.join("hypr-runtime").display());
+        assert!(log.contains(&expected), "{}", log);
+    }
+}
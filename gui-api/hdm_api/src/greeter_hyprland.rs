@@ -0,0 +1,218 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use config::HDMConfig;
+use crate::greeter::prepare_runtime_dir;
+use crate::monitor_config::greeter_monitor_rules;
+use crate::session_manager::shell_quote;
+
+/// Printed by the `exec-once` line when the interface exits with an error, so a crashed
+/// interface is not mistaken for a finished login when Hyprland exits cleanly
+pub const UI_CRASH_MARKER: &str = "hyprdm:ui-crashed";
+
+/// Runs the greeter inside Hyprland with a generated, throwaway `hyprland.conf`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyprlandGreeter {
+    pub binary: String,
+    /// Where the generated config is written; root owned, readable by the greeter user
+    pub config_path: PathBuf,
+    /// XDG_RUNTIME_DIR of the greeter, Hyprland puts its sockets there
+    pub runtime_dir: PathBuf,
+    /// Values of the `monitor=` lines; empty means `,preferred,auto,1`
+    pub monitors: Vec<String>,
+    pub kb_layout: String,
+    pub kb_variant: String,
+    pub kb_options: String,
+}
+
+impl Default for HyprlandGreeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyprlandGreeter {
+    pub fn new() -> Self {
+        Self {
+            binary: "Hyprland".into(),
+            config_path: PathBuf::from("/run/hyprdm/greeter/hyprland.conf"),
+            runtime_dir: PathBuf::from("/run/hyprdm/greeter/runtime"),
+            monitors: Vec::new(),
+            kb_layout: "us".into(),
+            kb_variant: String::new(),
+            kb_options: String::new(),
+        }
+    }
+
+    pub fn from_config(config: &HDMConfig) -> Self {
+        let mut greeter = Self::new();
+        greeter.binary = config.greeter_hyprland.clone();
+        greeter.kb_layout = config.greeter_kb_layout.clone();
+        greeter.kb_variant = config.greeter_kb_variant.clone().unwrap_or_default();
+        greeter.kb_options = config.greeter_kb_options.clone().unwrap_or_default();
+        match greeter_monitor_rules(config) {
+            Ok(rules) => greeter.monitors = rules.into_iter().map(|r| r.raw).collect(),
+            Err(e) => eprintln!("[HyprDM] Using the default monitor layout: {}", e),
+        }
+        greeter
+    }
+
+    /// The config text: outputs, keyboard, no animations, the interface as the only client,
+    /// and `exit` as soon as the interface quits after a login
+    pub fn render(&self, quickshell: &str, ui: &Path) -> Result<String, String> {
+        let mut lines = vec![
+            "# Generated by hyprdm for the greeter, changes are overwritten".to_string(),
+            String::new(),
+        ];
+
+        if self.monitors.is_empty() {
+            lines.push("monitor = ,preferred,auto,1".to_string());
+        }
+        for monitor in &self.monitors {
+            lines.push(format!("monitor = {}", hypr_value(monitor)?));
+        }
+        lines.push(String::new());
+
+        lines.push("env = QT_QPA_PLATFORM,wayland".to_string());
+        lines.push("env = XDG_CURRENT_DESKTOP,Hyprland".to_string());
+        lines.push(String::new());
+
+        lines.push("input {".to_string());
+        lines.push(format!("    kb_layout = {}", hypr_value(&self.kb_layout)?));
+        lines.push(format!("    kb_variant = {}", hypr_value(&self.kb_variant)?));
+        lines.push(format!("    kb_options = {}", hypr_value(&self.kb_options)?));
+        lines.push("}".to_string());
+        lines.push(String::new());
+
+        for block in [
+            "general {\n    gaps_in = 0\n    gaps_out = 0\n    border_size = 0\n}",
+            "animations {\n    enabled = false\n}",
+            "decoration {\n    rounding = 0\n}",
+            "misc {\n    disable_hyprland_logo = true\n    disable_splash_rendering = true\n    force_default_wallpaper = 0\n    disable_autoreload = true\n}",
+        ] {
+            lines.push(block.to_string());
+            lines.push(String::new());
+        }
+
+        // Hyprland runs exec lines through `sh -c`
+        let exec = format!(
+            "{} -p {} || echo {}; hyprctl dispatch exit",
+            shell_quote(quickshell),
+            shell_quote(&ui.to_string_lossy()),
+            UI_CRASH_MARKER
+        );
+        lines.push(format!("exec-once = {}", hypr_value(&exec)?));
+
+        Ok(lines.join("\n") + "\n")
+    }
+
+    /// Write the config for `ui` and prepare the runtime dir for `owner` (uid, gid)
+    pub fn write_config(&self, quickshell: &str, ui: &Path, owner: Option<(u32, u32)>) -> Result<PathBuf, String> {
+        let content = self.render(quickshell, ui)?;
+        if let Some(dir) = self.config_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o644)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&self.config_path)
+            .map_err(|e| format!("Failed to write {:?}: {}", self.config_path, e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write {:?}: {}", self.config_path, e))?;
+
+        prepare_runtime_dir(&self.runtime_dir, owner)?;
+
+        Ok(self.config_path.clone())
+    }
+}
+
+/// hyprlang reads `#` as a comment unless doubled, and a value cannot span lines
+fn hypr_value(value: &str) -> Result<String, String> {
+    if value.contains('\n') || value.contains('\r') {
+        return Err(format!("Line break in Hyprland config value: {:?}", value));
+    }
+    Ok(value.trim().replace('#', "##"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn render_defaults_to_one_preferred_monitor_and_us_keys() {
+        let config = HyprlandGreeter::new().render("quickshell", Path::new("/usr/share/hyprdm/ui/main.qml")).unwrap();
+        assert!(config.starts_with("# Generated by hyprdm"));
+        assert!(config.contains("\nmonitor = ,preferred,auto,1\n"));
+        assert!(config.contains("\n    kb_layout = us\n"));
+        assert!(config.contains("\n    enabled = false\n"));
+        assert!(config.ends_with(
+            "\nexec-once = quickshell -p /usr/share/hyprdm/ui/main.qml || echo hyprdm:ui-crashed; hyprctl dispatch exit\n"
+        ));
+    }
+
+    #[test]
+    fn render_uses_the_configured_monitors_and_keyboard() {
+        let mut greeter = HyprlandGreeter::new();
+        greeter.monitors = vec!["DP-1,2560x1440@144,0x0,1".into(), "HDMI-A-1,disable".into()];
+        greeter.kb_layout = "tr".into();
+        greeter.kb_options = "caps:escape".into();
+        let config = greeter.render("quickshell", Path::new("/ui/main.qml")).unwrap();
+
+        assert!(!config.contains("preferred"));
+        assert!(config.contains("\nmonitor = DP-1,2560x1440@144,0x0,1\nmonitor = HDMI-A-1,disable\n"));
+        assert!(config.contains("\n    kb_layout = tr\n    kb_variant = \n    kb_options = caps:escape\n"));
+    }
+
+    #[test]
+    fn exec_once_quotes_the_interface_and_marks_a_crash() {
+        let config = HyprlandGreeter::new()
+            .render("/opt/quick shell", Path::new("/srv/it's #1/main.qml"))
+            .unwrap();
+        let exec = config.lines().find(|l| l.starts_with("exec-once = ")).unwrap();
+        assert_eq!(
+            exec,
+            format!(
+                "exec-once = '/opt/quick shell' -p '/srv/it'\\''s ##1/main.qml' || echo {}; hyprctl dispatch exit",
+                UI_CRASH_MARKER
+            )
+        );
+    }
+
+    #[test]
+    fn hypr_value_doubles_hashes_and_refuses_line_breaks() {
+        assert_eq!(hypr_value("  #ff0000 "), Ok("##ff0000".to_string()));
+        assert_eq!(hypr_value("us"), Ok("us".to_string()));
+        assert!(hypr_value("us\nexec-once = sh").is_err());
+        assert!(hypr_value("us\rexec-once = sh").is_err());
+
+        let mut greeter = HyprlandGreeter::new();
+        greeter.kb_variant = "intl\nexec-once = sh".into();
+        assert!(greeter.render("quickshell", Path::new("/ui/main.qml")).is_err());
+    }
+
+    #[test]
+    fn write_config_replaces_the_file_but_not_a_planted_link() {
+        let dir = TempDir::new("greeter-hyprland");
+        let mut greeter = HyprlandGreeter::new();
+        greeter.config_path = dir.join("greeter/hyprland.conf");
+        greeter.runtime_dir = dir.join("runtime");
+        let ui = Path::new("/ui/main.qml");
+
+        assert_eq!(greeter.write_config("quickshell", ui, None), Ok(greeter.config_path.clone()));
+        assert_eq!(fs::read_to_string(&greeter.config_path).unwrap(), greeter.render("quickshell", ui).unwrap());
+        assert_eq!(fs::metadata(&greeter.config_path).unwrap().permissions().mode() & 0o777, 0o644);
+
+        let target = dir.write("target", "untouched");
+        fs::remove_file(&greeter.config_path).unwrap();
+        std::os::unix::fs::symlink(&target, &greeter.config_path).unwrap();
+        assert!(greeter.write_config("quickshell", ui, None).is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+    }
+}
@@ -0,0 +1,482 @@
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use smithay::reexports::wayland_protocols::xdg_shell::server::xdg_toplevel;
+use smithay::reexports::wayland_server::protocol::wl_output::{Subpixel, Transform};
+use smithay::reexports::wayland_server::protocol::wl_shm;
+use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
+use smithay::reexports::wayland_server::{DispatchData, Display};
+use smithay::utils::{Logical, Point, Size};
+use smithay::wayland::compositor::{
+    compositor_init, with_states, with_surface_tree_upward, BufferAssignment, SubsurfaceCachedState,
+    SurfaceAttributes, TraversalAction,
+};
+use smithay::wayland::output::{Mode, Output, PhysicalProperties};
+use smithay::wayland::seat::Seat;
+use smithay::wayland::shell::xdg::{xdg_shell_init, ToplevelSurface, XdgRequest};
+use smithay::wayland::shm::{init_shm_global, with_buffer_contents};
+
+use crate::event_loop::{EventLoop, StopHandle};
+use crate::monitor_config::{MonitorMode, MonitorPosition, MonitorRule};
+use crate::software_renderer::{Image, PixelFormat};
+use slog;
+
+/// Frames are composed at most this often, clients wait for the frame callback in between
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// Dark grey behind the windows, like the greeter's own background
+pub const DEFAULT_BACKGROUND: u32 = 0xff1e1e2e;
+
+/// An output that only exists in memory; `frame` is what it last showed
+pub struct VirtualOutput {
+    pub name: String,
+    pub output: Output,
+    pub location: (i32, i32),
+    pub frame: Image,
+}
+
+struct Window {
+    toplevel: ToplevelSurface,
+    output: usize,
+}
+
+#[derive(Default)]
+struct Activity {
+    /// Something committed since the last frame
+    dirty: bool,
+    /// Some surface attached a buffer
+    drawn: bool,
+    last_commit: Option<Instant>,
+}
+
+/// A compositor without GPU or display: clients draw into wl_shm buffers, frames are
+/// composed on the CPU and can be saved as PNG. Each new toplevel fills the next output.
+pub struct HeadlessCompositor {
+    pub display: Display,
+    pub seat: Seat,
+    pub outputs: Vec<VirtualOutput>,
+    pub event_loop: EventLoop,
+    pub background: u32,
+    windows: Rc<RefCell<Vec<Window>>>,
+    activity: Rc<RefCell<Activity>>,
+    started: Instant,
+}
+
+impl HeadlessCompositor {
+    /// One virtual output per (name, width, height), laid out left to right
+    pub fn new(outputs: &[(&str, u32, u32)]) -> Result<Self, String> {
+        if outputs.is_empty() {
+            return Err("A headless compositor needs at least one output".into());
+        }
+        let mut x = 0;
+        let layout: Vec<(String, u32, u32, (i32, i32))> = outputs
+            .iter()
+            .map(|(name, width, height)| {
+                let location = (x, 0);
+                x += *width as i32;
+                (name.to_string(), *width, *height, location)
+            })
+            .collect();
+        Self::with_layout(layout)
+    }
+
+    /// Outputs for the enabled rules with an exact mode, e.g. the imported layout of the
+    /// last user; rules without a size get 1920x1080
+    pub fn from_monitor_rules(rules: &[MonitorRule]) -> Result<Self, String> {
+        let mut next_x = 0;
+        let mut layout = Vec::new();
+        for (i, rule) in rules.iter().filter(|r| !r.disabled).enumerate() {
+            let (width, height) = match rule.mode {
+                MonitorMode::Exact { width, height, .. } => (width, height),
+                _ => (1920, 1080),
+            };
+            // Portrait transforms swap the sides
+            let (width, height) = if rule.transform % 2 == 1 { (height, width) } else { (width, height) };
+            let location = match rule.position {
+                MonitorPosition::At { x, y } => (x, y),
+                MonitorPosition::Auto(_) => (next_x, 0),
+            };
+            next_x = next_x.max(location.0 + width as i32);
+            let name = if rule.is_default() || rule.name.starts_with("desc:") {
+                format!("HEADLESS-{}", i + 1)
+            } else {
+                rule.name.clone()
+            };
+            layout.push((name, width, height, location));
+        }
+        if layout.is_empty() {
+            layout.push(("HEADLESS-1".to_string(), 1920, 1080, (0, 0)));
+        }
+        Self::with_layout(layout)
+    }
+
+    fn with_layout(layout: Vec<(String, u32, u32, (i32, i32))>) -> Result<Self, String> {
+        let mut display = Display::new();
+        let logger: Option<slog::Logger> = None;
+
+        let activity = Rc::new(RefCell::new(Activity::default()));
+        let commits = activity.clone();
+        compositor_init(
+            &mut display,
+            move |surface: WlSurface, _dispatch: DispatchData| on_commit(&surface, &commits),
+            logger.clone(),
+        );
+        // ARGB8888 and XRGB8888 are always advertised, the renderer reads nothing else
+        init_shm_global(&mut display, Vec::new(), logger.clone());
+
+        let windows: Rc<RefCell<Vec<Window>>> = Rc::new(RefCell::new(Vec::new()));
+        let sizes: Vec<(u32, u32)> = layout.iter().map(|(_, w, h, _)| (*w, *h)).collect();
+        let toplevels = windows.clone();
+        xdg_shell_init(
+            &mut display,
+            move |request: XdgRequest, _dispatch: DispatchData| {
+                if let XdgRequest::NewToplevel { surface } = request {
+                    let mut windows = toplevels.borrow_mut();
+                    windows.retain(|w| w.toplevel.alive());
+                    let output = windows.len() % sizes.len();
+                    let (width, height) = sizes[output];
+                    // Fullscreen, so clients skip their decorations
+                    let configured = surface.with_pending_state(|state| {
+                        state.size = Some(Size::from((width as i32, height as i32)));
+                        state.states.set(xdg_toplevel::State::Fullscreen);
+                        state.states.set(xdg_toplevel::State::Activated);
+                    });
+                    if configured.is_ok() {
+                        surface.send_configure();
+                        windows.push(Window { toplevel: surface, output });
+                    }
+                }
+            },
+            logger.clone(),
+        );
+
+        let (seat, _seat_global) = Seat::new(&mut display, "HDM Seat".into(), logger.clone());
+
+        let mut outputs = Vec::new();
+        for (name, width, height, location) in layout {
+            let frame = Image::new(width, height, DEFAULT_BACKGROUND)?;
+            let physical_properties = PhysicalProperties {
+                size: Size::from((0, 0)),
+                subpixel: Subpixel::Unknown,
+                make: "HDM".into(),
+                model: "Headless".into(),
+            };
+            let (output, _output_global) =
+                Output::new(&mut display, name.clone(), physical_properties, logger.clone());
+            let mode = Mode { size: Size::from((width as i32, height as i32)), refresh: 60_000 };
+            output.change_current_state(Some(mode), Some(Transform::Normal), Some(1), Some(Point::from(location)));
+            output.set_preferred(mode);
+            outputs.push(VirtualOutput { name, output, location, frame });
+        }
+
+        let mut event_loop = EventLoop::new()?;
+        // Wakes the loop to compose frames that were held back
+        event_loop.add_timer(FRAME_INTERVAL, || {});
+
+        Ok(Self {
+            display,
+            seat,
+            outputs,
+            event_loop,
+            background: DEFAULT_BACKGROUND,
+            windows,
+            activity,
+            started: Instant::now(),
+        })
+    }
+
+    /// Listen on a free `wayland-N` socket in XDG_RUNTIME_DIR and return its name
+    pub fn add_socket(&mut self) -> Result<OsString, String> {
+        self.display
+            .add_socket_auto()
+            .map_err(|e| format!("Failed to create a Wayland socket: {}", e))
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.event_loop.stop_handle()
+    }
+
+    /// Serve clients and compose frames until stopped
+    pub fn run(&mut self) -> Result<(), String> {
+        self.serve(|_| false)
+    }
+
+    /// Serve clients until something was drawn and nothing committed for `settle`, or
+    /// until `timeout` or `keep_going` returns false. True if the clients settled.
+    pub fn run_until_idle<F: FnMut() -> bool>(
+        &mut self,
+        settle: Duration,
+        timeout: Duration,
+        mut keep_going: F,
+    ) -> Result<bool, String> {
+        let deadline = Instant::now() + timeout;
+        let mut settled = false;
+        self.serve(|activity| {
+            let now = Instant::now();
+            settled = activity.drawn && activity.last_commit.map(|t| now - t >= settle).unwrap_or(false);
+            settled || now >= deadline || !keep_going()
+        })?;
+        Ok(settled)
+    }
+
+    fn serve<F: FnMut(&Activity) -> bool>(&mut self, mut done: F) -> Result<(), String> {
+        let stop = self.event_loop.stop_handle();
+        let display_fd = self.display.get_poll_fd();
+        let display = &mut self.display;
+        let outputs = &mut self.outputs;
+        let windows = &self.windows;
+        let activity = &self.activity;
+        let background = self.background;
+        let started = self.started;
+        let mut last_frame: Option<Instant> = None;
+
+        self.event_loop.run(&[display_fd], |ready| {
+            if ready[0] {
+                display
+                    .dispatch(Duration::ZERO, &mut ())
+                    .map_err(|e| format!("Failed to dispatch Wayland clients: {}", e))?;
+            }
+
+            let due = last_frame.map(|t| t.elapsed() >= FRAME_INTERVAL).unwrap_or(true);
+            if due && activity.borrow().dirty {
+                activity.borrow_mut().dirty = false;
+                last_frame = Some(Instant::now());
+                compose(outputs, &windows.borrow(), background, started.elapsed().as_millis() as u32);
+            }
+            display.flush_clients(&mut ());
+
+            if done(&activity.borrow()) {
+                stop.stop();
+            }
+            Ok(())
+        })
+    }
+
+    /// Compose every output now instead of waiting for the next frame
+    pub fn render(&mut self) {
+        self.activity.borrow_mut().dirty = false;
+        let time = self.started.elapsed().as_millis() as u32;
+        compose(&mut self.outputs, &self.windows.borrow(), self.background, time);
+        self.display.flush_clients(&mut ());
+    }
+
+    /// Last frame of the output called `name`
+    pub fn capture(&self, name: &str) -> Option<&Image> {
+        self.outputs.iter().find(|o| o.name == name).map(|o| &o.frame)
+    }
+
+    pub fn capture_png(&self, name: &str, path: &Path) -> Result<(), String> {
+        self.capture(name)
+            .ok_or_else(|| format!("No output called {}", name))?
+            .save_png(path)
+    }
+}
+
+/// Keep a CPU copy of every new buffer so the client can reuse it right away
+fn on_commit(surface: &WlSurface, activity: &RefCell<Activity>) {
+    let changed = with_states(surface, |states| {
+        states.data_map.insert_if_missing(|| RefCell::new(None::<Image>));
+        let slot = match states.data_map.get::<RefCell<Option<Image>>>() {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let buffer = states.cached_state.current::<SurfaceAttributes>().buffer.take();
+        match buffer {
+            Some(BufferAssignment::NewBuffer { buffer, .. }) => {
+                let image = with_buffer_contents(&buffer, |data, info| {
+                    let format = match info.format {
+                        wl_shm::Format::Argb8888 => PixelFormat::Argb8888,
+                        wl_shm::Format::Xrgb8888 => PixelFormat::Xrgb8888,
+                        other => return Err(format!("Unsupported buffer format {:?}", other)),
+                    };
+                    Image::from_shm(
+                        data,
+                        info.offset as usize,
+                        info.width as u32,
+                        info.height as u32,
+                        info.stride as usize,
+                        format,
+                    )
+                })
+                .map_err(|e| format!("Unreadable buffer: {:?}", e))
+                .and_then(|image| image);
+                buffer.release();
+                match image {
+                    Ok(image) => {
+                        *slot.borrow_mut() = Some(image);
+                        true
+                    }
+                    Err(e) => {
+                        eprintln!("[HyprDM] {}", e);
+                        false
+                    }
+                }
+            }
+            Some(BufferAssignment::Removed) => {
+                *slot.borrow_mut() = None;
+                true
+            }
+            None => false,
+        }
+    })
+    .unwrap_or(false);
+
+    let mut activity = activity.borrow_mut();
+    // Frame callbacks are answered on the next frame even without new content
+    activity.dirty = true;
+    if changed {
+        activity.drawn = true;
+        activity.last_commit = Some(Instant::now());
+    }
+}
+
+/// Draw each window's surface tree onto its output, bottom to top, and answer the
+/// frame callbacks of everything drawn
+fn compose(outputs: &mut [VirtualOutput], windows: &[Window], background: u32, time: u32) {
+    for output in outputs.iter_mut() {
+        output.frame.fill(background);
+    }
+
+    for window in windows.iter().filter(|w| w.toplevel.alive()) {
+        let surface = match window.toplevel.get_surface() {
+            Some(surface) => surface,
+            None => continue,
+        };
+        let frame = match outputs.get_mut(window.output) {
+            Some(output) => &mut output.frame,
+            None => continue,
+        };
+
+        let origin: Point<i32, Logical> = Point::from((0, 0));
+        with_surface_tree_upward(
+            surface,
+            origin,
+            |_, states, location| {
+                TraversalAction::DoChildren(*location + subsurface_offset(states))
+            },
+            |_, states, location| {
+                let location = *location + subsurface_offset(states);
+                if let Some(slot) = states.data_map.get::<RefCell<Option<Image>>>() {
+                    if let Some(image) = slot.borrow().as_ref() {
+                        frame.draw(image, location.x, location.y);
+                    }
+                }
+                for callback in states.cached_state.current::<SurfaceAttributes>().frame_callbacks.drain(..) {
+                    callback.done(time);
+                }
+            },
+            |_, _, _| true,
+        );
+    }
+}
+
+fn subsurface_offset(states: &smithay::wayland::compositor::SurfaceData) -> Point<i32, Logical> {
+    if states.role == Some("subsurface") {
+        states.cached_state.current::<SubsurfaceCachedState>().location
+    } else {
+        Point::from((0, 0))
+    }
+}
+
+/// Run `ui` in a headless compositor with one output per size and save what each output
+/// shows once the interface stops drawing. One output writes `out`, more write
+/// `<stem>-<output>.png` next to it.
+pub fn preview_interface(
+    quickshell: &str,
+    ui: &Path,
+    sizes: &[(u32, u32)],
+    out: &Path,
+    timeout: Duration,
+) -> Result<Vec<PathBuf>, String> {
+    let names: Vec<String> = (1..=sizes.len()).map(|i| format!("HEADLESS-{}", i)).collect();
+    let outputs: Vec<(&str, u32, u32)> =
+        names.iter().zip(sizes).map(|(name, (w, h))| (name.as_str(), *w, *h)).collect();
+    let mut compositor = HeadlessCompositor::new(&outputs)?;
+    compositor.event_loop.handle_signals = true;
+    let socket = compositor.add_socket()?;
+    println!("[HyprDM] Previewing {:?} on {:?}", ui, socket);
+
+    let mut child = Command::new(quickshell)
+        .arg("-p")
+        .arg(ui)
+        .env("WAYLAND_DISPLAY", &socket)
+        .env("QT_QPA_PLATFORM", "wayland")
+        .env("HYPRDM_GREETER", "1")
+        .env("HYPRDM_PREVIEW", "1")
+        .env_remove("DISPLAY")
+        .current_dir(ui.parent().unwrap_or(Path::new("/")))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", quickshell, e))?;
+
+    let result = compositor.run_until_idle(Duration::from_secs(1), timeout, || matches!(child.try_wait(), Ok(None)));
+
+    // The whole group, quickshell may have started helpers
+    unsafe { libc::kill(-(child.id() as i32), libc::SIGTERM) };
+    let _ = child.wait();
+
+    if !result? {
+        eprintln!("[HyprDM] The interface did not settle, saving the last frame");
+    }
+    if !compositor.activity.borrow().drawn {
+        return Err(format!("{:?} never drew anything", ui));
+    }
+
+    let mut written = Vec::new();
+    for output in &compositor.outputs {
+        let path = if compositor.outputs.len() == 1 {
+            out.to_path_buf()
+        } else {
+            let stem = out.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "preview".into());
+            out.with_file_name(format!("{}-{}.png", stem, output.name))
+        };
+        output.frame.save_png(&path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_are_laid_out_left_to_right() {
+        let mut compositor = HeadlessCompositor::new(&[("A", 640, 480), ("B", 320, 200)]).unwrap();
+        let placed: Vec<_> = compositor.outputs.iter().map(|o| (o.name.as_str(), o.location)).collect();
+        assert_eq!(placed, [("A", (0, 0)), ("B", (640, 0))]);
+
+        compositor.render();
+        let frame = compositor.capture("B").unwrap();
+        assert_eq!((frame.width, frame.height), (320, 200));
+        assert_eq!(frame.pixel(319, 199), Some(DEFAULT_BACKGROUND));
+        assert!(compositor.capture("C").is_none());
+    }
+
+    #[test]
+    fn monitor_rules_become_outputs() {
+        let rules: Vec<MonitorRule> = ["DP-1, 1280x720, 0x0, 1, transform, 1", "eDP-1, disable", ", preferred, auto, 1"]
+            .iter()
+            .map(|r| MonitorRule::parse(r).unwrap())
+            .collect();
+        let compositor = HeadlessCompositor::from_monitor_rules(&rules).unwrap();
+        let placed: Vec<_> = compositor
+            .outputs
+            .iter()
+            .map(|o| (o.name.as_str(), o.frame.width, o.frame.height, o.location))
+            .collect();
+        assert_eq!(placed, [("DP-1", 720, 1280, (0, 0)), ("HEADLESS-2", 1920, 1080, (720, 0))]);
+    }
+
+    #[test]
+    fn impossible_outputs_are_refused() {
+        assert!(HeadlessCompositor::new(&[]).is_err());
+        assert!(HeadlessCompositor::new(&[("huge", 100_000, 100_000)]).is_err());
+    }
+}
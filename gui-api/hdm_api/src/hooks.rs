@@ -0,0 +1,165 @@
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use config::HDMConfig;
+use crate::process_group;
+
+/// Environment passed to every hook script:
+/// `USER`, `HYPRDM_SESSION` (session desktop ID or name), `HYPRDM_SESSION_NAME`,
+/// `XDG_VTNR` (when the session has a VT) and `HYPRDM_HOOK` (the hook's name)
+#[derive(Debug, Clone, Default)]
+pub struct HookEnv {
+    pub user: String,
+    pub session_id: String,
+    pub session_name: String,
+    pub vt: Option<u32>,
+}
+
+/// Admin provided scripts run around the greeter and the session
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub session_wrapper: Option<String>,
+    pub greeter_setup: Option<String>,
+    pub session_setup: Option<String>,
+    pub session_cleanup: Option<String>,
+    pub timeout: Duration,
+}
+
+impl Hooks {
+    pub fn from_config(config: &HDMConfig) -> Self {
+        Self {
+            session_wrapper: config.session_wrapper.clone(),
+            greeter_setup: config.greeter_setup_script.clone(),
+            session_setup: config.session_setup_script.clone(),
+            session_cleanup: config.session_cleanup_script.clone(),
+            timeout: Duration::from_secs(config.hook_timeout_secs),
+        }
+    }
+
+    /// Runs before the greeter is shown
+    pub fn run_greeter_setup(&self, env: &HookEnv) -> Result<(), String> {
+        run_hook("greeter_setup", self.greeter_setup.as_deref(), env, self.timeout)
+    }
+
+    /// Runs as root before the session starts, a failure aborts the login
+    pub fn run_session_setup(&self, env: &HookEnv) -> Result<(), String> {
+        run_hook("session_setup", self.session_setup.as_deref(), env, self.timeout)
+    }
+
+    /// Runs as root after the session has stopped
+    pub fn run_session_cleanup(&self, env: &HookEnv) -> Result<(), String> {
+        run_hook("session_cleanup", self.session_cleanup.as_deref(), env, self.timeout)
+    }
+}
+
+/// Run `script` with the hook environment; a non-zero exit or a timeout is an error
+pub fn run_hook(name: &str, script: Option<&str>, env: &HookEnv, timeout: Duration) -> Result<(), String> {
+    let script = match script {
+        Some(s) if !s.trim().is_empty() => s,
+        _ => return Ok(()),
+    };
+
+    let mut cmd = Command::new(script);
+    cmd.env("USER", &env.user)
+        .env("HYPRDM_SESSION", &env.session_id)
+        .env("HYPRDM_SESSION_NAME", &env.session_name)
+        .env("HYPRDM_HOOK", name)
+        .stdin(Stdio::null())
+        // Own process group, so a timeout also kills whatever the script started
+        .process_group(0);
+    if let Some(vt) = env.vt {
+        cmd.env("XDG_VTNR", vt.to_string());
+    }
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("Failed to run {} hook {}: {}", name, script, e))?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => {
+                return Err(format!("{} hook {} failed with {}", name, script, status));
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = process_group::signal(child.id(), libc::SIGKILL);
+                let _ = child.wait();
+                return Err(format!("{} hook {} timed out after {:?}", name, script, timeout));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(format!("Failed to wait for {} hook: {}", name, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(dir: &TempDir, name: &str, body: &str) -> String {
+        let path = dir.write(name, &format!("#!/bin/sh\n{}\n", body));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn env() -> HookEnv {
+        HookEnv {
+            user: "alice".into(),
+            session_id: "hyprland".into(),
+            session_name: "Hyprland".into(),
+            vt: Some(2),
+        }
+    }
+
+    #[test]
+    fn hooks_get_the_session_environment() {
+        let dir = TempDir::new("hooks-env");
+        let out = dir.join("env");
+        let hook = script(&dir, "setup", &format!(
+            "echo \"$USER $HYPRDM_SESSION $HYPRDM_SESSION_NAME $XDG_VTNR $HYPRDM_HOOK\" > {}",
+            out.display()
+        ));
+        run_hook("session_setup", Some(&hook), &env(), Duration::from_secs(10)).unwrap();
+        assert_eq!(fs::read_to_string(out).unwrap(), "alice hyprland Hyprland 2 session_setup\n");
+    }
+
+    #[test]
+    fn exit_status_decides_and_missing_hooks_pass() {
+        let dir = TempDir::new("hooks-status");
+        let fail = script(&dir, "fail", "exit 1");
+        let err = run_hook("session_setup", Some(&fail), &env(), Duration::from_secs(10)).unwrap_err();
+        assert!(err.contains("failed"), "{}", err);
+
+        assert_eq!(run_hook("session_setup", None, &env(), Duration::ZERO), Ok(()));
+        assert_eq!(run_hook("session_setup", Some("  "), &env(), Duration::ZERO), Ok(()));
+        assert!(run_hook("x", Some("/nonexistent/hook"), &env(), Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn timeout_kills_everything_the_hook_started() {
+        let dir = TempDir::new("hooks-timeout");
+        let pid_file = dir.join("pid");
+        let hook = script(&dir, "slow", &format!("sleep 30 & echo $! > {}; wait", pid_file.display()));
+
+        let started = Instant::now();
+        let err = run_hook("session_setup", Some(&hook), &env(), Duration::from_millis(500)).unwrap_err();
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let pid = fs::read_to_string(pid_file).unwrap().trim().to_string();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // The orphaned sleep is reparented and reaped, or left as a zombie at most
+        while fs::read_to_string(format!("/proc/{}/stat", pid))
+            .map(|stat| !stat.contains(") Z "))
+            .unwrap_or(false)
+        {
+            assert!(Instant::now() < deadline, "hook child {} survived the timeout", pid);
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
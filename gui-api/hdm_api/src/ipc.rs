@@ -1,44 +1,282 @@
-use std::process::Command;
+use std::env;
+use std::io::{ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-pub struct HyprlandIPC;
+use serde::de::DeserializeOwned;
+
+pub mod commands;
+pub mod events;
+pub mod instances;
+pub mod queries;
+#[cfg(test)]
+pub(crate) mod standin;
+
+pub use commands::{BatchReport, CommandBatch, HyprCommand};
+pub use events::{EventSubscriber, HyprlandEvent};
+pub use instances::{hypr_roots, list_instances, socket_owner, HyprlandInstance};
+pub use queries::{Client, Devices, Layers, Monitor, Version, Workspace};
+
+/// Request socket of a Hyprland instance, relative to its instance directory
+pub const REQUEST_SOCKET: &str = ".socket.sock";
+
+/// Event socket of a Hyprland instance, relative to its instance directory
+pub const EVENT_SOCKET: &str = ".socket2.sock";
+
+/// Talks to Hyprland over its request socket, the same one `hyprctl` uses
+#[derive(Debug, Clone)]
+pub struct HyprlandIPC {
+    /// Fixed socket; None resolves it from HYPRLAND_INSTANCE_SIGNATURE on every request
+    socket: Option<PathBuf>,
+    timeout: Duration,
+}
+
+impl Default for HyprlandIPC {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Directory of the instance `signature`: `$XDG_RUNTIME_DIR/hypr`, then other users'
+/// `/run/user/<uid>/hypr` (readable as root), then the pre-0.40 `/tmp/hypr` (not as root).
+/// Only a request socket owned by the owner of its directory counts
+pub fn instance_dir(signature: &str) -> Option<PathBuf> {
+    instance_dir_in(&hypr_roots(), signature)
+}
+
+fn instance_dir_in(roots: &[PathBuf], signature: &str) -> Option<PathBuf> {
+    if signature.is_empty() || signature.contains('/') || signature == "." || signature == ".." {
+        return None;
+    }
+    roots
+        .iter()
+        .map(|root| root.join(signature))
+        .find(|dir| socket_owner(dir, REQUEST_SOCKET).is_some())
+}
+
+/// `name` inside the directory of the instance named by HYPRLAND_INSTANCE_SIGNATURE
+pub fn instance_socket(name: &str) -> Result<PathBuf, String> {
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .map_err(|_| "HYPRLAND_INSTANCE_SIGNATURE is not set, is Hyprland running?".to_string())?;
+    instance_dir(&signature)
+        .filter(|dir| socket_owner(dir, name).is_some())
+        .map(|dir| dir.join(name))
+        .ok_or_else(|| format!("No Hyprland socket found for instance {}", signature))
+}
 
 impl HyprlandIPC {
-    /// Sends a Hyprland IPC command
-    pub fn send_command(&self, cmd: &str) -> Result<(), String> {
-        let output = Command::new("hyprctl")
-            .arg(cmd)
-            .output()
-            .map_err(|e| format!("IPC command failed: {}", e))?;
-
-        if !output.status.success() {
-            return Err(format!(
-                "IPC command error: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+    pub fn new() -> Self {
+        Self {
+            socket: None,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Use `path` instead of looking up the running instance, e.g. a stand-in server
+    pub fn with_socket(path: &Path) -> Self {
+        Self {
+            socket: Some(path.to_path_buf()),
+            ..Self::new()
         }
+    }
 
-        Ok(())
+    /// Talk to the instance with this HYPRLAND_INSTANCE_SIGNATURE
+    pub fn from_instance(signature: &str) -> Result<Self, String> {
+        instance_dir(signature)
+            .map(|dir| Self::from_instance_dir(&dir))
+            .ok_or_else(|| format!("No Hyprland socket found for instance {}", signature))
     }
 
-    /// Returns the active window status
-    pub fn get_status(&self) -> Result<String, String> {
-        let output = Command::new("hyprctl")
-            .arg("activewindow")
-            .output()
-            .map_err(|e| format!("Failed to get IPC status: {}", e))?;
-
-        if !output.status.success() {
-            return Err(format!(
-                "IPC status error: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+    /// Talk to the instance living in `dir`, e.g. `/run/user/1000/hypr/<signature>`
+    pub fn from_instance_dir(dir: &Path) -> Self {
+        Self::with_socket(&dir.join(REQUEST_SOCKET))
+    }
+
+    /// Read/write timeout for a single request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn socket_path(&self) -> Result<PathBuf, String> {
+        match &self.socket {
+            Some(path) => Ok(path.clone()),
+            None => instance_socket(REQUEST_SOCKET),
         }
+    }
+
+    /// Send one raw request (e.g. `dispatch workspace 2`, `j/monitors`) and return the reply
+    pub fn request(&self, request: &str) -> Result<String, String> {
+        let path = self.socket_path()?;
+        let mut stream = UnixStream::connect(&path)
+            .map_err(|e| format!("Failed to connect to {:?}: {}", path, e))?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(|e| e.to_string())?;
+
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| self.io_error("send IPC request", e))?;
+        // Hyprland answers after it has read the request and closes the connection
+        let _ = stream.shutdown(Shutdown::Write);
 
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        let mut reply = Vec::new();
+        stream
+            .read_to_end(&mut reply)
+            .map_err(|e| self.io_error("read IPC reply", e))?;
+        Ok(String::from_utf8_lossy(&reply).to_string())
+    }
+
+    fn io_error(&self, what: &str, e: std::io::Error) -> String {
+        match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                format!("Failed to {}: timed out after {:?}", what, self.timeout)
+            }
+            _ => format!("Failed to {}: {}", what, e),
+        }
+    }
+
+    /// Sends a raw request line as is; prefer `execute` with a `HyprCommand`
+    pub fn send_command(&self, cmd: &str) -> Result<(), String> {
+        let reply = self.request(cmd)?;
+        check_reply(&reply).map(|_| ())
+    }
+
+    /// Hyprland's plain text `activewindow` reply, as `hyprctl activewindow` prints it
+    pub fn get_status(&self) -> Result<String, String> {
+        self.request("activewindow")
     }
 
     /// Updates and returns the active window (alias for get_status)
     pub fn update_active_window(&self) -> Result<String, String> {
         self.get_status()
     }
+
+    /// Send `j/<what>` and deserialize the JSON reply
+    pub fn query<T: DeserializeOwned>(&self, what: &str) -> Result<T, String> {
+        let reply = self.request(&format!("j/{}", what))?;
+        let reply = check_reply(&reply)?;
+        serde_json::from_str(&reply).map_err(|e| format!("Invalid {} reply: {}", what, e))
+    }
+
+    pub fn monitors(&self) -> Result<Vec<Monitor>, String> {
+        self.query("monitors")
+    }
+
+    pub fn workspaces(&self) -> Result<Vec<Workspace>, String> {
+        self.query("workspaces")
+    }
+
+    pub fn active_workspace(&self) -> Result<Workspace, String> {
+        self.query("activeworkspace")
+    }
+
+    pub fn clients(&self) -> Result<Vec<Client>, String> {
+        self.query("clients")
+    }
+
+    /// None when no window has focus (Hyprland replies with `{}`)
+    pub fn active_window(&self) -> Result<Option<Client>, String> {
+        let client: Client = self.query("activewindow")?;
+        Ok(if client.address.is_empty() { None } else { Some(client) })
+    }
+
+    pub fn layers(&self) -> Result<Layers, String> {
+        self.query("layers")
+    }
+
+    pub fn devices(&self) -> Result<Devices, String> {
+        self.query("devices")
+    }
+
+    pub fn version(&self) -> Result<Version, String> {
+        self.query("version")
+    }
+}
+
+/// Hyprland reports failures as plain text instead of "ok" or data
+fn check_reply(reply: &str) -> Result<String, String> {
+    let trimmed = reply.trim();
+    let lower = trimmed.to_lowercase();
+    if lower.starts_with("unknown request")
+        || lower.starts_with("invalid")
+        || lower.starts_with("error")
+        || lower.starts_with("couldn't")
+    {
+        return Err(format!("IPC command error: {}", trimmed));
+    }
+    Ok(reply.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::standin::StandIn;
+    use super::*;
+
+    #[test]
+    fn get_status_returns_the_raw_reply() {
+        let standin = StandIn::recorded("ipc-status");
+        let status = standin.ipc().get_status().unwrap();
+        assert!(status.starts_with("Window 55d4e5b0e1a0 -> ~/src:\n\tmapped: 1"), "{}", status);
+        assert_eq!(standin.ipc().update_active_window().unwrap(), status);
+
+        // Whatever Hyprland answers is handed back, error text included
+        let invalid = StandIn::start("ipc-status-invalid", &[("activewindow", "Invalid")]);
+        assert_eq!(invalid.ipc().get_status(), Ok("Invalid".to_string()));
+    }
+
+    #[test]
+    fn commands_report_hyprland_errors() {
+        let standin = StandIn::recorded("ipc-command");
+        let ipc = standin.ipc();
+        assert_eq!(ipc.send_command("dispatch workspace 2"), Ok(()));
+        let err = ipc.send_command("dispatch nosuchdispatcher").unwrap_err();
+        assert!(err.contains("Invalid dispatcher"), "{}", err);
+        assert_eq!(standin.requests(), ["dispatch workspace 2", "dispatch nosuchdispatcher"]);
+    }
+
+    #[test]
+    fn a_missing_socket_or_silent_server_is_an_error() {
+        let gone = HyprlandIPC::with_socket(Path::new("/nonexistent/.socket.sock"));
+        assert!(gone.request("j/version").unwrap_err().contains("Failed to connect"));
+
+        // Accepts but never answers
+        let dir = crate::test_util::TempDir::new("ipc-silent");
+        let listener = std::os::unix::net::UnixListener::bind(dir.join(REQUEST_SOCKET)).unwrap();
+        let ipc = HyprlandIPC::from_instance_dir(dir.path()).with_timeout(Duration::from_millis(200));
+        let err = ipc.request("j/version").unwrap_err();
+        assert!(err.contains("timed out"), "{}", err);
+        drop(listener);
+    }
+
+    #[test]
+    fn instance_dir_skips_untrusted_sockets() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, chown needs root");
+            return;
+        }
+        let (planted, own) = (crate::test_util::TempDir::new("ipc-planted"), crate::test_util::TempDir::new("ipc-own"));
+        let bind = |root: &crate::test_util::TempDir| {
+            std::fs::create_dir_all(root.join("sig")).unwrap();
+            std::os::unix::net::UnixListener::bind(root.join("sig").join(REQUEST_SOCKET)).unwrap()
+        };
+        let (_a, _b) = (bind(&planted), bind(&own));
+        std::os::unix::fs::lchown(planted.join("sig").join(REQUEST_SOCKET), Some(65534), None).unwrap();
+
+        let roots = [planted.path().to_path_buf(), own.path().to_path_buf()];
+        assert_eq!(instance_dir_in(&roots, "sig"), Some(own.join("sig")));
+        assert_eq!(instance_dir_in(&roots, "missing"), None);
+        assert_eq!(instance_dir_in(&roots, "../ipc-own/sig"), None);
+        assert_eq!(instance_dir_in(&roots, ""), None);
+    }
+
+    #[test]
+    fn check_reply_recognizes_error_text() {
+        for reply in ["unknown request", "Invalid dispatcher", "error: nope", "Couldn't find monitor"] {
+            assert!(check_reply(reply).is_err(), "{}", reply);
+        }
+        assert_eq!(check_reply("ok"), Ok("ok".to_string()));
+        assert_eq!(check_reply("{\"invalid\": false}"), Ok("{\"invalid\": false}".to_string()));
+    }
 }
@@ -0,0 +1,308 @@
+use super::{check_reply, HyprlandIPC};
+
+/// A request that changes Hyprland's state, see `HyprlandIPC::execute`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HyprCommand {
+    /// `dispatch <dispatcher> <args>`, e.g. `workspace 2` or `exec kitty`
+    Dispatch { dispatcher: String, args: String },
+    /// `keyword <key> <value>`, e.g. `general:gaps_in 5`
+    Keyword { key: String, value: String },
+    SetCursor { theme: String, size: u32 },
+    Reload,
+    /// `notify <icon> <time ms> <color> <message>`; icon -1 for none, color "0" for the default
+    Notify { icon: i32, timeout_ms: u32, color: String, message: String },
+}
+
+impl HyprCommand {
+    pub fn dispatch(dispatcher: &str, args: &str) -> Self {
+        HyprCommand::Dispatch { dispatcher: dispatcher.to_string(), args: args.to_string() }
+    }
+
+    pub fn keyword(key: &str, value: &str) -> Self {
+        HyprCommand::Keyword { key: key.to_string(), value: value.to_string() }
+    }
+
+    pub fn set_cursor(theme: &str, size: u32) -> Self {
+        HyprCommand::SetCursor { theme: theme.to_string(), size }
+    }
+
+    pub fn notify(icon: i32, timeout_ms: u32, color: &str, message: &str) -> Self {
+        HyprCommand::Notify {
+            icon,
+            timeout_ms,
+            color: color.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// The request line. Hyprland has no quoting: arguments go through as they are,
+    /// spaces and commas included, only line breaks are turned into spaces
+    pub fn to_request(&self) -> Result<String, String> {
+        Ok(match self {
+            HyprCommand::Dispatch { dispatcher, args } => {
+                single_word("dispatcher", dispatcher)?;
+                join(&["dispatch", dispatcher.trim(), &escape(args)])
+            }
+            HyprCommand::Keyword { key, value } => {
+                single_word("keyword", key)?;
+                join(&["keyword", key.trim(), &escape(value)])
+            }
+            HyprCommand::SetCursor { theme, size } => {
+                let theme = escape(theme);
+                if theme.is_empty() {
+                    return Err("Cursor theme is empty".into());
+                }
+                // Hyprland takes the last word as the size, so theme names may contain spaces
+                format!("setcursor {} {}", theme, size)
+            }
+            HyprCommand::Reload => "reload".to_string(),
+            HyprCommand::Notify { icon, timeout_ms, color, message } => {
+                let color = if color.trim().is_empty() { "0" } else { color.trim() };
+                single_word("notify color", color)?;
+                format!("notify {} {} {} {}", icon, timeout_ms, color, escape(message))
+            }
+        })
+    }
+}
+
+/// Trimmed, with line breaks and other control characters as spaces
+fn escape(arg: &str) -> String {
+    arg.trim().chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+fn single_word(what: &str, value: &str) -> Result<(), String> {
+    let value = value.trim();
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return Err(format!("Invalid {}: {:?}", what, value));
+    }
+    Ok(())
+}
+
+fn join(parts: &[&str]) -> String {
+    parts.iter().filter(|p| !p.is_empty()).cloned().collect::<Vec<_>>().join(" ")
+}
+
+/// Outcome of every command in a batch, in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchReport {
+    pub results: Vec<(HyprCommand, Result<(), String>)>,
+}
+
+impl BatchReport {
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(|(_, r)| r.is_ok())
+    }
+
+    /// Failed commands with their error
+    pub fn errors(&self) -> Vec<(&HyprCommand, &str)> {
+        self.results
+            .iter()
+            .filter_map(|(c, r)| r.as_ref().err().map(|e| (c, e.as_str())))
+            .collect()
+    }
+}
+
+/// Collects commands and sends them as one `[[BATCH]]` request
+#[derive(Debug, Clone, Default)]
+pub struct CommandBatch {
+    commands: Vec<HyprCommand>,
+}
+
+impl CommandBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(mut self, command: HyprCommand) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    pub fn dispatch(self, dispatcher: &str, args: &str) -> Self {
+        self.push(HyprCommand::dispatch(dispatcher, args))
+    }
+
+    pub fn keyword(self, key: &str, value: &str) -> Self {
+        self.push(HyprCommand::keyword(key, value))
+    }
+
+    pub fn set_cursor(self, theme: &str, size: u32) -> Self {
+        self.push(HyprCommand::set_cursor(theme, size))
+    }
+
+    pub fn reload(self) -> Self {
+        self.push(HyprCommand::Reload)
+    }
+
+    pub fn notify(self, icon: i32, timeout_ms: u32, color: &str, message: &str) -> Self {
+        self.push(HyprCommand::notify(icon, timeout_ms, color, message))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn send(self, ipc: &HyprlandIPC) -> Result<BatchReport, String> {
+        ipc.execute_batch(&self.commands)
+    }
+}
+
+impl HyprlandIPC {
+    /// Send one command, Hyprland's error text becomes the Err
+    pub fn execute(&self, command: &HyprCommand) -> Result<(), String> {
+        let reply = self.request(&command.to_request()?)?;
+        command_result(&reply)
+    }
+
+    /// Send the commands as `[[BATCH]]` requests; Err only when nothing could be sent.
+    /// Hyprland splits a batch at every `;`, so a command containing one goes out on its own
+    pub fn execute_batch(&self, commands: &[HyprCommand]) -> Result<BatchReport, String> {
+        let lines = commands
+            .iter()
+            .map(HyprCommand::to_request)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut results = Vec::with_capacity(commands.len());
+        let mut start = 0;
+        while start < lines.len() {
+            let end = if lines[start].contains(';') {
+                start + 1
+            } else {
+                start + lines[start..].iter().take_while(|l| !l.contains(';')).count()
+            };
+
+            let sent = if lines[start].contains(';') {
+                self.request(&lines[start])
+            } else {
+                self.request(&format!("[[BATCH]]{}", lines[start..end].join(";")))
+            };
+            let outcomes: Vec<Result<(), String>> = match sent {
+                Ok(reply) => split_batch_reply(&reply, end - start).iter().map(|r| command_result(r)).collect(),
+                Err(e) if start == 0 => return Err(e),
+                // Earlier requests already ran, keep their outcome
+                Err(e) => vec![Err(e); end - start],
+            };
+            results.extend(commands[start..end].iter().cloned().zip(outcomes));
+            start = end;
+        }
+
+        Ok(BatchReport { results })
+    }
+}
+
+/// Commands answer "ok" (or nothing); anything else is Hyprland's error message
+fn command_result(reply: &str) -> Result<(), String> {
+    check_reply(reply)?;
+    match reply.trim() {
+        "" | "ok" => Ok(()),
+        other => Err(format!("IPC command error: {}", other)),
+    }
+}
+
+/// Newer Hyprland separates batch replies with "\n\n\n", older releases just concatenate them
+fn split_batch_reply(reply: &str, count: usize) -> Vec<String> {
+    let mut parts: Vec<String> = if reply.contains("\n\n\n") {
+        reply.split("\n\n\n").map(str::to_string).collect()
+    } else {
+        let mut parts = Vec::new();
+        let mut rest = reply;
+        while parts.len() + 1 < count && rest.starts_with("ok") {
+            parts.push("ok".to_string());
+            rest = &rest[2..];
+        }
+        // A lone command may answer nothing, a short reply must not pass for one
+        if parts.is_empty() || !rest.is_empty() {
+            parts.push(rest.to_string());
+        }
+        parts
+    };
+
+    // A trailing delimiter leaves an empty last part
+    if parts.len() > count && parts.last().map(|p| p.trim().is_empty()).unwrap_or(false) {
+        parts.pop();
+    }
+    // Without a reply of its own a command's outcome is unknown
+    parts.resize(count, "no reply for this command in the batch".to_string());
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::standin::StandIn;
+
+    #[test]
+    fn arguments_pass_through_with_spaces_and_commas() {
+        let request = |c: HyprCommand| c.to_request().unwrap();
+        assert_eq!(request(HyprCommand::dispatch("exec", " kitty --title 'my term' ")), "dispatch exec kitty --title 'my term'");
+        assert_eq!(request(HyprCommand::dispatch("movetoworkspace", "2,address:0x1")), "dispatch movetoworkspace 2,address:0x1");
+        assert_eq!(request(HyprCommand::keyword("bind", "SUPER,Q,exec,kitty")), "keyword bind SUPER,Q,exec,kitty");
+        assert_eq!(request(HyprCommand::set_cursor("Bibata Modern Ice", 24)), "setcursor Bibata Modern Ice 24");
+        assert_eq!(request(HyprCommand::notify(-1, 3000, "", "a, b; c")), "notify -1 3000 0 a, b; c");
+        assert_eq!(request(HyprCommand::dispatch("exec", "sh -c 'a; b'")), "dispatch exec sh -c 'a; b'");
+    }
+
+    #[test]
+    fn line_breaks_become_spaces() {
+        let request = HyprCommand::notify(1, 1000, "rgb(ff0000)", "line one\nline two\r\n").to_request();
+        assert_eq!(request, Ok("notify 1 1000 rgb(ff0000) line one line two".to_string()));
+        assert_eq!(
+            HyprCommand::dispatch("exec", "kitty\nreload").to_request(),
+            Ok("dispatch exec kitty reload".to_string())
+        );
+    }
+
+    #[test]
+    fn names_must_be_single_words() {
+        assert!(HyprCommand::dispatch("", "2").to_request().is_err());
+        assert!(HyprCommand::dispatch("work space", "2").to_request().is_err());
+        assert!(HyprCommand::keyword("general:gaps_in\nreload", "5").to_request().is_err());
+        assert!(HyprCommand::notify(0, 1, "red green", "x").to_request().is_err());
+        assert!(HyprCommand::set_cursor(" ", 24).to_request().is_err());
+    }
+
+    #[test]
+    fn batches_send_commands_with_semicolons_on_their_own() {
+        let standin = StandIn::start(
+            "commands-batch",
+            &[
+                ("[[BATCH]]dispatch workspace 2;keyword general:gaps_in 5", "ok\n\n\nok"),
+                ("dispatch exec sh -c 'a; b'", "ok"),
+                ("[[BATCH]]dispatch nosuchdispatcher", "Invalid dispatcher"),
+            ],
+        );
+        let report = CommandBatch::new()
+            .dispatch("workspace", "2")
+            .keyword("general:gaps_in", "5")
+            .dispatch("exec", "sh -c 'a; b'")
+            .dispatch("nosuchdispatcher", "")
+            .send(&standin.ipc())
+            .unwrap();
+
+        assert_eq!(
+            standin.requests(),
+            [
+                "[[BATCH]]dispatch workspace 2;keyword general:gaps_in 5",
+                "dispatch exec sh -c 'a; b'",
+                "[[BATCH]]dispatch nosuchdispatcher",
+            ]
+        );
+        assert_eq!(report.results.len(), 4);
+        assert_eq!(report.errors().len(), 1);
+        assert_eq!(report.errors()[0].0, &HyprCommand::dispatch("nosuchdispatcher", ""));
+    }
+
+    #[test]
+    fn batch_replies_of_old_and_new_releases() {
+        assert_eq!(split_batch_reply("ok\n\n\nok\n\n\n", 2), ["ok", "ok"]);
+        assert_eq!(split_batch_reply("okokInvalid dispatcher", 3), ["ok", "ok", "Invalid dispatcher"]);
+        assert_eq!(split_batch_reply("ok", 2), ["ok", "no reply for this command in the batch"]);
+    }
+
+    #[test]
+    fn empty_batch_and_unreachable_socket() {
+        let ipc = HyprlandIPC::with_socket(std::path::Path::new("/nonexistent/.socket.sock"));
+        assert!(CommandBatch::new().send(&ipc).unwrap().results.is_empty());
+        assert!(CommandBatch::new().reload().send(&ipc).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{instance_socket, EVENT_SOCKET};
+
+/// One line from `.socket2.sock`, `EVENT>>DATA`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HyprlandEvent {
+    ActiveWindow { class: String, title: String },
+    /// Address of the focused window, empty when nothing is focused
+    ActiveWindowV2 { address: String },
+    Workspace { name: String },
+    WorkspaceV2 { id: i32, name: String },
+    FocusedMonitor { monitor: String, workspace: String },
+    MonitorAdded { name: String },
+    MonitorRemoved { name: String },
+    OpenWindow { address: String, workspace: String, class: String, title: String },
+    CloseWindow { address: String },
+    MoveWindow { address: String, workspace: String },
+    CreateWorkspace { name: String },
+    DestroyWorkspace { name: String },
+    Submap { name: String },
+    ConfigReloaded,
+    /// Any event this enum does not model yet
+    Unknown { name: String, data: String },
+}
+
+impl HyprlandEvent {
+    /// Parse one line without the trailing newline, None if it is not `EVENT>>DATA`
+    pub fn parse(line: &str) -> Option<Self> {
+        let (name, data) = line.split_once(">>")?;
+        // Titles may contain commas, so only split as far as each event needs
+        let fields = |n: usize| -> Vec<String> {
+            let mut parts: Vec<String> = data.splitn(n, ',').map(str::to_string).collect();
+            parts.resize(n, String::new());
+            parts
+        };
+
+        let event = match name {
+            "activewindow" => {
+                let f = fields(2);
+                HyprlandEvent::ActiveWindow { class: f[0].clone(), title: f[1].clone() }
+            }
+            "activewindowv2" => HyprlandEvent::ActiveWindowV2 { address: data.to_string() },
+            "workspace" => HyprlandEvent::Workspace { name: data.to_string() },
+            "workspacev2" => {
+                let f = fields(2);
+                match f[0].parse() {
+                    Ok(id) => HyprlandEvent::WorkspaceV2 { id, name: f[1].clone() },
+                    Err(_) => return Some(Self::unknown(name, data)),
+                }
+            }
+            "focusedmon" => {
+                let f = fields(2);
+                HyprlandEvent::FocusedMonitor { monitor: f[0].clone(), workspace: f[1].clone() }
+            }
+            "monitoradded" => HyprlandEvent::MonitorAdded { name: data.to_string() },
+            "monitorremoved" => HyprlandEvent::MonitorRemoved { name: data.to_string() },
+            "openwindow" => {
+                let f = fields(4);
+                HyprlandEvent::OpenWindow {
+                    address: f[0].clone(),
+                    workspace: f[1].clone(),
+                    class: f[2].clone(),
+                    title: f[3].clone(),
+                }
+            }
+            "closewindow" => HyprlandEvent::CloseWindow { address: data.to_string() },
+            "movewindow" => {
+                let f = fields(2);
+                HyprlandEvent::MoveWindow { address: f[0].clone(), workspace: f[1].clone() }
+            }
+            "createworkspace" => HyprlandEvent::CreateWorkspace { name: data.to_string() },
+            "destroyworkspace" => HyprlandEvent::DestroyWorkspace { name: data.to_string() },
+            "submap" => HyprlandEvent::Submap { name: data.to_string() },
+            "configreloaded" => HyprlandEvent::ConfigReloaded,
+            _ => Self::unknown(name, data),
+        };
+        Some(event)
+    }
+
+    fn unknown(name: &str, data: &str) -> Self {
+        HyprlandEvent::Unknown { name: name.to_string(), data: data.to_string() }
+    }
+
+    /// Event name and data as they appear on the socket
+    pub fn raw(&self) -> (String, String) {
+        let (name, data) = match self {
+            HyprlandEvent::ActiveWindow { class, title } => ("activewindow", format!("{},{}", class, title)),
+            HyprlandEvent::ActiveWindowV2 { address } => ("activewindowv2", address.clone()),
+            HyprlandEvent::Workspace { name } => ("workspace", name.clone()),
+            HyprlandEvent::WorkspaceV2 { id, name } => ("workspacev2", format!("{},{}", id, name)),
+            HyprlandEvent::FocusedMonitor { monitor, workspace } => ("focusedmon", format!("{},{}", monitor, workspace)),
+            HyprlandEvent::MonitorAdded { name } => ("monitoradded", name.clone()),
+            HyprlandEvent::MonitorRemoved { name } => ("monitorremoved", name.clone()),
+            HyprlandEvent::OpenWindow { address, workspace, class, title } => {
+                ("openwindow", format!("{},{},{},{}", address, workspace, class, title))
+            }
+            HyprlandEvent::CloseWindow { address } => ("closewindow", address.clone()),
+            HyprlandEvent::MoveWindow { address, workspace } => ("movewindow", format!("{},{}", address, workspace)),
+            HyprlandEvent::CreateWorkspace { name } => ("createworkspace", name.clone()),
+            HyprlandEvent::DestroyWorkspace { name } => ("destroyworkspace", name.clone()),
+            HyprlandEvent::Submap { name } => ("submap", name.clone()),
+            HyprlandEvent::ConfigReloaded => ("configreloaded", String::new()),
+            HyprlandEvent::Unknown { name, data } => return (name.clone(), data.clone()),
+        };
+        (name.to_string(), data)
+    }
+}
+
+type EventCallback = Box<dyn Fn(&HyprlandEvent) + Send>;
+
+/// Reads `.socket2.sock` on a background thread, reconnecting when Hyprland restarts
+pub struct EventSubscriber {
+    /// Fixed socket; None resolves it from HYPRLAND_INSTANCE_SIGNATURE on every connect
+    socket: Option<PathBuf>,
+    pub reconnect_delay: Duration,
+    callback: Arc<Mutex<Option<EventCallback>>>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Default for EventSubscriber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventSubscriber {
+    pub fn new() -> Self {
+        Self {
+            socket: None,
+            reconnect_delay: Duration::from_secs(1),
+            callback: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(false)),
+            handle: None,
+        }
+    }
+
+    /// Use `path` instead of looking up the running instance, e.g. a stand-in server
+    pub fn with_socket(path: &Path) -> Self {
+        let mut subscriber = Self::new();
+        subscriber.socket = Some(path.to_path_buf());
+        subscriber
+    }
+
+    pub fn with_reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Also deliver every event to `callback`, e.g. an FFI function pointer
+    pub fn on_event<F: Fn(&HyprlandEvent) + Send + 'static>(&self, callback: F) {
+        if let Ok(mut cb) = self.callback.lock() {
+            *cb = Some(Box::new(callback));
+        }
+    }
+
+    /// Start reading, events are delivered on the returned channel
+    pub fn spawn(&mut self) -> Result<Receiver<HyprlandEvent>, String> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err("Event subscriber is already running".into());
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let socket = self.socket.clone();
+        let delay = self.reconnect_delay;
+        let callback = self.callback.clone();
+        let running = self.running.clone();
+
+        self.handle = Some(thread::spawn(move || {
+            let emit = |event: HyprlandEvent| -> bool {
+                if let Ok(cb) = callback.lock() {
+                    if let Some(cb) = cb.as_ref() {
+                        cb(&event);
+                    }
+                }
+                // Keep running for the callback even when nobody reads the channel
+                tx.send(event).is_ok() || callback.lock().map(|cb| cb.is_some()).unwrap_or(false)
+            };
+
+            while running.load(Ordering::SeqCst) {
+                let path = match &socket {
+                    Some(p) => Ok(p.clone()),
+                    None => instance_socket(EVENT_SOCKET),
+                };
+                match path.and_then(|p| read_events(&p, &running, &emit)) {
+                    Ok(false) => break,
+                    Ok(true) => {}
+                    Err(e) => eprintln!("[HyprDM] {}", e),
+                }
+                if running.load(Ordering::SeqCst) {
+                    thread::sleep(delay);
+                }
+            }
+            running.store(false, Ordering::SeqCst);
+        }));
+
+        Ok(rx)
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for EventSubscriber {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Read one connection until it closes; Ok(false) when nobody listens anymore
+fn read_events(path: &Path, running: &AtomicBool, emit: &dyn Fn(HyprlandEvent) -> bool) -> Result<bool, String> {
+    let stream = UnixStream::connect(path)
+        .map_err(|e| format!("Failed to connect to {:?}: {}", path, e))?;
+    // Wake up regularly so stop() does not wait for the next event
+    stream
+        .set_read_timeout(Some(Duration::from_millis(250)))
+        .map_err(|e| e.to_string())?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    while running.load(Ordering::SeqCst) {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(true),
+            Ok(_) if line.last() != Some(&b'\n') => continue,
+            Ok(_) => {}
+            // A timeout keeps the bytes read so far in `line`
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(e) => return Err(format!("Failed to read Hyprland events: {}", e)),
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        if let Some(event) = HyprlandEvent::parse(text.trim_end_matches('\n')) {
+            if !emit(event) {
+                return Ok(false);
+            }
+        }
+        line.clear();
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::standin::StandIn;
+
+    #[test]
+    fn parses_events_and_keeps_commas_in_titles() {
+        let parse = |line| HyprlandEvent::parse(line).unwrap();
+        assert_eq!(
+            parse("activewindow>>kitty,vim a.rs, b.rs"),
+            HyprlandEvent::ActiveWindow { class: "kitty".into(), title: "vim a.rs, b.rs".into() }
+        );
+        assert_eq!(
+            parse("openwindow>>55d4e5b0e1a0,2,firefox,Inbox, 3 unread"),
+            HyprlandEvent::OpenWindow {
+                address: "55d4e5b0e1a0".into(),
+                workspace: "2".into(),
+                class: "firefox".into(),
+                title: "Inbox, 3 unread".into(),
+            }
+        );
+        assert_eq!(parse("workspacev2>>3,code"), HyprlandEvent::WorkspaceV2 { id: 3, name: "code".into() });
+        assert_eq!(parse("activewindowv2>>"), HyprlandEvent::ActiveWindowV2 { address: String::new() });
+        assert_eq!(parse("configreloaded>>"), HyprlandEvent::ConfigReloaded);
+        // Missing fields are left empty instead of dropping the event
+        assert_eq!(parse("movewindow>>55d4"), HyprlandEvent::MoveWindow { address: "55d4".into(), workspace: String::new() });
+    }
+
+    #[test]
+    fn unmodelled_and_malformed_lines() {
+        assert_eq!(HyprlandEvent::parse("no separator"), None);
+        assert_eq!(
+            HyprlandEvent::parse("workspacev2>>special,scratch"),
+            Some(HyprlandEvent::Unknown { name: "workspacev2".into(), data: "special,scratch".into() })
+        );
+        assert_eq!(
+            HyprlandEvent::parse("pin>>55d4,1"),
+            Some(HyprlandEvent::Unknown { name: "pin".into(), data: "55d4,1".into() })
+        );
+    }
+
+    #[test]
+    fn raw_round_trips_through_parse() {
+        for line in [
+            "activewindow>>kitty,a, b",
+            "workspace>>1",
+            "workspacev2>>1,1",
+            "focusedmon>>DP-1,2",
+            "monitoradded>>HDMI-A-1",
+            "monitorremoved>>HDMI-A-1",
+            "openwindow>>1,2,c,t",
+            "closewindow>>1",
+            "createworkspace>>4",
+            "destroyworkspace>>4",
+            "submap>>resize",
+            "configreloaded>>",
+            "pin>>55d4,1",
+        ] {
+            let (name, data) = HyprlandEvent::parse(line).unwrap().raw();
+            assert_eq!(format!("{}>>{}", name, data), line);
+        }
+    }
+
+    #[test]
+    fn subscriber_reassembles_lines_and_reconnects() {
+        let standin = StandIn::recorded("events-reconnect");
+        let server = standin.serve_events(vec![
+            "workspace>>1\nactivewindow>>kitty,~/src\n".to_string(),
+            "monitoradded>>HDMI-A-1\nconfigreloaded>>\n".to_string(),
+        ]);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut subscriber = EventSubscriber::with_socket(&standin.dir.join(EVENT_SOCKET))
+            .with_reconnect_delay(Duration::from_millis(20));
+        let log = seen.clone();
+        subscriber.on_event(move |event| log.lock().unwrap().push(event.clone()));
+        let events = subscriber.spawn().unwrap();
+
+        let received: Vec<_> = (0..4).map(|_| events.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        assert_eq!(
+            received,
+            [
+                HyprlandEvent::Workspace { name: "1".into() },
+                HyprlandEvent::ActiveWindow { class: "kitty".into(), title: "~/src".into() },
+                HyprlandEvent::MonitorAdded { name: "HDMI-A-1".into() },
+                HyprlandEvent::ConfigReloaded,
+            ]
+        );
+        assert_eq!(*seen.lock().unwrap(), received);
+        assert!(subscriber.spawn().unwrap_err().contains("already running"));
+
+        subscriber.stop();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn stop_does_not_wait_for_the_next_event() {
+        let standin = StandIn::recorded("events-stop");
+        // Connected but silent
+        let listener = std::os::unix::net::UnixListener::bind(standin.dir.join(EVENT_SOCKET)).unwrap();
+        let mut subscriber = EventSubscriber::with_socket(&standin.dir.join(EVENT_SOCKET));
+        let _events = subscriber.spawn().unwrap();
+        let _client = listener.accept().unwrap();
+
+        let started = std::time::Instant::now();
+        subscriber.stop();
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
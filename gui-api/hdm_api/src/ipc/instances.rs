@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{EventSubscriber, HyprlandIPC, EVENT_SOCKET, REQUEST_SOCKET};
+use crate::session_policy::user_name;
+
+/// A running Hyprland found in one of the runtime directories
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyprlandInstance {
+    /// HYPRLAND_INSTANCE_SIGNATURE of the instance
+    pub signature: String,
+    pub dir: PathBuf,
+    /// From `hyprland.lock`
+    pub pid: Option<u32>,
+    /// Owner of the request socket
+    pub uid: u32,
+    pub user: Option<String>,
+    /// WAYLAND_DISPLAY of the instance, from `hyprland.lock`
+    pub wayland_display: Option<String>,
+}
+
+impl HyprlandInstance {
+    pub fn ipc(&self) -> HyprlandIPC {
+        HyprlandIPC::from_instance_dir(&self.dir)
+    }
+
+    pub fn events(&self) -> EventSubscriber {
+        EventSubscriber::with_socket(&self.dir.join(EVENT_SOCKET))
+    }
+}
+
+/// Directories holding one `<signature>` directory per instance, in lookup order
+pub fn hypr_roots() -> Vec<PathBuf> {
+    let runtime = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    // Anybody can create /tmp/hypr, root only looks at the per-user runtime dirs
+    let is_root = unsafe { libc::geteuid() } == 0;
+    roots_in(runtime.as_deref(), Path::new("/run/user"), (!is_root).then_some(Path::new("/tmp/hypr")))
+}
+
+fn roots_in(runtime: Option<&Path>, run_user: &Path, tmp: Option<&Path>) -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if let Some(runtime) = runtime {
+        roots.push(runtime.join("hypr"));
+    }
+
+    let mut users: Vec<PathBuf> = fs::read_dir(run_user)
+        .map(|entries| entries.flatten().map(|e| e.path().join("hypr")).collect())
+        .unwrap_or_default();
+    users.sort();
+    roots.extend(users);
+    roots.extend(tmp.map(Path::to_path_buf));
+
+    let mut seen = HashSet::new();
+    roots.retain(|r| seen.insert(r.clone()));
+    roots
+}
+
+/// Owner of the socket `name` in the instance directory `dir`, None unless the socket,
+/// `dir` and the root above it are no links and all belong to that same user
+pub fn socket_owner(dir: &Path, name: &str) -> Option<u32> {
+    let socket = fs::symlink_metadata(dir.join(name)).ok()?;
+    if !socket.file_type().is_socket() {
+        return None;
+    }
+    let owned = |path: &Path| {
+        fs::symlink_metadata(path)
+            .map(|m| m.is_dir() && m.uid() == socket.uid())
+            .unwrap_or(false)
+    };
+    (owned(dir) && dir.parent().map(owned).unwrap_or(false)).then_some(socket.uid())
+}
+
+/// Every instance whose request socket answers, e.g. the greeter plus each logged in user
+pub fn list_instances() -> Vec<HyprlandInstance> {
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+
+    for root in hypr_roots() {
+        let mut dirs: Vec<PathBuf> = match fs::read_dir(&root) {
+            Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        dirs.sort();
+
+        for dir in dirs {
+            let key = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+            if !seen.insert(key) {
+                continue;
+            }
+            if let Some(instance) = inspect_instance(&dir) {
+                found.push(instance);
+            }
+        }
+    }
+
+    found
+}
+
+fn inspect_instance(dir: &Path) -> Option<HyprlandInstance> {
+    let uid = socket_owner(dir, REQUEST_SOCKET)?;
+    let socket = dir.join(REQUEST_SOCKET);
+    let signature = dir.file_name()?.to_string_lossy().to_string();
+
+    // hyprland.lock: PID on the first line, the Wayland socket name on the second
+    let lock = fs::read_to_string(dir.join("hyprland.lock")).unwrap_or_default();
+    let mut lines = lock.lines().map(str::trim);
+    let pid = lines.next().and_then(|l| l.parse::<u32>().ok());
+    let wayland_display = lines.next().filter(|l| !l.is_empty()).map(str::to_string);
+
+    // A leftover directory from a crashed instance has a lock with a dead PID
+    if let Some(pid) = pid {
+        if !Path::new(&format!("/proc/{}", pid)).exists() {
+            return None;
+        }
+    }
+
+    let ipc = HyprlandIPC::with_socket(&socket).with_timeout(Duration::from_millis(300));
+    ipc.request("version").ok()?;
+
+    Some(HyprlandInstance {
+        signature,
+        dir: dir.to_path_buf(),
+        pid,
+        uid,
+        user: user_name(uid),
+        wayland_display,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::net::UnixListener;
+
+    const NOBODY: u32 = 65534;
+
+    fn chown(path: &Path, uid: u32) {
+        std::os::unix::fs::lchown(path, Some(uid), Some(uid)).unwrap();
+    }
+
+    /// `<root>/<signature>/.socket.sock`, listening
+    fn instance(root: &Path, signature: &str) -> UnixListener {
+        fs::create_dir_all(root.join(signature)).unwrap();
+        UnixListener::bind(root.join(signature).join(REQUEST_SOCKET)).unwrap()
+    }
+
+    #[test]
+    fn tmp_hypr_is_only_searched_when_asked() {
+        let run = TempDir::new("instances-roots");
+        fs::create_dir_all(run.join("1000")).unwrap();
+        fs::create_dir_all(run.join("0")).unwrap();
+        let runtime = run.join("1000");
+
+        let roots = roots_in(Some(&runtime), run.path(), None);
+        assert_eq!(roots, [run.join("1000/hypr"), run.join("0/hypr")]);
+        let roots = roots_in(None, run.path(), Some(Path::new("/tmp/hypr")));
+        assert_eq!(roots.last().unwrap(), Path::new("/tmp/hypr"));
+    }
+
+    #[test]
+    fn sockets_must_belong_to_the_directory_owner() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, chown needs root");
+            return;
+        }
+        let me = unsafe { libc::geteuid() };
+        let root = TempDir::new("instances-owner");
+        let _own = instance(&root.join("hypr"), "own");
+        let _planted = instance(&root.join("hypr"), "planted");
+        chown(&root.join("hypr/planted").join(REQUEST_SOCKET), NOBODY);
+
+        assert_eq!(socket_owner(&root.join("hypr/own"), REQUEST_SOCKET), Some(me));
+        assert_eq!(socket_owner(&root.join("hypr/planted"), REQUEST_SOCKET), None);
+        assert_eq!(socket_owner(&root.join("hypr/own"), EVENT_SOCKET), None);
+
+        // The whole chain has to agree, a foreign instance or root directory is not enough
+        chown(&root.join("hypr/planted").join(REQUEST_SOCKET), me);
+        chown(&root.join("hypr/planted"), NOBODY);
+        assert_eq!(socket_owner(&root.join("hypr/planted"), REQUEST_SOCKET), None);
+        chown(&root.join("hypr"), NOBODY);
+        assert_eq!(socket_owner(&root.join("hypr/own"), REQUEST_SOCKET), None);
+    }
+
+    #[test]
+    fn links_and_plain_files_are_not_sockets() {
+        let root = TempDir::new("instances-links");
+        let _real = instance(&root.join("hypr"), "real");
+        std::os::unix::fs::symlink(root.join("hypr/real"), root.join("hypr/link")).unwrap();
+        root.write("hypr/file/.socket.sock", "");
+        fs::create_dir_all(root.join("hypr/sock-link")).unwrap();
+        std::os::unix::fs::symlink(root.join("hypr/real").join(REQUEST_SOCKET), root.join("hypr/sock-link").join(REQUEST_SOCKET)).unwrap();
+
+        assert_eq!(socket_owner(&root.join("hypr/real"), REQUEST_SOCKET), Some(unsafe { libc::geteuid() }));
+        assert_eq!(socket_owner(&root.join("hypr/link"), REQUEST_SOCKET), None);
+        assert_eq!(socket_owner(&root.join("hypr/file"), REQUEST_SOCKET), None);
+        assert_eq!(socket_owner(&root.join("hypr/sock-link"), REQUEST_SOCKET), None);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+// Types for the `j/` (JSON) requests. Every field has a default and unknown
+// fields are ignored, so older and newer Hyprland releases both deserialize.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct WorkspaceRef {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Monitor {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub make: String,
+    pub model: String,
+    pub serial: String,
+    /// Mode size in pixels, before scale and transform
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: f32,
+    pub x: i32,
+    pub y: i32,
+    pub active_workspace: WorkspaceRef,
+    pub special_workspace: WorkspaceRef,
+    /// Space reserved by bars: left, top, right, bottom
+    pub reserved: Vec<i32>,
+    pub scale: f32,
+    /// wl_output transform, 0-7
+    pub transform: u8,
+    pub focused: bool,
+    pub dpms_status: bool,
+    pub vrr: bool,
+    pub disabled: bool,
+    pub current_format: String,
+    pub available_modes: Vec<String>,
+}
+
+impl Monitor {
+    /// Size in layout coordinates, i.e. after scale and rotation
+    pub fn logical_size(&self) -> (u32, u32) {
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        let w = (self.width as f32 / scale).round() as u32;
+        let h = (self.height as f32 / scale).round() as u32;
+        // Odd transforms rotate by 90 or 270 degrees
+        if self.transform % 2 == 1 { (h, w) } else { (w, h) }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    pub monitor: String,
+    #[serde(rename = "monitorID")]
+    pub monitor_id: i32,
+    pub windows: u32,
+    pub hasfullscreen: bool,
+    pub lastwindow: String,
+    pub lastwindowtitle: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Client {
+    pub address: String,
+    pub mapped: bool,
+    pub hidden: bool,
+    pub at: [i32; 2],
+    pub size: [i32; 2],
+    pub workspace: WorkspaceRef,
+    pub floating: bool,
+    pub pseudo: bool,
+    /// Monitor id
+    pub monitor: i32,
+    pub class: String,
+    pub title: String,
+    pub initial_class: String,
+    pub initial_title: String,
+    pub pid: i32,
+    pub xwayland: bool,
+    pub pinned: bool,
+    /// 0 none, 1 maximized, 2 fullscreen; older releases send a bool
+    #[serde(deserialize_with = "fullscreen_mode")]
+    pub fullscreen: u8,
+    pub grouped: Vec<String>,
+    pub tags: Vec<String>,
+    #[serde(rename = "focusHistoryID")]
+    pub focus_history_id: i32,
+}
+
+fn fullscreen_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(true) => 2,
+        serde_json::Value::Number(n) => n.as_u64().unwrap_or(0).min(u8::MAX as u64) as u8,
+        _ => 0,
+    })
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => s,
+        serde_json::Value::Number(n) => n.to_string(),
+        _ => String::new(),
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Layer {
+    pub address: String,
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    pub namespace: String,
+    pub pid: i32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct MonitorLayers {
+    /// Layer level ("0" background .. "3" overlay) -> surfaces
+    pub levels: HashMap<String, Vec<Layer>>,
+}
+
+/// `j/layers`: monitor name -> layers
+pub type Layers = HashMap<String, MonitorLayers>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Mouse {
+    pub address: String,
+    pub name: String,
+    pub default_speed: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Keyboard {
+    pub address: String,
+    pub name: String,
+    pub rules: String,
+    pub model: String,
+    pub layout: String,
+    pub variant: String,
+    pub options: String,
+    pub active_keymap: String,
+    pub main: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct InputDevice {
+    pub address: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Devices {
+    pub mice: Vec<Mouse>,
+    pub keyboards: Vec<Keyboard>,
+    pub tablets: Vec<InputDevice>,
+    pub touch: Vec<InputDevice>,
+    pub switches: Vec<InputDevice>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Version {
+    pub branch: String,
+    pub commit: String,
+    pub dirty: bool,
+    pub commit_message: String,
+    pub commit_date: String,
+    pub tag: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub commits: String,
+    pub flags: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::standin::StandIn;
+
+    #[test]
+    fn recorded_replies_deserialize() {
+        let standin = StandIn::recorded("queries-recorded");
+        let ipc = standin.ipc();
+
+        let monitors = ipc.monitors().unwrap();
+        assert_eq!(monitors.len(), 2);
+        assert_eq!((monitors[0].name.as_str(), monitors[0].refresh_rate), ("DP-1", 59.997));
+        assert_eq!(monitors[0].active_workspace, WorkspaceRef { id: 1, name: "1".into() });
+        assert_eq!(monitors[0].reserved, [0, 30, 0, 0]);
+        // Sparse entries fall back to defaults
+        assert_eq!((monitors[1].transform, monitors[1].make.as_str()), (1, ""));
+
+        let window = ipc.active_window().unwrap().unwrap();
+        assert_eq!((window.class.as_str(), window.at, window.size), ("kitty", [10, 40], [1900, 1030]));
+        assert_eq!(window.focus_history_id, 0);
+
+        let workspaces = ipc.workspaces().unwrap();
+        assert_eq!((workspaces[0].monitor_id, workspaces[0].windows), (0, 2));
+
+        let version = ipc.version().unwrap();
+        assert_eq!((version.tag.as_str(), version.commits.as_str()), ("v0.45.2", "5404"));
+
+        assert_eq!(
+            standin.requests(),
+            ["j/monitors", "j/activewindow", "j/workspaces", "j/version"]
+        );
+    }
+
+    #[test]
+    fn no_focused_window_and_error_replies() {
+        let standin = StandIn::start("queries-empty", &[("j/activewindow", "{}"), ("j/clients", "[{\"address\": ")]);
+        let ipc = standin.ipc();
+        assert_eq!(ipc.active_window(), Ok(None));
+        assert!(ipc.clients().unwrap_err().starts_with("Invalid clients reply"));
+        // Not in the table: the stand-in answers like Hyprland does
+        assert!(ipc.devices().unwrap_err().contains("unknown request"));
+    }
+
+    #[test]
+    fn older_release_formats() {
+        let standin = StandIn::start(
+            "queries-old",
+            &[
+                ("j/clients", r#"[{"address": "0x1", "fullscreen": true, "fullscreenMode": 0}, {"address": "0x2", "fullscreen": false}]"#),
+                ("j/version", r#"{"tag": "v0.30.0", "commits": "3900"}"#),
+            ],
+        );
+        let clients = standin.ipc().clients().unwrap();
+        assert_eq!(clients.iter().map(|c| c.fullscreen).collect::<Vec<_>>(), [2, 0]);
+        assert_eq!(standin.ipc().version().unwrap().commits, "3900");
+    }
+
+    #[test]
+    fn layers_and_devices() {
+        let standin = StandIn::start(
+            "queries-layers",
+            &[
+                ("j/layers", r#"{"DP-1": {"levels": {"0": [{"address": "0xa", "x": 0, "y": 0, "w": 1920, "h": 1080, "namespace": "wallpaper", "pid": 7}], "2": []}}}"#),
+                ("j/devices", r#"{"mice": [{"address": "0x3", "name": "logitech", "defaultSpeed": 0.0}], "keyboards": [{"name": "at-kbd", "layout": "us,de", "active_keymap": "English (US)", "main": true}]}"#),
+            ],
+        );
+        let layers = standin.ipc().layers().unwrap();
+        assert_eq!(layers["DP-1"].levels["0"][0].namespace, "wallpaper");
+        assert!(layers["DP-1"].levels["2"].is_empty());
+
+        let devices = standin.ipc().devices().unwrap();
+        assert_eq!(devices.mice[0].name, "logitech");
+        assert_eq!((devices.keyboards[0].active_keymap.as_str(), devices.keyboards[0].main), ("English (US)", true));
+        assert!(devices.tablets.is_empty());
+    }
+
+    #[test]
+    fn logical_size_applies_scale_and_rotation() {
+        let monitor = |scale, transform| Monitor { width: 3840, height: 2160, scale, transform, ..Default::default() };
+        assert_eq!(monitor(2.0, 0).logical_size(), (1920, 1080));
+        assert_eq!(monitor(2.0, 1).logical_size(), (1080, 1920));
+        assert_eq!(monitor(1.5, 6).logical_size(), (2560, 1440));
+        assert_eq!(monitor(0.0, 3).logical_size(), (2160, 3840));
+    }
+}
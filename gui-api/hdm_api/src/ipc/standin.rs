@@ -0,0 +1,112 @@
+//! Stand-in for a Hyprland instance in tests: a request socket that replays replies
+//! recorded from a real Hyprland
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{HyprlandIPC, EVENT_SOCKET, REQUEST_SOCKET};
+use crate::test_util::TempDir;
+
+/// Replies of Hyprland 0.45 to the requests the tests use, trimmed to the fields they read
+pub(crate) const RECORDED: &[(&str, &str)] = &[
+    ("activewindow", "Window 55d4e5b0e1a0 -> ~/src:\n\tmapped: 1\n\thidden: 0\n\tat: 10,40\n\tsize: 1900,1030\n\tworkspace: 1 (1)\n\tclass: kitty\n\ttitle: ~/src\n\tpid: 4242\n\n"),
+    ("j/activewindow", r#"{"address": "0x55d4e5b0e1a0", "mapped": true, "hidden": false, "at": [10, 40], "size": [1900, 1030], "workspace": {"id": 1, "name": "1"}, "floating": false, "monitor": 0, "class": "kitty", "title": "~/src", "initialClass": "kitty", "initialTitle": "kitty", "pid": 4242, "xwayland": false, "pinned": false, "fullscreen": 0, "fullscreenClient": 0, "grouped": [], "tags": [], "swallowing": "0x0", "focusHistoryID": 0}"#),
+    ("j/monitors", r#"[{"id": 0, "name": "DP-1", "description": "Dell Inc. DELL U2720Q", "make": "Dell Inc.", "model": "DELL U2720Q", "serial": "ABC123", "width": 3840, "height": 2160, "refreshRate": 59.99700, "x": 0, "y": 0, "activeWorkspace": {"id": 1, "name": "1"}, "specialWorkspace": {"id": 0, "name": ""}, "reserved": [0, 30, 0, 0], "scale": 2.00, "transform": 0, "focused": true, "dpmsStatus": true, "vrr": false, "solitary": "0", "activelyTearing": false, "disabled": false, "currentFormat": "XRGB8888", "mirrorOf": "none", "availableModes": ["3840x2160@60.00Hz", "2560x1440@59.95Hz"]}, {"id": 1, "name": "HDMI-A-1", "width": 1920, "height": 1080, "refreshRate": 60.0, "x": 1920, "y": 0, "scale": 1.0, "transform": 1, "focused": false}]"#),
+    ("j/version", r#"{"branch": "", "commit": "12f9a0d0b93f691d4d9923716557154d74777b0a", "version": "0.45.2", "dirty": false, "commit_message": "version: bump to 0.45.2", "commit_date": "Sat Nov 16 15:03:35 2024", "tag": "v0.45.2", "commits": 5404, "buildAquamarine": "0.5.0", "flags": []}"#),
+    ("j/workspaces", r#"[{"id": 1, "name": "1", "monitor": "DP-1", "monitorID": 0, "windows": 2, "hasfullscreen": false, "lastwindow": "0x55d4e5b0e1a0", "lastwindowtitle": "~/src", "ispersistent": false}]"#),
+    ("dispatch workspace 2", "ok"),
+    ("keyword general:gaps_in 5", "ok"),
+    ("dispatch nosuchdispatcher", "Invalid dispatcher"),
+    ("j/nosuchrequest", "unknown request"),
+];
+
+/// A request socket at `<dir>/.socket.sock` answering from a table of replies;
+/// requests without a reply get "unknown request", like Hyprland
+pub(crate) struct StandIn {
+    pub dir: TempDir,
+    requests: Arc<Mutex<Vec<String>>>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StandIn {
+    pub fn recorded(name: &str) -> Self {
+        Self::start(name, RECORDED)
+    }
+
+    pub fn start(name: &str, replies: &[(&str, &str)]) -> Self {
+        let dir = TempDir::new(name);
+        let listener = UnixListener::bind(dir.join(REQUEST_SOCKET)).expect("bind stand-in socket");
+        let replies: HashMap<String, String> =
+            replies.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (log, stop) = (requests.clone(), stopped.clone());
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let mut request = String::new();
+                if stream.read_to_string(&mut request).is_err() {
+                    continue;
+                }
+                let reply = replies.get(&request).map(String::as_str).unwrap_or("unknown request");
+                log.lock().unwrap().push(request);
+                let _ = stream.write_all(reply.as_bytes());
+            }
+        });
+
+        Self { dir, requests, stopped, handle: Some(handle) }
+    }
+
+    pub fn socket(&self) -> PathBuf {
+        self.dir.join(REQUEST_SOCKET)
+    }
+
+    pub fn ipc(&self) -> HyprlandIPC {
+        HyprlandIPC::from_instance_dir(self.dir.path())
+    }
+
+    /// Serve `.socket2.sock` next to the request socket: the n-th client gets the
+    /// n-th chunk of event text, sent in two halves so lines arrive split, and is then
+    /// disconnected like on a Hyprland restart
+    pub fn serve_events(&self, connections: Vec<String>) -> JoinHandle<()> {
+        let listener = UnixListener::bind(self.dir.join(EVENT_SOCKET)).expect("bind stand-in event socket");
+        thread::spawn(move || {
+            for text in connections {
+                let Ok((mut stream, _)) = listener.accept() else { return };
+                let (head, tail) = text.split_at(text.len() / 2);
+                let _ = stream.write_all(head.as_bytes());
+                thread::sleep(Duration::from_millis(50));
+                let _ = stream.write_all(tail.as_bytes());
+            }
+        })
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag
+        let _ = UnixStream::connect(self.socket());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
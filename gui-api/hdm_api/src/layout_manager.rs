@@ -19,7 +19,7 @@ impl LayoutManager {
     pub fn new(config: &HDMConfig) -> Self {
         Self {
             panel: Panel {
-                name: config.default_session.clone(),
+                name: config.greeter_interface.clone(),
                 layout: Layout::Tiling,
             },
             global_layout: Layout::Tiling,
@@ -1,11 +1,31 @@
 pub mod session_manager;
+pub mod session_log;
+pub mod session_supervisor;
+pub mod process_group;
+pub mod systemd_scope;
+pub mod vt;
+pub mod session_registry;
+pub mod session_worker;
+pub mod hooks;
+pub mod session_policy;
+pub mod greeter;
+pub mod greeter_hyprland;
+pub mod monitor_config;
+pub mod daemon;
+pub mod workspace_snapshot;
 pub mod user_manager;
 pub mod theme_manager;
 pub mod layout_manager;
 pub mod compositor;
+pub mod event_loop;
+pub mod software_renderer;
+pub mod headless;
 pub mod ipc;
 pub mod unidata;
 
+#[cfg(test)]
+mod test_util;
+
 // cdylib için direkt export
 pub use theme_manager::ThemeManager;
 pub use compositor::Compositor;
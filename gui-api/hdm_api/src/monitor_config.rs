@@ -0,0 +1,502 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use config::HDMConfig;
+use crate::daemon::{last_user, lookup_user};
+use crate::session_policy::user_home;
+use crate::session_policy::FsUser;
+
+/// Includes nested deeper than this are ignored, Hyprland configs never need that many
+const MAX_SOURCE_DEPTH: usize = 16;
+
+/// Resolution part of a `monitor=` rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorMode {
+    Preferred,
+    HighRes,
+    HighRr,
+    /// Any other keyword Hyprland understands, e.g. `maxwidth`
+    Keyword(String),
+    Exact { width: u32, height: u32, refresh: Option<f32> },
+}
+
+/// Position part of a `monitor=` rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorPosition {
+    /// `auto` or one of its directions, e.g. `auto-right`
+    Auto(String),
+    At { x: i32, y: i32 },
+}
+
+/// One `monitor=` line of a hyprland.conf
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorRule {
+    /// Output name like `DP-1`, `desc:<description>`, or empty for every other output
+    pub name: String,
+    pub disabled: bool,
+    pub mode: MonitorMode,
+    pub position: MonitorPosition,
+    /// None for `auto`
+    pub scale: Option<f32>,
+    /// wl_output transform, 0-7
+    pub transform: u8,
+    /// The value after `monitor =` with variables substituted, as Hyprland reads it
+    pub raw: String,
+}
+
+impl MonitorRule {
+    /// Parse the value of a `monitor=` line; None for rules that do not describe a
+    /// layout, such as `addreserved`
+    pub fn parse(value: &str) -> Option<Self> {
+        let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+        let name = fields.first()?.to_string();
+        let mut rule = MonitorRule {
+            name,
+            disabled: false,
+            mode: MonitorMode::Preferred,
+            position: MonitorPosition::Auto("auto".into()),
+            scale: None,
+            transform: 0,
+            raw: value.trim().to_string(),
+        };
+
+        match fields.get(1).copied() {
+            Some("disable") | Some("disabled") => {
+                rule.disabled = true;
+                return Some(rule);
+            }
+            Some("addreserved") | None => return None,
+            Some(mode) => rule.mode = parse_mode(mode)?,
+        }
+        if let Some(position) = fields.get(2) {
+            rule.position = parse_position(position)?;
+        }
+        if let Some(scale) = fields.get(3) {
+            rule.scale = match *scale {
+                "auto" => None,
+                s => Some(s.parse::<f32>().ok().filter(|s| *s > 0.0)?),
+            };
+        }
+
+        // Optional `key, value` pairs follow, e.g. `transform, 1` or `vrr, 1`
+        for pair in fields.get(4..).unwrap_or(&[]).chunks(2) {
+            if let [key, value] = pair {
+                if *key == "transform" {
+                    rule.transform = value.parse::<u8>().ok().filter(|t| *t < 8)?;
+                }
+            }
+        }
+        Some(rule)
+    }
+
+    /// True for the catch-all rule, `monitor = ,preferred,auto,1`
+    pub fn is_default(&self) -> bool {
+        self.name.is_empty()
+    }
+}
+
+fn parse_mode(mode: &str) -> Option<MonitorMode> {
+    Some(match mode {
+        "preferred" => MonitorMode::Preferred,
+        "highres" => MonitorMode::HighRes,
+        "highrr" => MonitorMode::HighRr,
+        m if m.chars().all(|c| c.is_ascii_alphabetic()) => MonitorMode::Keyword(m.to_string()),
+        m => {
+            let (size, refresh) = match m.split_once('@') {
+                Some((size, refresh)) => (size, Some(refresh.trim_end_matches("Hz").parse::<f32>().ok()?)),
+                None => (m, None),
+            };
+            let (w, h) = size.split_once('x')?;
+            MonitorMode::Exact { width: w.parse().ok()?, height: h.parse().ok()?, refresh }
+        }
+    })
+}
+
+fn parse_position(position: &str) -> Option<MonitorPosition> {
+    if position.starts_with("auto") {
+        return Some(MonitorPosition::Auto(position.to_string()));
+    }
+    // The x part may itself be negative, e.g. -1920x0
+    let split = position.char_indices().skip(1).find(|(_, c)| *c == 'x')?.0;
+    let (x, y) = (&position[..split], &position[split + 1..]);
+    Some(MonitorPosition::At { x: x.parse().ok()?, y: y.parse().ok()? })
+}
+
+/// Size of an output whose rule names no exact mode, e.g. `preferred`
+pub const DEFAULT_OUTPUT_SIZE: (u32, u32) = (1920, 1080);
+
+/// An output as an enabled rule lays it out, see `output_layout`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputLayout {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// In mHz, as wl_output wants it
+    pub refresh: i32,
+    pub x: i32,
+    pub y: i32,
+    /// wl_output only carries integer scales
+    pub scale: i32,
+    pub transform: u8,
+}
+
+impl OutputLayout {
+    /// Size in layout coordinates, after scale and rotation
+    fn logical_size(&self) -> (i32, i32) {
+        let (w, h) = (self.width as i32 / self.scale, self.height as i32 / self.scale);
+        if self.transform % 2 == 1 { (h, w) } else { (w, h) }
+    }
+}
+
+/// One output per enabled rule, in rule order. Fixed positions are kept and `auto`
+/// ones are placed next to everything laid out so far, to the right unless the rule
+/// says `auto-left`, `auto-up` or `auto-down`. Rules for `desc:` or every other
+/// output get the names `HDM-1`, `HDM-2`, ... by their place in the list
+pub fn output_layout(rules: &[MonitorRule]) -> Vec<OutputLayout> {
+    let mut outputs: Vec<(OutputLayout, Option<&str>)> = rules
+        .iter()
+        .filter(|r| !r.disabled)
+        .enumerate()
+        .map(|(i, rule)| {
+            let (width, height, refresh) = match rule.mode {
+                MonitorMode::Exact { width, height, refresh } => (width, height, refresh.unwrap_or(60.0)),
+                _ => (DEFAULT_OUTPUT_SIZE.0, DEFAULT_OUTPUT_SIZE.1, 60.0),
+            };
+            let name = if rule.is_default() || rule.name.starts_with("desc:") {
+                format!("HDM-{}", i + 1)
+            } else {
+                rule.name.clone()
+            };
+            let (x, y, auto) = match &rule.position {
+                MonitorPosition::At { x, y } => (*x, *y, None),
+                MonitorPosition::Auto(direction) => (0, 0, Some(direction.as_str())),
+            };
+            let layout = OutputLayout {
+                name,
+                width,
+                height,
+                refresh: (refresh * 1000.0).round() as i32,
+                x,
+                y,
+                scale: rule.scale.map(|s| s.round().max(1.0) as i32).unwrap_or(1),
+                transform: rule.transform,
+            };
+            (layout, auto)
+        })
+        .collect();
+
+    // Bounds of the outputs placed so far: left, top, right, bottom
+    let mut bounds: Option<(i32, i32, i32, i32)> = None;
+    let extend = |o: &OutputLayout, bounds: &mut Option<(i32, i32, i32, i32)>| {
+        let (w, h) = o.logical_size();
+        let (l, t, r, b) = bounds.unwrap_or((o.x, o.y, o.x + w, o.y + h));
+        *bounds = Some((l.min(o.x), t.min(o.y), r.max(o.x + w), b.max(o.y + h)));
+    };
+    for (output, _) in outputs.iter().filter(|(_, auto)| auto.is_none()) {
+        extend(output, &mut bounds);
+    }
+    for (output, auto) in outputs.iter_mut() {
+        let Some(direction) = auto else { continue };
+        if let Some((l, t, r, b)) = bounds {
+            let (w, h) = output.logical_size();
+            (output.x, output.y) = match *direction {
+                "auto-left" => (l - w, t),
+                "auto-up" => (l, t - h),
+                "auto-down" => (l, b),
+                _ => (r, t),
+            };
+        }
+        extend(output, &mut bounds);
+    }
+
+    outputs.into_iter().map(|(output, _)| output).collect()
+}
+
+/// Collects `monitor=` rules while following `source=` and substituting `$variables`
+struct Reader {
+    home: PathBuf,
+    vars: Vec<(String, String)>,
+    rules: Vec<MonitorRule>,
+    visited: HashSet<PathBuf>,
+}
+
+impl Reader {
+    fn read(&mut self, path: &Path, depth: usize) -> Result<(), String> {
+        if depth > MAX_SOURCE_DEPTH {
+            eprintln!("[HyprDM] Ignoring {:?}: sourced too deep", path);
+            return Ok(());
+        }
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if !self.visited.insert(key) {
+            return Ok(());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+
+        // monitor, source and variables only count outside of `category { }` blocks
+        let mut nesting = 0usize;
+        for line in content.lines() {
+            let line = strip_comment(line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.ends_with('{') {
+                nesting += 1;
+                continue;
+            }
+            if line == "}" {
+                nesting = nesting.saturating_sub(1);
+                continue;
+            }
+            if nesting > 0 {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => continue,
+            };
+            if let Some(name) = key.strip_prefix('$') {
+                let value = self.substitute(value);
+                self.vars.retain(|(n, _)| n != name);
+                self.vars.push((name.to_string(), value));
+                // Longest name first so `$mon` does not eat the start of `$monitor`
+                self.vars.sort_by_key(|v| std::cmp::Reverse(v.0.len()));
+            } else if key == "source" {
+                for file in self.expand_source(&self.substitute(value), &dir) {
+                    // A missing include is an error in Hyprland too, but the rest still loads
+                    if let Err(e) = self.read(&file, depth + 1) {
+                        eprintln!("[HyprDM] {}", e);
+                    }
+                }
+            } else if key == "monitor" {
+                let value = self.substitute(value);
+                match MonitorRule::parse(&value) {
+                    Some(rule) => self.add(rule),
+                    None => eprintln!("[HyprDM] Skipping monitor rule {:?}", value),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A later rule for the same output replaces the earlier one, as in Hyprland
+    fn add(&mut self, rule: MonitorRule) {
+        match self.rules.iter_mut().find(|r| r.name == rule.name) {
+            Some(existing) => *existing = rule,
+            None => self.rules.push(rule),
+        }
+    }
+
+    fn substitute(&self, value: &str) -> String {
+        let mut value = value.to_string();
+        for (name, replacement) in &self.vars {
+            value = value.replace(&format!("${}", name), replacement);
+        }
+        value
+    }
+
+    /// `~` is the config owner's home, relative paths start at the including file;
+    /// a `*` in the file name matches like a shell glob
+    fn expand_source(&self, value: &str, dir: &Path) -> Vec<PathBuf> {
+        let path = match value.strip_prefix("~/") {
+            Some(rest) => self.home.join(rest),
+            None => dir.join(value),
+        };
+        let pattern = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if !pattern.contains('*') {
+            return vec![path];
+        }
+
+        let parent = path.parent().unwrap_or(Path::new("/"));
+        let mut files: Vec<PathBuf> = fs::read_dir(parent)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.is_file())
+                    .filter(|p| p.file_name().map(|n| glob_match(&pattern, &n.to_string_lossy())).unwrap_or(false))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
+}
+
+/// `#` starts a comment, `##` is a literal `#`
+fn strip_comment(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '#' {
+            if chars.peek() == Some(&'#') {
+                chars.next();
+                out.push('#');
+                continue;
+            }
+            break;
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || name.len() < first.len() + last.len() || !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// All `monitor=` rules of `path` and the files it sources; `home` resolves `~`
+pub fn parse_monitor_rules(path: &Path, home: &Path) -> Result<Vec<MonitorRule>, String> {
+    let mut reader = Reader {
+        home: home.to_path_buf(),
+        vars: Vec::new(),
+        rules: Vec::new(),
+        visited: HashSet::new(),
+    };
+    reader.read(path, 0)?;
+    Ok(reader.rules)
+}
+
+/// `~/.config/hypr/hyprland.conf`
+pub fn user_hyprland_conf(home: &Path) -> PathBuf {
+    home.join(".config/hypr/hyprland.conf")
+}
+
+/// The monitor layout for the greeter, from `greeter_monitors`: "last_user" reads the
+/// hyprland.conf of whoever logged in last, "none" keeps the defaults, anything else is a file
+pub fn greeter_monitor_rules(config: &HDMConfig) -> Result<Vec<MonitorRule>, String> {
+    match config.greeter_monitors.as_str() {
+        "none" => Ok(Vec::new()),
+        "last_user" => {
+            let user = match last_user() {
+                Some(user) => user,
+                None => return Ok(Vec::new()),
+            };
+            let (uid, gid) = lookup_user(&user)?;
+            let home = user_home(&user).ok_or_else(|| format!("User {} has no home directory", user))?;
+            // The file and everything it sources is read with the user's permissions
+            let _as_user = FsUser::switch(uid, gid);
+            parse_monitor_rules(&user_hyprland_conf(&home), &home)
+        }
+        path => {
+            let home = user_home("root").unwrap_or_else(|| PathBuf::from("/root"));
+            parse_monitor_rules(Path::new(path), &home)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn names(rules: &[MonitorRule]) -> Vec<&str> {
+        rules.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_rules_with_negative_positions() {
+        let rule = MonitorRule::parse("DP-2, 2560x1440@143.9Hz, -2560x-360, 1.25, transform, 3").unwrap();
+        assert_eq!(rule.mode, MonitorMode::Exact { width: 2560, height: 1440, refresh: Some(143.9) });
+        assert_eq!(rule.position, MonitorPosition::At { x: -2560, y: -360 });
+        assert_eq!((rule.scale, rule.transform), (Some(1.25), 3));
+
+        let rule = MonitorRule::parse("HDMI-A-1,preferred,0x-1080,auto").unwrap();
+        assert_eq!((rule.position, rule.scale), (MonitorPosition::At { x: 0, y: -1080 }, None));
+        assert!(MonitorRule::parse(",preferred,auto,1").unwrap().is_default());
+        assert!(MonitorRule::parse("eDP-1,disable").unwrap().disabled);
+        assert_eq!(MonitorRule::parse("eDP-1,addreserved,30,0,0,0"), None);
+        assert_eq!(MonitorRule::parse("eDP-1,1920x1080,1x"), None);
+        assert_eq!(MonitorRule::parse("eDP-1,1920x1080,0x0,1,transform,9"), None);
+    }
+
+    #[test]
+    fn variables_are_substituted_longest_name_first() {
+        let home = TempDir::new("monitors-vars");
+        let conf = home.write(
+            "hyprland.conf",
+            "$mon = DP-1\n$monitor = HDMI-A-1\n$res = 1920x1080@60\n\
+             monitor = $monitor, $res, -1920x0, 1\n\
+             monitor = $mon, highrr, 0x0, 1 # trailing comment\n\
+             general {\n    monitor = ignored, preferred, auto, 1\n}\n",
+        );
+        let rules = parse_monitor_rules(&conf, home.path()).unwrap();
+        assert_eq!(names(&rules), ["HDMI-A-1", "DP-1"]);
+        assert_eq!(rules[0].raw, "HDMI-A-1, 1920x1080@60, -1920x0, 1");
+        assert_eq!(rules[1].mode, MonitorMode::HighRr);
+    }
+
+    #[test]
+    fn source_follows_globs_home_and_cycles() {
+        let home = TempDir::new("monitors-source");
+        home.write(".config/hypr/monitors/10-laptop.conf", "monitor = eDP-1, preferred, 0x0, 1\n");
+        home.write(".config/hypr/monitors/20-desk.conf", "monitor = DP-1, 3840x2160, auto-right, 2\nsource = ~/.config/hypr/hyprland.conf\n");
+        home.write(".config/hypr/monitors/notes.txt", "monitor = WRONG, preferred, auto, 1\n");
+        home.write(".config/hypr/overrides.conf", "monitor = eDP-1, disable\n");
+        let conf = home.write(
+            ".config/hypr/hyprland.conf",
+            "source = ./monitors/*.conf\nsource = ~/.config/hypr/overrides.conf\nsource = missing.conf\n",
+        );
+
+        let rules = parse_monitor_rules(&conf, home.path()).unwrap();
+        assert_eq!(names(&rules), ["eDP-1", "DP-1"]);
+        // The later rule for the same output wins
+        assert!(rules[0].disabled);
+        assert_eq!(user_hyprland_conf(home.path()), conf);
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("*.conf", "monitors.conf"));
+        assert!(glob_match("mon*-*.conf", "monitors-desk.conf"));
+        assert!(!glob_match("*.conf", "monitors.conf.bak"));
+        assert!(!glob_match("a*a", "a"));
+    }
+
+    #[test]
+    fn layout_has_an_output_per_enabled_rule() {
+        let rules: Vec<MonitorRule> = [
+            "DP-1, 3840x2160@59.997, 0x0, 2",
+            "HDMI-A-1, 1920x1080, auto, 1, transform, 1",
+            "eDP-1, disable",
+            "desc:Dell Inc. U2720Q, preferred, -1920x0, 1",
+            ", preferred, auto-down, 1",
+        ]
+        .iter()
+        .map(|r| MonitorRule::parse(r).unwrap())
+        .collect();
+
+        let layout = output_layout(&rules);
+        let placed: Vec<_> = layout.iter().map(|o| (o.name.as_str(), o.x, o.y, o.scale)).collect();
+        assert_eq!(
+            placed,
+            [
+                ("DP-1", 0, 0, 2),
+                // Right of everything with a fixed place: DP-1 is 1920 wide at scale 2
+                ("HDMI-A-1", 1920, 0, 1),
+                ("HDM-3", -1920, 0, 1),
+                // Below the tallest, the rotated HDMI-A-1
+                ("HDM-4", -1920, 1920, 1),
+            ]
+        );
+        assert_eq!((layout[0].width, layout[0].height, layout[0].refresh), (3840, 2160, 59997));
+        assert_eq!((layout[2].width, layout[2].height, layout[2].refresh), (1920, 1080, 60000));
+        assert!(output_layout(&[MonitorRule::parse("eDP-1,disable").unwrap()]).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs;
+
+/// Pids still alive at each step of a graceful stop
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StopReport {
+    /// Group members that received SIGTERM
+    pub terminated: Vec<u32>,
+    /// Members still alive after the grace period, these received SIGKILL
+    pub killed: Vec<u32>,
+    /// Members still alive after SIGKILL (stuck in uninterruptible sleep etc.)
+    pub survivors: Vec<u32>,
+}
+
+/// Live (non-zombie) processes whose process group is `pgid`
+pub fn members(pgid: u32) -> Vec<u32> {
+    let mut pids = Vec::new();
+    let entries = match fs::read_dir("/proc") {
+        Ok(e) => e,
+        Err(_) => return pids,
+    };
+
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) {
+            Some(p) => p,
+            None => continue,
+        };
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        // /proc/<pid>/stat: pid (comm) state ppid pgrp ...; comm may contain spaces
+        let rest = match stat.rfind(')') {
+            Some(i) => &stat[i + 1..],
+            None => continue,
+        };
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        if fields.len() < 3 || fields[0] == "Z" {
+            continue;
+        }
+        if fields[2].parse::<u32>().ok() == Some(pgid) {
+            pids.push(pid);
+        }
+    }
+
+    pids.sort_unstable();
+    pids
+}
+
+/// Send `signal` to every process in the group
+pub fn signal(pgid: u32, signal: i32) -> Result<(), String> {
+    let ret = unsafe { libc::kill(-(pgid as libc::pid_t), signal) };
+    if ret != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(format!("Failed to signal process group {}: {}", pgid, err));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn members_finds_the_whole_group_and_signal_reaches_it() {
+        let mut child = Command::new("/bin/sh")
+            .args(["-c", "sleep 30 & sleep 30 & wait"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let pgid = child.id();
+        wait_for(|| members(pgid).len() == 3);
+        assert!(members(pgid).contains(&pgid));
+
+        signal(pgid, libc::SIGKILL).unwrap();
+        child.wait().unwrap();
+        wait_for(|| members(pgid).is_empty());
+    }
+
+    #[test]
+    fn signalling_a_gone_group_is_not_an_error() {
+        let mut child = Command::new("true").process_group(0).spawn().unwrap();
+        let pgid = child.id();
+        child.wait().unwrap();
+        assert!(members(pgid).is_empty());
+        assert_eq!(signal(pgid, libc::SIGTERM), Ok(()));
+    }
+}
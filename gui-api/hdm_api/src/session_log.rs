@@ -0,0 +1,260 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use config::HDMConfig;
+use crate::session_policy::FsUser;
+
+/// Where the stdout/stderr of a session goes
+#[derive(Debug, Clone)]
+pub enum SessionOutput {
+    Null,
+    File { path: PathBuf, max_size: u64 },
+    Journald,
+}
+
+impl SessionOutput {
+    /// Build the output target from the config for a user with the given home directory
+    pub fn from_config(config: &HDMConfig, home: &Path) -> Self {
+        if config.session_log_journald {
+            SessionOutput::Journald
+        } else {
+            SessionOutput::File {
+                path: config.session_log_file(home),
+                max_size: config.session_log_max_size,
+            }
+        }
+    }
+}
+
+/// Size-capped log, rotated per session: opening it moves the previous session's log to
+/// `<name>.old`. Output past `max_size` moves the earlier part of the same session to
+/// `<name>.1` instead, so the previous session stays readable
+pub struct RotatingLog {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+    written: u64,
+    /// uid and gid every file operation runs as
+    owner: Option<(u32, u32)>,
+}
+
+impl RotatingLog {
+    /// Rotate the existing log and open a fresh one
+    pub fn open(path: &Path, max_size: u64) -> Result<Self, String> {
+        Self::open_as(path, max_size, None)
+    }
+
+    /// Same as `open`, creating, rotating and opening as `owner`, so a log in a user's home
+    /// belongs to them and root never follows a link they planted there
+    pub fn open_as(path: &Path, max_size: u64, owner: Option<(u32, u32)>) -> Result<Self, String> {
+        let _as_user = owner.and_then(|(uid, gid)| FsUser::switch(uid, gid));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create log dir {:?}: {}", parent, e))?;
+        }
+        rotate_session(path).map_err(|e| format!("Failed to rotate log {:?}: {}", path, e))?;
+        let file = open_truncated(path)
+            .map_err(|e| format!("Failed to open log {:?}: {}", path, e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            file,
+            written: 0,
+            owner,
+        })
+    }
+
+    /// Forward everything read from `reader` into the log on a background thread
+    pub fn pump<R: Read + Send + 'static>(log: Arc<Mutex<RotatingLog>>, mut reader: R) {
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if let Ok(mut log) = log.lock() {
+                            let _ = log.write_all(&buf[..n]);
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_size > 0 && self.written + buf.len() as u64 > self.max_size {
+            self.file.flush()?;
+            // The fs uid is per thread, the pump threads need their own switch
+            let _as_user = self.owner.and_then(|(uid, gid)| FsUser::switch(uid, gid));
+            rename_if_exists(&self.path, &with_suffix(&self.path, ".1"))?;
+            self.file = open_truncated(&self.path)?;
+            self.written = 0;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Rename without following links; a missing `from` is fine
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// The last session's `<name>` and `<name>.1` become `<name>.old` and `<name>.old.1`
+fn rotate_session(path: &Path) -> io::Result<()> {
+    let old = with_suffix(path, ".old");
+    rename_if_exists(path, &old)?;
+    let part = with_suffix(path, ".1");
+    let old_part = with_suffix(&old, ".1");
+    if part.symlink_metadata().is_ok() {
+        fs::rename(part, old_part)
+    } else {
+        // Don't leave an older session's first part next to the new backup
+        match fs::remove_file(old_part) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+fn open_truncated(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default()
+    }
+
+    #[test]
+    fn output_is_the_users_log_unless_journald_is_set() {
+        let mut config = HDMConfig::default();
+        match SessionOutput::from_config(&config, Path::new("/home/alice")) {
+            SessionOutput::File { path, max_size } => {
+                assert_eq!(path, Path::new("/home/alice/.local/state/hyprdm/session.log"));
+                assert_eq!(max_size, config.session_log_max_size);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        config.session_log_journald = true;
+        assert!(matches!(SessionOutput::from_config(&config, Path::new("/home/alice")), SessionOutput::Journald));
+    }
+
+    #[test]
+    fn each_open_starts_a_new_session_log() {
+        let dir = TempDir::new("log-sessions");
+        let path = dir.join("state/session.log");
+
+        let mut log = RotatingLog::open(&path, 0).unwrap();
+        log.write_all(b"first").unwrap();
+        drop(log);
+        let mut log = RotatingLog::open(&path, 0).unwrap();
+        log.write_all(b"second").unwrap();
+
+        assert_eq!(read(&path), "second");
+        assert_eq!(read(&with_suffix(&path, ".old")), "first");
+    }
+
+    #[test]
+    fn size_rotation_keeps_the_previous_session() {
+        let dir = TempDir::new("log-size");
+        let path = dir.join("session.log");
+
+        let mut log = RotatingLog::open(&path, 8).unwrap();
+        log.write_all(b"previous").unwrap();
+        drop(log);
+
+        let mut log = RotatingLog::open(&path, 8).unwrap();
+        log.write_all(b"aaaa").unwrap();
+        log.write_all(b"bbbb").unwrap();
+        log.write_all(b"cccc").unwrap();
+
+        assert_eq!(read(&with_suffix(&path, ".old")), "previous");
+        assert_eq!(read(&with_suffix(&path, ".1")), "aaaabbbb");
+        assert_eq!(read(&path), "cccc");
+
+        // The next session takes both parts along
+        drop(log);
+        RotatingLog::open(&path, 8).unwrap();
+        assert_eq!(read(&with_suffix(&path, ".old.1")), "aaaabbbb");
+        assert_eq!(read(&with_suffix(&path, ".old")), "cccc");
+        assert!(!with_suffix(&path, ".1").exists());
+    }
+
+    #[test]
+    fn links_are_never_written_through() {
+        let dir = TempDir::new("log-links");
+        let target = dir.write("target", "untouched");
+        let path = dir.join("session.log");
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        assert!(open_truncated(&path).is_err());
+        let mut log = RotatingLog::open(&path, 0).unwrap();
+        log.write_all(b"log").unwrap();
+        assert_eq!(read(&target), "untouched");
+        assert!(!path.symlink_metadata().unwrap().file_type().is_symlink());
+    }
+
+    #[test]
+    fn open_as_creates_the_log_as_its_owner() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, needs root");
+            return;
+        }
+        let dir = TempDir::new("log-owner");
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+        let path = dir.join("home/.local/state/hyprdm/session.log");
+
+        let mut log = RotatingLog::open_as(&path, 4, Some((65534, 65534))).unwrap();
+        log.write_all(b"12345678").unwrap();
+        for file in [dir.join("home"), path.clone(), with_suffix(&path, ".1")] {
+            assert_eq!(fs::metadata(&file).unwrap().uid(), 65534, "{:?}", file);
+        }
+    }
+
+    #[test]
+    fn pump_copies_a_reader_into_the_log() {
+        let dir = TempDir::new("log-pump");
+        let path = dir.join("session.log");
+        let log = Arc::new(Mutex::new(RotatingLog::open(&path, 0).unwrap()));
+        RotatingLog::pump(log, io::Cursor::new(b"hello from the session".to_vec()));
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while read(&path) != "hello from the session" {
+            assert!(std::time::Instant::now() < deadline);
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+}
@@ -1,5 +1,53 @@
-use std::process::{Command, Child};
+use std::ffi::OsString;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Child, ExitStatus, Stdio};
 use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use config::HDMConfig;
+use crate::hooks::{HookEnv, Hooks};
+use crate::process_group::{self, StopReport};
+use crate::session_log::{RotatingLog, SessionOutput};
+use crate::session_policy::UserIdentity;
+use crate::systemd_scope::{scope_unit_name, BusctlBus, ScopeGate, SystemdBus};
+use crate::workspace_snapshot;
+
+/// PATH of a fresh login, the rest of the daemon's environment is not passed on
+const LOGIN_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// How the session process is launched
+#[derive(Debug, Clone)]
+pub enum LaunchMode {
+    /// Plain child process in its own process group
+    Direct,
+    /// Child process moved into a transient systemd scope unit
+    SystemdScope(Arc<dyn SystemdBus>),
+    /// Scope in the session user's own systemd instance, reached on their bus as them
+    UserScope,
+}
+
+impl LaunchMode {
+    /// Build the launch mode from `session_launch_mode` in the config
+    pub fn from_config(config: &HDMConfig) -> Self {
+        match config.session_launch_mode.as_str() {
+            "scope" => LaunchMode::SystemdScope(Arc::new(BusctlBus::system())),
+            "user-scope" => LaunchMode::UserScope,
+            _ => LaunchMode::Direct,
+        }
+    }
+}
+
+/// What a session runs: the parts of a session taken from its desktop entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionProgram {
+    pub name: String,
+    pub exec: String,
+    pub args: Vec<String>,
+    pub desktop_id: Option<String>,
+    pub uwsm: bool,
+}
 
 /// Represents a Wayland session
 #[derive(Debug)]
@@ -7,6 +55,23 @@ pub struct Session {
     pub name: String,
     pub exec: String,
     pub child: Option<Child>,
+    pub output: SessionOutput,
+    pub started_at: Option<Instant>,
+    pub stop_grace: Duration,
+    pub user: Option<String>,
+    /// Switch to this account before exec; None keeps the caller's uid and environment
+    pub identity: Option<UserIdentity>,
+    /// Variables from the PAM session, applied on top of the login environment
+    pub pam_env: Vec<(OsString, OsString)>,
+    pub launch_mode: LaunchMode,
+    pub scope: Option<String>,
+    pub vt: Option<u32>,
+    pub args: Vec<String>,
+    pub desktop_id: Option<String>,
+    pub uwsm: bool,
+    pub hooks: Hooks,
+    /// Save the user's workspace layout before stopping the session
+    pub snapshot_layout: bool,
 }
 
 impl Session {
@@ -16,6 +81,176 @@ impl Session {
             name: name.to_string(),
             exec: exec.to_string(),
             child: None,
+            output: Self::default_output(),
+            started_at: None,
+            stop_grace: Duration::from_secs(5),
+            user: None,
+            identity: None,
+            pam_env: Vec::new(),
+            launch_mode: LaunchMode::Direct,
+            scope: None,
+            vt: None,
+            args: Vec::new(),
+            desktop_id: None,
+            uwsm: false,
+            hooks: Hooks::default(),
+            snapshot_layout: false,
+        }
+    }
+
+    /// Set where the session's stdout/stderr goes
+    pub fn with_output(mut self, output: SessionOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Set the user the session belongs to
+    pub fn with_user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Run the session as `identity`, with HOME, USER and XDG_RUNTIME_DIR from its passwd entry
+    pub fn with_identity(mut self, identity: UserIdentity) -> Self {
+        self.user = Some(identity.name.clone());
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Pass the environment of the user's PAM session to the session process
+    pub fn with_pam_env(mut self, env: Vec<(OsString, OsString)>) -> Self {
+        self.pam_env = env;
+        self
+    }
+
+    /// The program this session runs
+    pub fn program(&self) -> SessionProgram {
+        SessionProgram {
+            name: self.name.clone(),
+            exec: self.exec.clone(),
+            args: self.args.clone(),
+            desktop_id: self.desktop_id.clone(),
+            uwsm: self.uwsm,
+        }
+    }
+
+    /// Run `program` from now on, the user, environment and launch settings stay
+    pub fn set_program(&mut self, program: SessionProgram) {
+        self.name = program.name;
+        self.exec = program.exec;
+        self.args = program.args;
+        self.desktop_id = program.desktop_id;
+        self.uwsm = program.uwsm;
+    }
+
+    /// Set how the session process is launched
+    pub fn with_launch_mode(mut self, mode: LaunchMode) -> Self {
+        self.launch_mode = mode;
+        self
+    }
+
+    /// Set the VT the session runs on, exported as XDG_VTNR
+    pub fn with_vt(mut self, vt: u32) -> Self {
+        self.vt = Some(vt);
+        self
+    }
+
+    /// Whether the desktop entry itself already starts the compositor through uwsm
+    pub fn is_uwsm_managed(&self) -> bool {
+        Path::new(&self.exec).file_name().and_then(|n| n.to_str()) == Some("uwsm")
+    }
+
+    /// Whether the session runs under uwsm, either by its own entry or by wrapping
+    pub fn uses_uwsm(&self) -> bool {
+        self.uwsm || self.is_uwsm_managed()
+    }
+
+    /// Full command line the session is launched with
+    pub fn command_line(&self) -> Vec<String> {
+        let mut argv = vec![self.exec.clone()];
+        argv.extend(self.args.iter().cloned());
+
+        if self.uwsm && !self.is_uwsm_managed() {
+            let mut wrapped = vec!["uwsm".to_string(), "start".to_string(), "--".to_string()];
+            match &self.desktop_id {
+                Some(id) => wrapped.push(format!("{}.desktop", id)),
+                None => wrapped.extend(argv),
+            }
+            argv = wrapped;
+        }
+
+        if let Some(wrapper) = &self.hooks.session_wrapper {
+            argv.insert(0, wrapper.clone());
+        }
+
+        if let SessionOutput::Journald = self.output {
+            let mut journald = vec![
+                "systemd-cat".to_string(),
+                "-t".to_string(),
+                self.name.clone(),
+                "--".to_string(),
+            ];
+            journald.extend(argv);
+            argv = journald;
+        }
+
+        argv
+    }
+
+    /// Environment handed to the hook scripts
+    pub fn hook_env(&self) -> HookEnv {
+        HookEnv {
+            user: self.user.clone()
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_default(),
+            session_id: self.desktop_id.clone().unwrap_or_else(|| self.name.clone()),
+            session_name: self.name.clone(),
+            vt: self.vt,
+        }
+    }
+
+    /// Name of the systemd scope unit for this session
+    pub fn scope_unit(&self) -> String {
+        let user = self.user.clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        scope_unit_name(&user, &self.name)
+    }
+
+    /// Bus of the systemd instance the session's scope lives in, None without a scope
+    fn systemd_bus(&self) -> Option<Arc<dyn SystemdBus>> {
+        match &self.launch_mode {
+            LaunchMode::Direct => None,
+            LaunchMode::SystemdScope(bus) => Some(bus.clone()),
+            LaunchMode::UserScope => Some(match &self.identity {
+                Some(identity) => Arc::new(BusctlBus::for_user(identity.uid, identity.gid)),
+                None => Arc::new(BusctlBus::user()),
+            }),
+        }
+    }
+
+    /// Apply the session related settings from the config
+    pub fn with_config(mut self, config: &HDMConfig, home: &Path) -> Self {
+        self.output = SessionOutput::from_config(config, home);
+        self.stop_grace = Duration::from_secs(config.session_stop_grace_secs);
+        self.launch_mode = LaunchMode::from_config(config);
+        self.uwsm = config.uwsm_wraps(self.desktop_id.as_deref(), &self.name);
+        self.hooks = Hooks::from_config(config);
+        self.snapshot_layout = config.workspace_snapshot;
+        self
+    }
+
+    /// The caller's own log; root has no home of its own to log into, a session started
+    /// by the daemon gets the user's log from `with_config`
+    fn default_output() -> SessionOutput {
+        if unsafe { libc::geteuid() } == 0 {
+            return SessionOutput::Null;
+        }
+        match std::env::var("HOME") {
+            Ok(home) if !home.is_empty() => {
+                SessionOutput::from_config(&HDMConfig::default(), Path::new(&home))
+            }
+            _ => SessionOutput::Null,
         }
     }
 
@@ -25,24 +260,198 @@ impl Session {
             return Err("Session is already running".into());
         }
 
-        let child = Command::new(&self.exec)
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-            .map_err(|e| format!("Session start error: {}", e))?;
+        // A failing setup script refuses the login
+        self.hooks.run_session_setup(&self.hook_env())?;
+
+        let argv = self.command_line();
+        let bus = self.systemd_bus();
+        // With a scope the child waits before exec until it has been moved into it
+        let gate = match &bus {
+            Some(_) => match ScopeGate::new() {
+                Ok(gate) => Some(gate),
+                Err(e) => {
+                    self.run_cleanup();
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+        let mut cmd = match &gate {
+            Some(gate) => gate.command(&argv),
+            None => {
+                let mut cmd = Command::new(&argv[0]);
+                cmd.args(&argv[1..]);
+                cmd
+            }
+        };
+
+        // Own process group, so stop can signal everything the session spawned
+        cmd.process_group(0);
+        if let Some(identity) = &self.identity {
+            login_as(&mut cmd, identity, &self.pam_env);
+        }
+        if let Some(vt) = self.vt {
+            cmd.env("XDG_VTNR", vt.to_string());
+        }
+
+        let log = match &self.output {
+            SessionOutput::File { path, max_size } => {
+                cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+                let owner = self.identity.as_ref().map(|i| (i.uid, i.gid));
+                match RotatingLog::open_as(path, *max_size, owner) {
+                    Ok(log) => Some(Arc::new(Mutex::new(log))),
+                    Err(e) => {
+                        self.run_cleanup();
+                        return Err(e);
+                    }
+                }
+            }
+            _ => {
+                cmd.stdout(Stdio::null()).stderr(Stdio::null());
+                None
+            }
+        };
+
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                self.run_cleanup();
+                return Err(format!("Session start error: {}", e));
+            }
+        };
+
+        if let Some(log) = log {
+            if let Some(stdout) = child.stdout.take() {
+                RotatingLog::pump(log.clone(), stdout);
+            }
+            if let Some(stderr) = child.stderr.take() {
+                RotatingLog::pump(log, stderr);
+            }
+        }
+
+        if let (Some(bus), Some(gate)) = (bus, gate) {
+            let unit = self.scope_unit();
+            let description = format!("HyprDM session {}", self.name);
+            match bus.start_transient_scope(&unit, &description, &[child.id()], self.stop_grace) {
+                Ok(()) => self.scope = Some(unit),
+                Err(e) => eprintln!("Failed to create scope {}, running without it: {}", unit, e),
+            }
+            gate.release();
+        }
 
         self.child = Some(child);
+        self.started_at = Some(Instant::now());
         Ok(())
     }
 
+    /// Check whether the session process has exited, without blocking
+    pub fn try_wait(&mut self) -> Option<ExitStatus> {
+        let status = self.child.as_mut()?.try_wait().ok()??;
+        self.child = None;
+        self.scope = None;
+        self.run_cleanup();
+        Some(status)
+    }
+
+    /// How long the current session process has been running
+    pub fn uptime(&self) -> Option<Duration> {
+        self.started_at.map(|t| t.elapsed())
+    }
+
     /// Stop the session safely
     pub fn stop(&mut self) -> Result<(), String> {
-        if let Some(mut child) = self.child.take() {
-            // Kill the process and wait for it to exit
-            child.kill().map_err(|e| format!("Session stop error: {}", e))?;
-            child.wait().map_err(|e| format!("Failed to wait for child: {}", e))?;
+        self.stop_graceful(self.stop_grace).map(|_| ())
+    }
+
+    /// Send SIGTERM to the session's process group, wait up to `grace`,
+    /// then SIGKILL whatever is left
+    pub fn stop_graceful(&mut self, grace: Duration) -> Result<StopReport, String> {
+        let mut report = StopReport::default();
+        let mut child = match self.child.take() {
+            Some(c) => c,
+            None => return Ok(report),
+        };
+        self.started_at = None;
+
+        let pgid = child.id();
+        report.terminated = process_group::members(pgid);
+
+        // Hyprland has to be asked before it goes away
+        if self.snapshot_layout {
+            if let Some(user) = &self.user {
+                match workspace_snapshot::save_for_user(user) {
+                    Ok(path) => println!("[HyprDM] Saved workspace layout to {:?}", path),
+                    Err(e) => eprintln!("[HyprDM] Workspace layout not saved: {}", e),
+                }
+            }
+        }
+
+        if !self.request_stop() {
+            process_group::signal(pgid, libc::SIGTERM)
+                .map_err(|e| format!("Session stop error: {}", e))?;
+        }
+
+        let deadline = Instant::now() + grace;
+        loop {
+            // Reap the leader so it doesn't linger as a zombie in the group
+            let _ = child.try_wait();
+            if process_group::members(pgid).is_empty() || Instant::now() >= deadline {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        report.killed = process_group::members(pgid);
+        if !report.killed.is_empty() {
+            process_group::signal(pgid, libc::SIGKILL)
+                .map_err(|e| format!("Session stop error: {}", e))?;
+        }
+
+        child.wait().map_err(|e| format!("Failed to wait for child: {}", e))?;
+        std::thread::sleep(Duration::from_millis(100));
+        report.survivors = process_group::members(pgid);
+        self.run_cleanup();
+
+        Ok(report)
+    }
+
+    fn run_cleanup(&self) {
+        if let Err(e) = self.hooks.run_session_cleanup(&self.hook_env()) {
+            eprintln!("{}", e);
+        }
+    }
+
+    /// `uwsm stop` talks to the session user's systemd, so it runs as them
+    fn uwsm_stop_command(&self) -> Command {
+        let mut cmd = Command::new("uwsm");
+        cmd.arg("stop").stdout(Stdio::null()).stderr(Stdio::null());
+        if let Some(identity) = &self.identity {
+            login_as(&mut cmd, identity, &self.pam_env);
+        }
+        cmd
+    }
+
+    /// Ask uwsm or systemd to stop the session, false if neither is in charge of it
+    fn request_stop(&mut self) -> bool {
+        if self.uses_uwsm() {
+            match self.uwsm_stop_command().status() {
+                Ok(status) if status.success() => return true,
+                Ok(status) => eprintln!("uwsm stop exited with {}", status),
+                Err(e) => eprintln!("Failed to run uwsm stop: {}", e),
+            }
+        }
+
+        // With a scope systemd terminates the whole cgroup
+        match (self.scope.take(), self.systemd_bus()) {
+            (Some(unit), Some(bus)) => match bus.stop_unit(&unit) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Failed to stop scope {}: {}", unit, e);
+                    false
+                }
+            },
+            _ => false,
         }
-        Ok(())
     }
 
     /// Restart the session
@@ -63,13 +472,17 @@ impl Session {
 
     /// Read all available Wayland session desktop files from the system
     pub fn available_sessions() -> Vec<Session> {
-        let mut sessions = Vec::new();
-        let paths = vec![
-            "/usr/share/wayland-sessions",
-            "/usr/share/wayland-session",
-        ];
+        Self::sessions_in(&[
+            Path::new("/usr/share/wayland-sessions"),
+            Path::new("/usr/share/wayland-session"),
+        ])
+    }
 
-        for path in paths {
+    /// Sessions from the desktop files in `dirs`, sorted by name; an earlier dir wins a
+    /// desktop ID clash
+    fn sessions_in(dirs: &[&Path]) -> Vec<Session> {
+        let mut sessions = Vec::new();
+        for path in dirs {
             if let Ok(entries) = fs::read_dir(path) {
                 for entry in entries.flatten() {
                     let path = entry.path();
@@ -94,8 +507,16 @@ impl Session {
 
                             if !name.is_empty() && !exec.is_empty() && !hidden {
                                 // Clean up %U, %u, %F placeholders
-                                exec = exec.split_whitespace().next().unwrap_or("").to_string();
-                                sessions.push(Session::new(&name, &exec));
+                                let mut argv = split_exec(&exec);
+                                if argv.is_empty() {
+                                    continue;
+                                }
+                                let program = argv.remove(0);
+                                let mut session = Session::new(&name, &program);
+                                session.args = argv;
+                                session.desktop_id = path.file_stem()
+                                    .map(|s| s.to_string_lossy().to_string());
+                                sessions.push(session);
                             }
                         }
                     }
@@ -103,6 +524,456 @@ impl Session {
             }
         }
 
+        // read_dir order is arbitrary; sort by name and keep the first entry per desktop ID
+        let mut seen = std::collections::HashSet::new();
+        sessions.retain(|s| match &s.desktop_id {
+            Some(id) => seen.insert(id.clone()),
+            None => true,
+        });
+        sessions.sort_by(|a, b| {
+            a.name.to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.desktop_id.cmp(&b.desktop_id))
+        });
+        sessions
+    }
+
+    /// Find a session by desktop-file ID, exact Name, then case-insensitive Name
+    pub fn find_in<'a>(sessions: &'a [Session], query: &str) -> Option<&'a Session> {
+        let query = query.trim().trim_end_matches(".desktop");
+        sessions.iter()
+            .find(|s| s.desktop_id.as_deref() == Some(query))
+            .or_else(|| sessions.iter().find(|s| s.name == query))
+            .or_else(|| sessions.iter().find(|s| s.name.eq_ignore_ascii_case(query)))
+    }
+
+    /// Resolve `default_session`, walking `default_session_fallbacks`, then
+    /// "hyprland", then the first available session
+    pub fn resolve_default(sessions: &[Session], config: &HDMConfig) -> Option<DefaultSession> {
+        let mut chain = vec![config.default_session.clone()];
+        chain.extend(config.default_session_fallbacks.iter().cloned());
+        chain.push("hyprland".to_string());
+
+        for (i, query) in chain.iter().enumerate() {
+            if let Some(session) = Self::find_in(sessions, query) {
+                let fallback_reason = (i > 0).then(|| format!(
+                    "default_session '{}' not found, using '{}' from the fallback chain",
+                    config.default_session, query
+                ));
+                return Some(DefaultSession {
+                    name: session.name.clone(),
+                    desktop_id: session.desktop_id.clone(),
+                    fallback_reason,
+                });
+            }
+        }
+
+        sessions.first().map(|s| DefaultSession {
+            name: s.name.clone(),
+            desktop_id: s.desktop_id.clone(),
+            fallback_reason: Some(format!(
+                "default_session '{}' and its fallbacks not found, using first available '{}'",
+                config.default_session, s.name
+            )),
+        })
+    }
+
+    /// Move the resolved default session to the front, printing a diagnostic
+    /// when the configured default had to be replaced
+    pub fn order_with_default(mut sessions: Vec<Session>, config: &HDMConfig) -> Vec<Session> {
+        let default = match Self::resolve_default(&sessions, config) {
+            Some(d) => d,
+            None => {
+                eprintln!("No sessions available, default_session '{}' cannot be used", config.default_session);
+                return sessions;
+            }
+        };
+        if let Some(reason) = &default.fallback_reason {
+            eprintln!("{}", reason);
+        }
+
+        if let Some(pos) = sessions.iter().position(|s| default.matches(s)) {
+            let session = sessions.remove(pos);
+            sessions.insert(0, session);
+        }
         sessions
     }
 }
+
+/// Start `cmd` as `identity` with a login environment: passwd values first, the PAM
+/// session's variables on top
+fn login_as(cmd: &mut Command, identity: &UserIdentity, pam_env: &[(OsString, OsString)]) {
+    let home = if identity.home.is_dir() { identity.home.as_path() } else { Path::new("/") };
+    cmd.env_clear()
+        .env("PATH", LOGIN_PATH)
+        .env("HOME", &identity.home)
+        .env("USER", &identity.name)
+        .env("LOGNAME", &identity.name)
+        .env("SHELL", &identity.shell)
+        .env("XDG_RUNTIME_DIR", identity.runtime_dir())
+        .env("DBUS_SESSION_BUS_ADDRESS", format!("unix:path={}/bus", identity.runtime_dir().display()))
+        .env("XDG_SESSION_TYPE", "wayland")
+        .env("XDG_SESSION_CLASS", "user")
+        .envs(pam_env.iter().map(|(key, value)| (key, value)))
+        .current_dir(home);
+    if let Ok(lang) = std::env::var("LANG") {
+        cmd.env("LANG", lang);
+    }
+
+    // std would drop the supplementary groups, so switch by hand
+    let identity = identity.clone();
+    unsafe { cmd.pre_exec(move || identity.switch_process()) };
+}
+
+/// Outcome of resolving `default_session` against the available sessions
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefaultSession {
+    pub name: String,
+    pub desktop_id: Option<String>,
+    /// Set when the configured default was missing and a fallback was used
+    pub fallback_reason: Option<String>,
+}
+
+impl DefaultSession {
+    pub fn matches(&self, session: &Session) -> bool {
+        match &self.desktop_id {
+            Some(id) => session.desktop_id.as_ref() == Some(id),
+            None => session.name == self.name,
+        }
+    }
+}
+
+/// Split a desktop entry Exec value into arguments, dropping field codes like %U
+pub(crate) fn split_exec(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            '\\' if quoted => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+
+    args.into_iter()
+        .filter(|a| !(a.len() == 2 && a.starts_with('%')))
+        .map(|a| a.replace("%%", "%"))
+        .collect()
+}
+
+/// Quote an argument for `sh -c`, leaving plain words alone
+pub(crate) fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn wait_exit(session: &mut Session) -> ExitStatus {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(status) = session.try_wait() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "session did not exit");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn shell(name: &str, script: &str) -> Session {
+        let mut session = Session::new(name, "/bin/sh").with_output(SessionOutput::Null);
+        session.args = vec!["-c".into(), script.into()];
+        session
+    }
+
+    #[test]
+    fn graceful_stop_terminates_the_whole_group() {
+        let mut session = shell("stop", "sleep 30 & sleep 30 & wait");
+        session.start().unwrap();
+        let pgid = session.child.as_ref().unwrap().id();
+        while process_group::members(pgid).len() < 3 {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let report = session.stop_graceful(Duration::from_secs(5)).unwrap();
+        assert_eq!(report.terminated.len(), 3);
+        assert!(report.killed.is_empty());
+        assert!(report.survivors.is_empty());
+        assert!(session.child.is_none());
+    }
+
+    #[test]
+    fn members_ignoring_sigterm_are_killed_after_the_grace_period() {
+        let mut session = shell("stubborn", "trap '' TERM; sleep 30 & trap '' TERM; wait; sleep 30");
+        session.start().unwrap();
+        let pgid = session.child.as_ref().unwrap().id();
+        while process_group::members(pgid).len() < 2 {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let started = Instant::now();
+        let report = session.stop_graceful(Duration::from_millis(300)).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(report.killed.contains(&pgid));
+        assert!(report.survivors.is_empty());
+    }
+
+    #[test]
+    fn stopping_a_session_that_is_not_running_is_a_no_op() {
+        let mut session = shell("idle", "true");
+        assert_eq!(session.stop_graceful(Duration::from_secs(1)), Ok(StopReport::default()));
+    }
+
+    #[test]
+    fn session_runs_as_its_identity_with_a_login_environment() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("skipped, switching users needs root");
+            return;
+        }
+        let dir = TempDir::new("identity");
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+        let out = dir.join("env");
+
+        let mut identity = UserIdentity::lookup("nobody").unwrap();
+        identity.groups = vec![identity.gid, 4242];
+        let script = format!(
+            "echo $(id -u) $(id -g) $(id -G) $HOME $USER $XDG_RUNTIME_DIR $XDG_SESSION_ID $DAEMON_ONLY > {}",
+            out.display()
+        );
+        std::env::set_var("DAEMON_ONLY", "leaked");
+        let mut session = shell("identity", &script)
+            .with_identity(identity.clone())
+            .with_pam_env(vec![("XDG_SESSION_ID".into(), "c7".into())]);
+        session.start().unwrap();
+        assert!(wait_exit(&mut session).success());
+
+        let seen = fs::read_to_string(&out).unwrap();
+        let expected = format!(
+            "{uid} {gid} {gid} 4242 {home} nobody /run/user/{uid} c7",
+            uid = identity.uid,
+            gid = identity.gid,
+            home = identity.home.display()
+        );
+        assert_eq!(seen.trim(), expected);
+        assert_eq!(session.user.as_deref(), Some("nobody"));
+    }
+
+    #[test]
+    fn scope_is_created_before_the_session_execs_and_stops_through_the_unit() {
+        use crate::systemd_scope::{fake::FakeSystemd, GATE_NAME};
+        let bus = Arc::new(FakeSystemd::default());
+        let mut session = shell("scoped", "sleep 30 & wait")
+            .with_launch_mode(LaunchMode::SystemdScope(bus.clone()));
+        session.user = Some("alice".into());
+        session.stop_grace = Duration::from_secs(7);
+        session.start().unwrap();
+
+        let pid = session.child.as_ref().unwrap().id();
+        assert_eq!(session.scope.as_deref(), Some("hyprdm-session-alice-scoped.scope"));
+        assert_eq!(
+            bus.calls.lock().unwrap()[0],
+            format!("start hyprdm-session-alice-scoped.scope \"HyprDM session scoped\" [{}] 7", pid)
+        );
+        assert!(bus.cmdlines.lock().unwrap()[0].contains(GATE_NAME));
+
+        // Released, the gate shell execs the session in place
+        while process_group::members(pid).len() < 2 {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let report = session.stop_graceful(Duration::from_secs(5)).unwrap();
+        assert_eq!(bus.calls.lock().unwrap()[1], "stop hyprdm-session-alice-scoped.scope");
+        assert!(report.killed.is_empty());
+        assert!(session.scope.is_none());
+    }
+
+    #[test]
+    fn session_still_runs_when_the_scope_cannot_be_created() {
+        use crate::systemd_scope::fake::FakeSystemd;
+        let bus = Arc::new(FakeSystemd { fail: Some("start"), ..Default::default() });
+        let mut session = shell("unscoped", "exit 3")
+            .with_launch_mode(LaunchMode::SystemdScope(bus.clone()));
+        session.start().unwrap();
+
+        assert!(session.scope.is_none());
+        assert_eq!(wait_exit(&mut session).code(), Some(3));
+    }
+
+    #[test]
+    fn user_scope_talks_to_the_session_users_bus() {
+        let config = HDMConfig { session_launch_mode: "user-scope".into(), ..Default::default() };
+        let mut session = Session::new("x", "true");
+        session.launch_mode = LaunchMode::from_config(&config);
+        session.identity = Some(UserIdentity {
+            name: "alice".into(),
+            uid: 1000,
+            gid: 100,
+            groups: vec![100],
+            home: "/home/alice".into(),
+            shell: "/bin/sh".into(),
+        });
+
+        let bus = format!("{:?}", session.systemd_bus().unwrap());
+        assert!(bus.contains("unix:path=/run/user/1000/bus"), "{}", bus);
+        assert!(bus.contains("run_as: Some((1000, 100))"), "{}", bus);
+        assert!(Session::new("x", "true").systemd_bus().is_none());
+    }
+
+    #[test]
+    fn uwsm_stop_runs_in_the_session_users_login_environment() {
+        let mut session = Session::new("Hyprland", "Hyprland").with_identity(UserIdentity {
+            name: "alice".into(),
+            uid: 1000,
+            gid: 100,
+            groups: vec![100],
+            home: "/nonexistent".into(),
+            shell: "/bin/sh".into(),
+        });
+        session.uwsm = true;
+        assert!(session.uses_uwsm());
+
+        let cmd = session.uwsm_stop_command();
+        let env: Vec<_> = cmd.get_envs()
+            .map(|(k, v)| (k.to_string_lossy().to_string(), v.map(|v| v.to_string_lossy().to_string())))
+            .collect();
+        assert!(env.contains(&("USER".into(), Some("alice".into()))));
+        assert!(env.contains(&("DBUS_SESSION_BUS_ADDRESS".into(), Some("unix:path=/run/user/1000/bus".into()))));
+        assert_eq!(cmd.get_current_dir(), Some(Path::new("/")));
+    }
+
+    #[test]
+    fn set_program_replaces_everything_the_command_line_is_built_from() {
+        let mut session = Session::new("Broken", "broken-wm").with_output(SessionOutput::Null);
+        session.args = vec!["--bad".into()];
+        session.uwsm = true;
+        session.desktop_id = Some("broken".into());
+        session.set_program(SessionProgram {
+            name: "Sway".into(),
+            exec: "sway".into(),
+            args: vec!["-d".into()],
+            desktop_id: None,
+            uwsm: false,
+        });
+        assert_eq!(session.command_line(), ["sway", "-d"]);
+        assert_eq!(session.name, "Sway");
+    }
+
+    #[test]
+    fn cleanup_runs_when_the_session_log_cannot_be_opened() {
+        let dir = TempDir::new("session-log-fail");
+        let marker = dir.join("cleaned");
+        let cleanup = dir.write("cleanup", &format!("#!/bin/sh\ntouch {}\n", marker.display()));
+        fs::set_permissions(&cleanup, fs::Permissions::from_mode(0o755)).unwrap();
+        dir.write("file", "");
+
+        let mut session = shell("log", "exit 0")
+            .with_output(SessionOutput::File { path: dir.join("file/session.log"), max_size: 1024 });
+        session.hooks.session_cleanup = Some(cleanup.to_string_lossy().to_string());
+        session.hooks.timeout = Duration::from_secs(10);
+
+        assert!(session.start().is_err());
+        assert!(session.child.is_none());
+        assert!(marker.exists());
+    }
+
+    fn entry(name: &str, id: &str) -> Session {
+        let mut session = Session::new(name, "true");
+        session.desktop_id = Some(id.into());
+        session
+    }
+
+    fn default_config(default: &str, fallbacks: &[&str]) -> HDMConfig {
+        HDMConfig {
+            default_session: default.into(),
+            default_session_fallbacks: fallbacks.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn desktop_files_are_sorted_deduplicated_and_split() {
+        let dir = TempDir::new("sessions-in");
+        dir.write("a/sway.desktop", "[Desktop Entry]\nName=sway\nExec=sway --unsupported-gpu %U\n");
+        dir.write("a/hyprland.desktop", "[Desktop Entry]\nName=Hyprland\nExec=\"/opt/hypr land/Hyprland\"\n");
+        dir.write("a/hidden.desktop", "[Desktop Entry]\nName=Hidden\nExec=x\nNoDisplay=true\n");
+        dir.write("a/notes.txt", "Name=Nope\nExec=nope\n");
+        dir.write("b/sway.desktop", "[Desktop Entry]\nName=Sway (other)\nExec=other\n");
+        dir.write("b/cage.desktop", "[Desktop Entry]\nName=Cage\nExec=cage -- app\n");
+
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        let sessions = Session::sessions_in(&[&a, &b]);
+        let names: Vec<_> = sessions.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Cage", "Hyprland", "sway"]);
+
+        assert_eq!(sessions[1].exec, "/opt/hypr land/Hyprland");
+        assert_eq!(sessions[2].exec, "sway");
+        assert_eq!(sessions[2].args, ["--unsupported-gpu"]);
+        assert_eq!(sessions[2].desktop_id.as_deref(), Some("sway"));
+    }
+
+    #[test]
+    fn lookup_prefers_desktop_id_then_exact_then_case_insensitive_name() {
+        let sessions = [entry("hyprland", "other"), entry("Hyprland", "hyprland-uwsm"), entry("Sway", "hyprland")];
+        assert_eq!(Session::find_in(&sessions, "hyprland.desktop").unwrap().name, "Sway");
+        assert_eq!(Session::find_in(&sessions, "Hyprland").unwrap().desktop_id.as_deref(), Some("hyprland-uwsm"));
+        assert_eq!(Session::find_in(&sessions, "SWAY").unwrap().name, "Sway");
+        assert!(Session::find_in(&sessions, "kde").is_none());
+    }
+
+    #[test]
+    fn default_walks_the_fallback_chain_with_a_diagnostic() {
+        let sessions = [entry("Cage", "cage"), entry("Sway", "sway")];
+
+        let found = Session::resolve_default(&sessions, &default_config("sway", &[])).unwrap();
+        assert_eq!((found.name.as_str(), found.fallback_reason), ("Sway", None));
+
+        let fallback = Session::resolve_default(&sessions, &default_config("kde", &["gnome", "Sway"])).unwrap();
+        assert_eq!(fallback.name, "Sway");
+        assert!(fallback.fallback_reason.unwrap().contains("'kde' not found"));
+
+        let first = Session::resolve_default(&sessions, &default_config("kde", &[])).unwrap();
+        assert_eq!(first.name, "Cage");
+        assert!(first.fallback_reason.unwrap().contains("first available"));
+
+        assert!(Session::resolve_default(&[], &default_config("kde", &[])).is_none());
+    }
+
+    #[test]
+    fn the_default_is_moved_to_the_front() {
+        let sessions = vec![entry("Cage", "cage"), entry("Hyprland", "hyprland"), entry("Sway", "sway")];
+        let ordered = Session::order_with_default(sessions, &default_config("missing", &[]));
+        let names: Vec<_> = ordered.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Hyprland", "Cage", "Sway"]);
+    }
+}
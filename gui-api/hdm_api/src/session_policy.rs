@@ -0,0 +1,345 @@
+use std::ffi::{CStr, CString};
+use std::io;
+use std::path::PathBuf;
+
+use config::HDMConfig;
+use crate::session_manager::Session;
+
+/// Passwd entry and groups a session process is switched to
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserIdentity {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups, including the primary one
+    pub groups: Vec<u32>,
+    pub home: PathBuf,
+    pub shell: PathBuf,
+}
+
+impl UserIdentity {
+    pub fn lookup(name: &str) -> Result<Self, String> {
+        let c_user = CString::new(name).map_err(|_| format!("Invalid user name: {}", name))?;
+        unsafe {
+            let pw = libc::getpwnam(c_user.as_ptr());
+            if pw.is_null() {
+                return Err(format!("User {} does not exist", name));
+            }
+            let (uid, gid) = ((*pw).pw_uid, (*pw).pw_gid);
+            let home = CStr::from_ptr((*pw).pw_dir).to_string_lossy().to_string();
+            let shell = CStr::from_ptr((*pw).pw_shell).to_string_lossy().to_string();
+            Ok(Self {
+                name: name.to_string(),
+                uid,
+                gid,
+                groups: group_ids(&c_user, gid),
+                home: PathBuf::from(home),
+                shell: PathBuf::from(if shell.is_empty() { "/bin/sh".to_string() } else { shell }),
+            })
+        }
+    }
+
+    /// Where logind creates the user's runtime directory
+    pub fn runtime_dir(&self) -> PathBuf {
+        PathBuf::from(format!("/run/user/{}", self.uid))
+    }
+
+    /// Switch the calling process to this user: groups first, then gid, then uid.
+    /// Only async-signal-safe calls, so it can run between fork and exec
+    pub fn switch_process(&self) -> io::Result<()> {
+        unsafe {
+            if libc::setgroups(self.groups.len() as _, self.groups.as_ptr()) != 0
+                || libc::setgid(self.gid) != 0
+                || libc::setuid(self.uid) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs file access in this thread as another user, so root never follows links in a
+/// user's home. The filesystem uid is per thread and does not touch the daemon's other threads.
+pub(crate) struct FsUser {
+    uid: u32,
+    gid: u32,
+}
+
+impl FsUser {
+    pub(crate) fn switch(uid: u32, gid: u32) -> Option<Self> {
+        if unsafe { libc::geteuid() } != 0 || uid == 0 {
+            return None;
+        }
+        unsafe {
+            let gid_before = libc::setfsgid(gid) as u32;
+            let uid_before = libc::setfsuid(uid) as u32;
+            Some(Self { uid: uid_before, gid: gid_before })
+        }
+    }
+}
+
+impl Drop for FsUser {
+    fn drop(&mut self) {
+        unsafe {
+            libc::setfsuid(self.uid);
+            libc::setfsgid(self.gid);
+        }
+    }
+}
+
+/// Group ids of `user` through getgrouplist
+fn group_ids(user: &CStr, primary: libc::gid_t) -> Vec<libc::gid_t> {
+    let mut count: libc::c_int = 32;
+    let mut gids: Vec<libc::gid_t> = vec![0; count as usize];
+    unsafe {
+        if libc::getgrouplist(user.as_ptr(), primary, gids.as_mut_ptr(), &mut count) < 0 {
+            // Buffer too small, count now holds the required size
+            gids = vec![0; count as usize];
+            if libc::getgrouplist(user.as_ptr(), primary, gids.as_mut_ptr(), &mut count) < 0 {
+                return vec![primary];
+            }
+        }
+    }
+    gids.truncate(count as usize);
+    gids
+}
+
+/// Names of all groups `user` belongs to, including the primary group
+pub fn user_groups(user: &str) -> Vec<String> {
+    let c_user = match CString::new(user) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut names = Vec::new();
+    unsafe {
+        let pw = libc::getpwnam(c_user.as_ptr());
+        if pw.is_null() {
+            return names;
+        }
+
+        for gid in group_ids(&c_user, (*pw).pw_gid) {
+            let gr = libc::getgrgid(gid);
+            if !gr.is_null() {
+                names.push(CStr::from_ptr((*gr).gr_name).to_string_lossy().to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Home directory of `user` from the passwd database
+pub fn user_home(user: &str) -> Option<PathBuf> {
+    let c_user = CString::new(user).ok()?;
+    unsafe {
+        let pw = libc::getpwnam(c_user.as_ptr());
+        if pw.is_null() {
+            return None;
+        }
+        let dir = CStr::from_ptr((*pw).pw_dir).to_string_lossy().to_string();
+        Some(PathBuf::from(dir))
+    }
+}
+
+/// Login name for `uid` from the passwd database
+pub fn user_name(uid: u32) -> Option<String> {
+    unsafe {
+        let pw = libc::getpwuid(uid);
+        if pw.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr((*pw).pw_name).to_string_lossy().to_string())
+    }
+}
+
+fn matches(session: &Session, key: &str) -> bool {
+    key == "*" || session.name == key || session.desktop_id.as_deref() == Some(key)
+}
+
+/// Whether a member of `groups` may see and start `session`
+pub fn is_allowed(config: &HDMConfig, groups: &[String], session: &Session) -> bool {
+    if config.session_hide.iter().any(|h| h != "*" && matches(session, h)) {
+        return false;
+    }
+    if config.session_allow.is_empty() {
+        return true;
+    }
+
+    let rules: Vec<&Vec<String>> = groups
+        .iter()
+        .filter_map(|g| config.session_allow.get(g))
+        .collect();
+
+    // Users without a group rule get the "*" rule, or everything if there is none
+    if rules.is_empty() {
+        return match config.session_allow.get("*") {
+            Some(rule) => rule.iter().any(|k| matches(session, k)),
+            None => true,
+        };
+    }
+    rules.iter().any(|rule| rule.iter().any(|k| matches(session, k)))
+}
+
+/// Display name of `session` after applying `session_alias.<id>` rules
+pub fn alias_for(config: &HDMConfig, session: &Session) -> Option<String> {
+    session.desktop_id
+        .as_ref()
+        .and_then(|id| config.session_aliases.get(id))
+        .or_else(|| config.session_aliases.get(&session.name))
+        .cloned()
+}
+
+/// Sessions `user` may see, default first and renamed according to the alias rules
+pub fn sessions_for_user(config: &HDMConfig, user: &str) -> Vec<Session> {
+    let groups = user_groups(user);
+    let visible: Vec<Session> = Session::available_sessions()
+        .into_iter()
+        .filter(|s| is_allowed(config, &groups, s))
+        .collect();
+
+    // Order before renaming so default_session still matches the real names
+    Session::order_with_default(visible, config)
+        .into_iter()
+        .map(|mut s| {
+            if let Some(alias) = alias_for(config, &s) {
+                s.name = alias;
+            }
+            s
+        })
+        .collect()
+}
+
+/// Look up a session by desktop ID, name or alias for `user`, refusing hidden ones
+pub fn find_session_for_user(config: &HDMConfig, user: &str, name: &str) -> Result<Session, String> {
+    let session = find_allowed(config, &user_groups(user), Session::available_sessions(), name)
+        .map_err(|e| format!("{} for {}", e, user))?;
+
+    let mut session = session.with_user(user);
+    if let Some(alias) = alias_for(config, &session) {
+        session.name = alias;
+    }
+    Ok(session)
+}
+
+fn is_named(config: &HDMConfig, session: &Session, name: &str) -> bool {
+    session.name == name
+        || session.desktop_id.as_deref() == Some(name)
+        || alias_for(config, session).as_deref() == Some(name)
+}
+
+/// First session called `name` among those `groups` may start; a hidden session never
+/// shadows a visible one with the same name
+fn find_allowed(config: &HDMConfig, groups: &[String], sessions: Vec<Session>, name: &str) -> Result<Session, String> {
+    let named: Vec<Session> = sessions.into_iter().filter(|s| is_named(config, s, name)).collect();
+    if named.is_empty() {
+        return Err(format!("Session not found: {}", name));
+    }
+    named
+        .into_iter()
+        .find(|s| is_allowed(config, groups, s))
+        .ok_or_else(|| format!("Session '{}' is not available", name))
+}
+
+/// Refuse `session` for `user` when the policy hides it, or hides an installed session
+/// running the same program under another name
+pub fn check_session(config: &HDMConfig, user: &str, session: &Session) -> Result<(), String> {
+    check_against(config, &user_groups(user), &Session::available_sessions(), session)
+        .map_err(|e| format!("{} for {}", e, user))
+}
+
+fn check_against(config: &HDMConfig, groups: &[String], installed: &[Session], session: &Session) -> Result<(), String> {
+    let refused = !is_allowed(config, groups, session)
+        || installed
+            .iter()
+            .any(|s| s.exec == session.exec && !is_allowed(config, groups, s));
+    if refused {
+        return Err(format!("Session '{}' is not available", session.name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(name: &str, id: &str, exec: &str) -> Session {
+        let mut session = Session::new(name, exec);
+        session.desktop_id = Some(id.into());
+        session
+    }
+
+    fn config(hide: &[&str], allow: &[(&str, &[&str])]) -> HDMConfig {
+        HDMConfig {
+            session_hide: hide.iter().map(|s| s.to_string()).collect(),
+            session_allow: allow
+                .iter()
+                .map(|(g, keys)| (g.to_string(), keys.iter().map(|k| k.to_string()).collect()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    fn groups(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn group_rules_limit_and_star_applies_to_everyone_else() {
+        let config = config(&[], &[("gamers", &["gamescope"]), ("*", &["hyprland"])]);
+        let hyprland = entry("Hyprland", "hyprland", "Hyprland");
+        let gamescope = entry("Gamescope", "gamescope", "gamescope");
+
+        assert!(is_allowed(&config, &groups(&["gamers", "users"]), &gamescope));
+        assert!(!is_allowed(&config, &groups(&["gamers"]), &hyprland));
+        assert!(is_allowed(&config, &groups(&["users"]), &hyprland));
+        assert!(!is_allowed(&config, &groups(&["users"]), &gamescope));
+    }
+
+    #[test]
+    fn hidden_sessions_are_refused_by_name_and_desktop_id() {
+        let config = config(&["sway"], &[]);
+        let sway = entry("Sway", "sway", "sway");
+        assert!(!is_allowed(&config, &[], &sway));
+
+        let sessions = || vec![entry("Sway", "sway", "sway"), entry("Hyprland", "hyprland", "Hyprland")];
+        let err = find_allowed(&config, &[], sessions(), "Sway").unwrap_err();
+        assert!(err.contains("not available"), "{}", err);
+        assert!(find_allowed(&config, &[], sessions(), "sway").is_err());
+        assert_eq!(find_allowed(&config, &[], sessions(), "hyprland").unwrap().name, "Hyprland");
+        assert!(find_allowed(&config, &[], sessions(), "kde").unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn a_hidden_entry_does_not_shadow_a_visible_one_with_the_same_name() {
+        let config = config(&["hyprland-debug"], &[]);
+        let sessions = vec![
+            entry("Hyprland", "hyprland-debug", "Hyprland-debug"),
+            entry("Hyprland", "hyprland", "Hyprland"),
+        ];
+        let found = find_allowed(&config, &[], sessions, "Hyprland").unwrap();
+        assert_eq!(found.desktop_id.as_deref(), Some("hyprland"));
+    }
+
+    #[test]
+    fn aliases_find_sessions_but_not_hidden_ones() {
+        let mut config = config(&["sway"], &[]);
+        config.session_aliases.insert("hyprland".into(), "Desktop".into());
+        config.session_aliases.insert("sway".into(), "Tiling".into());
+        let sessions = || vec![entry("Hyprland", "hyprland", "Hyprland"), entry("Sway", "sway", "sway")];
+
+        assert_eq!(find_allowed(&config, &[], sessions(), "Desktop").unwrap().name, "Hyprland");
+        assert!(find_allowed(&config, &[], sessions(), "Tiling").is_err());
+    }
+
+    #[test]
+    fn a_hidden_program_cannot_be_started_under_another_name() {
+        let config = config(&["sway"], &[]);
+        let installed = vec![entry("Sway", "sway", "sway")];
+
+        assert!(check_against(&config, &[], &installed, &Session::new("Anything", "sway")).is_err());
+        assert!(check_against(&config, &[], &installed, &Session::new("sway", "other")).is_err());
+        assert_eq!(check_against(&config, &[], &installed, &Session::new("Other", "other")), Ok(()));
+    }
+}
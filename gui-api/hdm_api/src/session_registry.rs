@@ -0,0 +1,307 @@
+use crate::session_manager::Session;
+use crate::session_policy::UserIdentity;
+use crate::session_worker::SessionWorker;
+use crate::user_manager::PamHandle;
+use crate::vt::{LinuxConsole, VirtualTerminal, VtBackend, VtManager};
+use std::time::Duration;
+
+/// A running session owned by the registry
+pub struct RegisteredSession {
+    pub id: u32,
+    pub user: String,
+    pub session: Session,
+    vt: Option<VirtualTerminal>,
+    /// The process holding the PAM session and running `session`
+    worker: Option<SessionWorker>,
+}
+
+impl RegisteredSession {
+    pub fn vt(&self) -> Option<u32> {
+        self.vt.as_ref().map(|vt| vt.number)
+    }
+}
+
+/// Summary of a live session, e.g. for an "already logged in" list
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub id: u32,
+    pub user: String,
+    pub session_name: String,
+    pub vt: Option<u32>,
+    pub running: bool,
+}
+
+/// Keeps several user sessions alive at once, each on its own VT
+pub struct SessionRegistry<B: VtBackend = LinuxConsole> {
+    sessions: Vec<RegisteredSession>,
+    next_id: u32,
+    vts: VtManager<B>,
+    greeter_vt: Option<VirtualTerminal>,
+}
+
+impl SessionRegistry<LinuxConsole> {
+    pub fn new() -> Self {
+        Self::with_vt_manager(VtManager::new())
+    }
+}
+
+impl Default for SessionRegistry<LinuxConsole> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: VtBackend> SessionRegistry<B> {
+    pub fn with_vt_manager(vts: VtManager<B>) -> Self {
+        Self {
+            sessions: Vec::new(),
+            next_id: 1,
+            vts,
+            greeter_vt: None,
+        }
+    }
+
+    /// Start `session` on a fresh VT as the user `pam` authenticated and register it.
+    /// The PAM session is opened by the session's worker, not here
+    pub fn start(&mut self, pam: PamHandle, session: Session) -> Result<u32, String> {
+        let username = pam.username.clone();
+        if self.find_by_user(&username).is_some() {
+            return Err(format!("{} already has a running session", username));
+        }
+        let identity = UserIdentity::lookup(&username)?;
+        let vt = self.vts.allocate()?;
+        let session = session.with_identity(identity).with_vt(vt.number);
+        self.register(username, Some(pam), session, vt)
+    }
+
+    /// Start `session` on `vt` in its own worker and keep it, or give the VT back if it fails
+    fn register(
+        &mut self,
+        user: String,
+        pam: Option<PamHandle>,
+        mut session: Session,
+        vt: VirtualTerminal,
+    ) -> Result<u32, String> {
+        if self.find_by_user(&user).is_some() {
+            self.vts.release(vt);
+            return Err(format!("{} already has a running session", user));
+        }
+        let worker = match SessionWorker::spawn(pam, &mut session) {
+            Ok(worker) => worker,
+            Err(e) => {
+                self.vts.release(vt);
+                return Err(e);
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sessions.push(RegisteredSession {
+            id,
+            user,
+            session,
+            vt: Some(vt),
+            worker: Some(worker),
+        });
+        Ok(id)
+    }
+
+    /// Open a VT for the greeter while the registered sessions keep running
+    pub fn open_greeter(&mut self) -> Result<u32, String> {
+        if let Some(vt) = &self.greeter_vt {
+            self.vts.switch_to(vt.number)?;
+            return Ok(vt.number);
+        }
+        let vt = self.vts.allocate()?;
+        let number = vt.number;
+        self.greeter_vt = Some(vt);
+        Ok(number)
+    }
+
+    /// Release the greeter's VT
+    pub fn close_greeter(&mut self) {
+        if let Some(vt) = self.greeter_vt.take() {
+            self.vts.release(vt);
+        }
+    }
+
+    /// Switch back to the running session of the user `pam` just re-authenticated.
+    /// No new PAM session is opened, the one from `start` is still alive
+    pub fn switch_to(&mut self, pam: &PamHandle) -> Result<u32, String> {
+        self.switch_to_user(&pam.username)
+    }
+
+    fn switch_to_user(&mut self, user: &str) -> Result<u32, String> {
+        self.reap();
+        let entry = self.sessions
+            .iter()
+            .find(|s| s.user == user)
+            .ok_or_else(|| format!("No running session for {}", user))?;
+
+        let number = entry.vt().ok_or("Session has no VT")?;
+        self.vts.switch_to(number)?;
+        Ok(entry.id)
+    }
+
+    /// Stop a registered session and free its VT
+    pub fn stop(&mut self, id: u32) -> Result<(), String> {
+        let index = self.sessions
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or_else(|| format!("Unknown session id {}", id))?;
+        let mut entry = self.sessions.remove(index);
+        let result = match entry.worker.as_mut() {
+            // The worker stops the session within the grace period, give it some slack
+            Some(worker) => worker.stop(entry.session.stop_grace + Duration::from_secs(2)),
+            None => Ok(()),
+        };
+        if let Some(vt) = entry.vt.take() {
+            self.vts.release(vt);
+        }
+        result
+    }
+
+    /// Drop sessions whose process has exited, returns their ids
+    pub fn reap(&mut self) -> Vec<u32> {
+        let mut exited = Vec::new();
+        for entry in self.sessions.iter_mut() {
+            if entry.worker.as_mut().is_none_or(|w| w.try_wait().is_some()) {
+                exited.push(entry.id);
+            }
+        }
+        for id in &exited {
+            let _ = self.stop(*id);
+        }
+        exited
+    }
+
+    pub fn get(&self, id: u32) -> Option<&RegisteredSession> {
+        self.sessions.iter().find(|s| s.id == id)
+    }
+
+    pub fn find_by_user(&self, user: &str) -> Option<&RegisteredSession> {
+        self.sessions.iter().find(|s| s.user == user)
+    }
+
+    pub fn list(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .iter()
+            .map(|s| SessionInfo {
+                id: s.id,
+                user: s.user.clone(),
+                session_name: s.session.name.clone(),
+                vt: s.vt(),
+                running: s.worker.is_some(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_log::SessionOutput;
+    use crate::vt::fake::FakeConsole;
+    use crate::vt::KdMode;
+    use std::time::{Duration, Instant};
+
+    fn registry() -> SessionRegistry<FakeConsole> {
+        SessionRegistry::with_vt_manager(VtManager::with_backend(FakeConsole::new(1, 2)))
+    }
+
+    fn command(exec: &str, args: &[&str]) -> Session {
+        let mut session = Session::new(exec, exec).with_output(SessionOutput::Null);
+        session.args = args.iter().map(|a| a.to_string()).collect();
+        session.stop_grace = Duration::from_secs(1);
+        session
+    }
+
+    /// What `start` does after PAM, without PAM
+    fn start_as(reg: &mut SessionRegistry<FakeConsole>, user: &str, session: Session) -> Result<u32, String> {
+        let vt = reg.vts.allocate()?;
+        let session = session.with_user(user).with_vt(vt.number);
+        reg.register(user.to_string(), None, session, vt)
+    }
+
+    fn open_vts(reg: &SessionRegistry<FakeConsole>) -> Vec<u32> {
+        reg.vts.backend.state.borrow().open.values().copied().collect()
+    }
+
+    #[test]
+    fn each_user_gets_a_session_on_its_own_vt() {
+        let mut reg = registry();
+        let alice = start_as(&mut reg, "alice", command("sleep", &["30"])).unwrap();
+        let bob = start_as(&mut reg, "bob", command("sleep", &["30"])).unwrap();
+
+        assert_ne!(alice, bob);
+        assert_eq!(reg.get(alice).unwrap().vt(), Some(2));
+        assert_eq!(reg.find_by_user("bob").unwrap().vt(), Some(3));
+        let list = reg.list();
+        assert_eq!(list.len(), 2);
+        assert!(list.iter().all(|s| s.running));
+        assert_eq!(reg.vts.backend.state.borrow().active, 3);
+
+        reg.stop(alice).unwrap();
+        reg.stop(bob).unwrap();
+        assert!(open_vts(&reg).is_empty());
+    }
+
+    #[test]
+    fn a_second_session_for_the_same_user_is_refused() {
+        let mut reg = registry();
+        let id = start_as(&mut reg, "alice", command("sleep", &["30"])).unwrap();
+        assert!(start_as(&mut reg, "alice", command("sleep", &["30"])).is_err());
+        assert_eq!(open_vts(&reg), [2]);
+        reg.stop(id).unwrap();
+    }
+
+    #[test]
+    fn a_session_that_fails_to_start_gives_its_vt_back() {
+        let mut reg = registry();
+        assert!(start_as(&mut reg, "alice", command("/nonexistent/hyprdm-session", &[])).is_err());
+        assert!(open_vts(&reg).is_empty());
+        assert_eq!(reg.vts.backend.state.borrow().kd_modes[&2], KdMode::Text);
+        assert!(reg.list().is_empty());
+    }
+
+    #[test]
+    fn switching_activates_the_users_vt() {
+        let mut reg = registry();
+        let alice = start_as(&mut reg, "alice", command("sleep", &["30"])).unwrap();
+        let bob = start_as(&mut reg, "bob", command("sleep", &["30"])).unwrap();
+
+        assert_eq!(reg.switch_to_user("alice"), Ok(alice));
+        assert_eq!(reg.vts.backend.state.borrow().active, 2);
+        assert!(reg.switch_to_user("carol").is_err());
+
+        reg.stop(alice).unwrap();
+        reg.stop(bob).unwrap();
+    }
+
+    #[test]
+    fn reap_drops_exited_sessions_and_frees_their_vt() {
+        let mut reg = registry();
+        let id = start_as(&mut reg, "alice", command("true", &[])).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while reg.get(id).is_some() {
+            assert!(Instant::now() < deadline, "session was never reaped");
+            reg.reap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(open_vts(&reg).is_empty());
+    }
+
+    #[test]
+    fn greeter_vt_is_reused_until_closed() {
+        let mut reg = registry();
+        let vt = reg.open_greeter().unwrap();
+        assert_eq!(reg.open_greeter(), Ok(vt));
+        assert_eq!(open_vts(&reg), [vt]);
+
+        reg.close_greeter();
+        assert!(open_vts(&reg).is_empty());
+        assert_ne!(reg.open_greeter().unwrap(), vt);
+        reg.close_greeter();
+    }
+}
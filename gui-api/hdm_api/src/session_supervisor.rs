@@ -0,0 +1,407 @@
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use config::HDMConfig;
+use crate::session_manager::{Session, SessionProgram};
+
+/// Upper bound for the retry backoff, however many retries are configured
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What happened to a supervised session
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionEvent {
+    /// The session exited with status 0
+    Exited,
+    /// The session exited with a non-zero status
+    ExitedWithCode(i32),
+    /// The session was killed by a signal
+    KilledBySignal(i32),
+    /// The session died within the crash window after being started
+    Crashed { uptime: Duration, code: Option<i32>, signal: Option<i32> },
+    /// The crash policy restarted the session
+    Restarted { attempt: u32 },
+    /// The crash policy switched to the default session
+    FellBackToDefault { name: String },
+    /// The greeter should be shown again
+    ReturnToGreeter,
+}
+
+/// What to do when a session keeps crashing right after start
+#[derive(Debug, Clone)]
+pub enum CrashPolicy {
+    ReturnToGreeter,
+    RetryWithBackoff { max_retries: u32, initial_delay: Duration },
+    /// Replace the crashing session with the default session's program
+    FallbackToDefault(SessionProgram),
+}
+
+impl CrashPolicy {
+    /// Build the policy from `crash_policy` in the config
+    pub fn from_config(config: &HDMConfig) -> Self {
+        match config.crash_policy.as_str() {
+            "retry" => CrashPolicy::RetryWithBackoff {
+                max_retries: config.crash_max_retries,
+                initial_delay: Duration::from_secs(1),
+            },
+            "fallback" => {
+                let sessions = Session::available_sessions();
+                let default = Session::resolve_default(&sessions, config)
+                    .and_then(|d| sessions.into_iter().find(|s| d.matches(s)));
+                match default {
+                    Some(mut default) => {
+                        default.uwsm = config.uwsm_wraps(default.desktop_id.as_deref(), &default.name);
+                        CrashPolicy::FallbackToDefault(default.program())
+                    }
+                    None => CrashPolicy::ReturnToGreeter,
+                }
+            }
+            _ => CrashPolicy::ReturnToGreeter,
+        }
+    }
+}
+
+type EventCallback = Box<dyn Fn(&SessionEvent) + Send>;
+
+/// Watches a running session and applies the crash policy when it dies
+pub struct SessionSupervisor {
+    pub session: Arc<Mutex<Session>>,
+    pub policy: CrashPolicy,
+    pub crash_window: Duration,
+    callback: Arc<Mutex<Option<EventCallback>>>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SessionSupervisor {
+    pub fn new(session: Session, policy: CrashPolicy, crash_window: Duration) -> Self {
+        Self {
+            session: Arc::new(Mutex::new(session)),
+            policy,
+            crash_window,
+            callback: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(false)),
+            handle: None,
+        }
+    }
+
+    pub fn from_config(session: Session, config: &HDMConfig) -> Self {
+        Self::new(
+            session,
+            CrashPolicy::from_config(config),
+            Duration::from_secs(config.crash_window_secs),
+        )
+    }
+
+    /// Also deliver every event to `callback`, e.g. an FFI function pointer
+    pub fn on_event<F: Fn(&SessionEvent) + Send + 'static>(&self, callback: F) {
+        if let Ok(mut cb) = self.callback.lock() {
+            *cb = Some(Box::new(callback));
+        }
+    }
+
+    /// Start watching the session, events are delivered on the returned channel
+    pub fn spawn(&mut self) -> Result<Receiver<SessionEvent>, String> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err("Supervisor is already running".into());
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let session = self.session.clone();
+        let policy = self.policy.clone();
+        let crash_window = self.crash_window;
+        let callback = self.callback.clone();
+        let running = self.running.clone();
+
+        self.handle = Some(thread::spawn(move || {
+            let emit = |event: SessionEvent| {
+                if let Ok(cb) = callback.lock() {
+                    if let Some(cb) = cb.as_ref() {
+                        cb(&event);
+                    }
+                }
+                let _ = tx.send(event);
+            };
+            supervise(&session, &policy, crash_window, &running, &emit);
+        }));
+
+        Ok(rx)
+    }
+
+    /// Stop watching; the session itself is left as it is
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for SessionSupervisor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn supervise(
+    session: &Mutex<Session>,
+    policy: &CrashPolicy,
+    crash_window: Duration,
+    running: &AtomicBool,
+    emit: &dyn Fn(SessionEvent),
+) {
+    let mut fast_crashes = 0u32;
+
+    while running.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(250));
+
+        let exited = {
+            let mut s = match session.lock() {
+                Ok(s) => s,
+                Err(_) => break,
+            };
+            let uptime = s.uptime();
+            s.try_wait().map(|status| (status, uptime.unwrap_or_default()))
+        };
+
+        let (status, uptime) = match exited {
+            Some(e) => e,
+            None => continue,
+        };
+
+        if uptime >= crash_window {
+            fast_crashes = 0;
+            emit(classify(status));
+            emit(SessionEvent::ReturnToGreeter);
+            continue;
+        }
+
+        fast_crashes += 1;
+        emit(SessionEvent::Crashed {
+            uptime,
+            code: status.code(),
+            signal: status.signal(),
+        });
+
+        // The policy applies from the first fast crash, `max_retries: 0` means no restart at all
+        let next = match policy {
+            CrashPolicy::ReturnToGreeter => None,
+            CrashPolicy::RetryWithBackoff { max_retries, initial_delay } => {
+                if fast_crashes > *max_retries {
+                    None
+                } else if !sleep_while_running(backoff_delay(*initial_delay, fast_crashes), running) {
+                    break;
+                } else {
+                    Some(SessionEvent::Restarted { attempt: fast_crashes })
+                }
+            }
+            CrashPolicy::FallbackToDefault(default) => {
+                let mut s = match session.lock() {
+                    Ok(s) => s,
+                    Err(_) => break,
+                };
+                if s.program() == *default {
+                    None
+                } else {
+                    s.set_program(default.clone());
+                    Some(SessionEvent::FellBackToDefault { name: default.name.clone() })
+                }
+            }
+        };
+
+        let restarted = match next {
+            Some(event) => match session.lock() {
+                Ok(mut s) => match s.start() {
+                    Ok(()) => {
+                        emit(event);
+                        true
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to restart session '{}': {}", s.name, e);
+                        false
+                    }
+                },
+                Err(_) => break,
+            },
+            None => false,
+        };
+
+        if !restarted {
+            fast_crashes = 0;
+            emit(SessionEvent::ReturnToGreeter);
+        }
+    }
+}
+
+/// `initial_delay` doubled for every retry after the first, capped at MAX_BACKOFF
+fn backoff_delay(initial_delay: Duration, retry: u32) -> Duration {
+    let factor = 2u32.checked_pow(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+    initial_delay.saturating_mul(factor).min(MAX_BACKOFF)
+}
+
+/// Sleep in short steps so `stop` is not held up by a long backoff; false once stopped
+fn sleep_while_running(delay: Duration, running: &AtomicBool) -> bool {
+    let step = Duration::from_millis(100);
+    let mut left = delay;
+    while !left.is_zero() {
+        if !running.load(Ordering::SeqCst) {
+            return false;
+        }
+        let nap = left.min(step);
+        thread::sleep(nap);
+        left -= nap;
+    }
+    running.load(Ordering::SeqCst)
+}
+
+fn classify(status: ExitStatus) -> SessionEvent {
+    match (status.code(), status.signal()) {
+        (Some(0), _) => SessionEvent::Exited,
+        (Some(code), _) => SessionEvent::ExitedWithCode(code),
+        (None, Some(signal)) => SessionEvent::KilledBySignal(signal),
+        (None, None) => SessionEvent::ExitedWithCode(-1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_log::SessionOutput;
+    use std::time::Instant;
+
+    fn shell(script: &str) -> Session {
+        let mut session = Session::new("test", "/bin/sh").with_output(SessionOutput::Null);
+        session.args = vec!["-c".into(), script.into()];
+        session.stop_grace = Duration::from_secs(1);
+        session
+    }
+
+    /// Events until ReturnToGreeter, or until `timeout`
+    fn events_until_greeter(rx: &Receiver<SessionEvent>, timeout: Duration) -> Vec<SessionEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut events = Vec::new();
+        while let Ok(event) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            let done = event == SessionEvent::ReturnToGreeter;
+            events.push(event);
+            if done {
+                break;
+            }
+        }
+        events
+    }
+
+    fn kinds(events: &[SessionEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|e| match e {
+                SessionEvent::Crashed { code, .. } => format!("crashed {:?}", code),
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn backoff_doubles_and_is_capped() {
+        let initial = Duration::from_secs(1);
+        assert_eq!(backoff_delay(initial, 1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(initial, 2), Duration::from_secs(2));
+        assert_eq!(backoff_delay(initial, 4), Duration::from_secs(8));
+        assert_eq!(backoff_delay(initial, 7), MAX_BACKOFF);
+        // 2^32 and beyond used to overflow
+        assert_eq!(backoff_delay(initial, 33), MAX_BACKOFF);
+        assert_eq!(backoff_delay(initial, u32::MAX), MAX_BACKOFF);
+        assert_eq!(backoff_delay(Duration::MAX, 2), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn stop_cuts_a_backoff_short() {
+        let running = AtomicBool::new(false);
+        let started = Instant::now();
+        assert!(!sleep_while_running(Duration::from_secs(30), &running));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn a_long_running_session_exits_to_the_greeter() {
+        let mut session = shell("exit 4");
+        session.start().unwrap();
+        let mut supervisor = SessionSupervisor::new(session, CrashPolicy::ReturnToGreeter, Duration::ZERO);
+        let rx = supervisor.spawn().unwrap();
+
+        let events = events_until_greeter(&rx, Duration::from_secs(10));
+        assert_eq!(events, [SessionEvent::ExitedWithCode(4), SessionEvent::ReturnToGreeter]);
+    }
+
+    #[test]
+    fn the_first_fast_crash_already_follows_the_policy() {
+        let mut session = shell("exit 3");
+        session.start().unwrap();
+        let mut supervisor = SessionSupervisor::new(session, CrashPolicy::ReturnToGreeter, Duration::from_secs(30));
+        let rx = supervisor.spawn().unwrap();
+
+        let events = events_until_greeter(&rx, Duration::from_secs(10));
+        assert_eq!(kinds(&events), ["crashed Some(3)", "ReturnToGreeter"]);
+    }
+
+    #[test]
+    fn no_retries_means_no_restart() {
+        let mut session = shell("exit 3");
+        session.start().unwrap();
+        let policy = CrashPolicy::RetryWithBackoff { max_retries: 0, initial_delay: Duration::from_millis(10) };
+        let mut supervisor = SessionSupervisor::new(session, policy, Duration::from_secs(30));
+        let rx = supervisor.spawn().unwrap();
+
+        let events = events_until_greeter(&rx, Duration::from_secs(10));
+        assert_eq!(kinds(&events), ["crashed Some(3)", "ReturnToGreeter"]);
+    }
+
+    #[test]
+    fn retries_stop_at_the_limit() {
+        let mut session = shell("kill -9 $$");
+        session.start().unwrap();
+        let policy = CrashPolicy::RetryWithBackoff { max_retries: 2, initial_delay: Duration::from_millis(10) };
+        let mut supervisor = SessionSupervisor::new(session, policy, Duration::from_secs(30));
+        let rx = supervisor.spawn().unwrap();
+
+        let events = events_until_greeter(&rx, Duration::from_secs(20));
+        assert_eq!(kinds(&events), [
+            "crashed None",
+            "Restarted { attempt: 1 }",
+            "crashed None",
+            "Restarted { attempt: 2 }",
+            "crashed None",
+            "ReturnToGreeter",
+        ]);
+        assert!(matches!(events[0], SessionEvent::Crashed { signal: Some(9), .. }));
+    }
+
+    #[test]
+    fn fallback_runs_the_whole_default_program_and_gives_up_on_it() {
+        let mut session = shell("exit 5");
+        session.desktop_id = Some("broken".into());
+        session.start().unwrap();
+        let default = SessionProgram {
+            name: "Default".into(),
+            exec: "/bin/sh".into(),
+            args: vec!["-c".into(), "exit 6".into()],
+            desktop_id: Some("default".into()),
+            uwsm: false,
+        };
+        let policy = CrashPolicy::FallbackToDefault(default.clone());
+        let mut supervisor = SessionSupervisor::new(session, policy, Duration::from_secs(30));
+        let rx = supervisor.spawn().unwrap();
+
+        let events = events_until_greeter(&rx, Duration::from_secs(20));
+        assert_eq!(kinds(&events), [
+            "crashed Some(5)",
+            "FellBackToDefault { name: \"Default\" }",
+            "crashed Some(6)",
+            "ReturnToGreeter",
+        ]);
+        assert_eq!(supervisor.session.lock().unwrap().program(), default);
+    }
+}
@@ -0,0 +1,199 @@
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use crate::session_manager::Session;
+use crate::user_manager::PamHandle;
+
+/// What the worker reports once the session process is running
+const STARTED: &str = "started";
+
+/// A forked process that owns one user session: it opens the PAM session, starts the
+/// session process, waits for it and closes PAM again. pam_systemd makes the caller
+/// of `open_session` the logind session leader, so the daemon never calls it itself
+#[derive(Debug)]
+pub struct SessionWorker {
+    pid: libc::pid_t,
+    /// Set once the worker has been reaped, its pid may belong to someone else by then
+    status: Option<ExitStatus>,
+}
+
+impl SessionWorker {
+    /// Fork a worker for `session`, opening `pam` in it first when given.
+    /// Returns once the session process has started or failed to
+    pub fn spawn(pam: Option<PamHandle>, session: &mut Session) -> Result<Self, String> {
+        let (mut report, worker_end) = UnixStream::pair()
+            .map_err(|e| format!("Failed to create the session worker pipe: {}", e))?;
+
+        match unsafe { libc::fork() } {
+            -1 => Err(format!("Failed to fork the session worker: {}", std::io::Error::last_os_error())),
+            0 => {
+                drop(report);
+                let code = run(pam, session, worker_end);
+                unsafe { libc::_exit(code) }
+            }
+            pid => {
+                drop(worker_end);
+                // The worker has its own copy, this one never opened a session
+                drop(pam);
+                let mut worker = SessionWorker { pid, status: None };
+                let mut message = String::new();
+                let _ = report.read_to_string(&mut message);
+                if message == STARTED {
+                    return Ok(worker);
+                }
+                worker.wait();
+                if message.is_empty() {
+                    message = "Session worker exited before starting the session".into();
+                }
+                Err(message)
+            }
+        }
+    }
+
+    /// Check whether the worker, and with it the session, has exited, without blocking
+    pub fn try_wait(&mut self) -> Option<ExitStatus> {
+        if self.status.is_none() {
+            let mut status = 0;
+            match unsafe { libc::waitpid(self.pid, &mut status, libc::WNOHANG) } {
+                0 => return None,
+                -1 => self.status = Some(ExitStatus::from_raw(0)),
+                _ => self.status = Some(ExitStatus::from_raw(status)),
+            }
+        }
+        self.status
+    }
+
+    /// Ask the worker to stop the session; SIGKILL it if it is not gone after `grace`
+    pub fn stop(&mut self, grace: Duration) -> Result<(), String> {
+        if self.try_wait().is_some() {
+            return Ok(());
+        }
+        if unsafe { libc::kill(self.pid, libc::SIGTERM) } != 0 {
+            return Err(format!("Session stop error: {}", std::io::Error::last_os_error()));
+        }
+
+        let deadline = Instant::now() + grace;
+        while Instant::now() < deadline {
+            if self.try_wait().is_some() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        unsafe { libc::kill(self.pid, libc::SIGKILL) };
+        self.wait();
+        Err(format!("Session worker {} did not stop in time and was killed", self.pid))
+    }
+
+    fn wait(&mut self) {
+        let mut status = 0;
+        unsafe { libc::waitpid(self.pid, &mut status, 0) };
+        self.status = Some(ExitStatus::from_raw(status));
+    }
+}
+
+/// The worker's side: never returns to the daemon's code, the caller exits with the result
+fn run(pam: Option<PamHandle>, session: &mut Session, mut report: UnixStream) -> i32 {
+    // Blocked before anything starts, so a stop request can't get lost; the session
+    // process gets a clean mask from std
+    let mut signals: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+    }
+
+    let mut pam = pam;
+    let started = match pam.as_mut() {
+        Some(pam) => pam.open_session("user", session.vt).map(|()| {
+            session.pam_env = pam.env().to_vec();
+        }),
+        None => Ok(()),
+    }
+    .and_then(|()| session.start());
+    let _ = report.write_all(match &started {
+        Ok(()) => STARTED.as_bytes(),
+        Err(e) => e.as_bytes(),
+    });
+    drop(report);
+    if started.is_err() {
+        return 1;
+    }
+
+    let timeout = libc::timespec { tv_sec: 0, tv_nsec: 200_000_000 };
+    let code = loop {
+        if let Some(status) = session.try_wait() {
+            break status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0));
+        }
+        if unsafe { libc::sigtimedwait(&signals, std::ptr::null_mut(), &timeout) } == libc::SIGTERM {
+            if let Err(e) = session.stop() {
+                eprintln!("[HyprDM] {}", e);
+            }
+            break 0;
+        }
+    };
+
+    // Closes the PAM session and deletes the credentials
+    drop(pam);
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_log::SessionOutput;
+    use crate::test_util::TempDir;
+    use std::path::Path;
+
+    fn shell(script: &str) -> Session {
+        let mut session = Session::new("worker", "/bin/sh").with_output(SessionOutput::Null);
+        session.args = vec!["-c".into(), script.into()];
+        session.stop_grace = Duration::from_secs(1);
+        session
+    }
+
+    #[test]
+    fn a_start_failure_comes_back_from_the_worker() {
+        let mut session = Session::new("missing", "/nonexistent/hyprdm-session").with_output(SessionOutput::Null);
+        let err = SessionWorker::spawn(None, &mut session).unwrap_err();
+        assert!(err.contains("Session start error"), "{}", err);
+    }
+
+    #[test]
+    fn the_worker_exits_with_the_session() {
+        let mut worker = SessionWorker::spawn(None, &mut shell("exit 3")).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let status = loop {
+            if let Some(status) = worker.try_wait() {
+                break status;
+            }
+            assert!(Instant::now() < deadline, "worker did not exit");
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(worker.stop(Duration::from_secs(1)), Ok(()));
+    }
+
+    #[test]
+    fn stopping_the_worker_stops_the_session() {
+        let dir = TempDir::new("worker");
+        let pidfile = dir.join("pid");
+        let script = format!("echo $$ > {}; exec sleep 30", pidfile.display());
+        let mut worker = SessionWorker::spawn(None, &mut shell(&script)).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let pid = loop {
+            if let Some(pid) = std::fs::read_to_string(&pidfile).ok().filter(|s| s.ends_with('\n')) {
+                break pid.trim().to_string();
+            }
+            assert!(Instant::now() < deadline, "session never started");
+            std::thread::sleep(Duration::from_millis(20));
+        };
+
+        worker.stop(Duration::from_secs(5)).unwrap();
+        assert!(worker.try_wait().is_some());
+        assert!(!Path::new("/proc").join(pid).exists());
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::Path;
+
+/// Layouts of wl_shm buffers the renderer understands, both little endian B, G, R, A/X
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Premultiplied alpha
+    Argb8888,
+    /// Alpha byte ignored, always opaque
+    Xrgb8888,
+}
+
+/// Largest width or height of an image, a 16384x16384 frame is already 1 GiB
+pub const MAX_IMAGE_SIZE: u32 = 16384;
+
+/// A CPU image in premultiplied ARGB, one u32 per pixel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, color: u32) -> Result<Self, String> {
+        Ok(Self { width, height, pixels: vec![color; pixel_count(width, height)?] })
+    }
+
+    /// Copy a client buffer; `offset` and `stride` are in bytes as wl_shm reports them
+    pub fn from_shm(
+        data: &[u8],
+        offset: usize,
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelFormat,
+    ) -> Result<Self, String> {
+        let count = pixel_count(width, height)?;
+        let row_bytes = width as usize * 4;
+        if stride < row_bytes {
+            return Err(format!("Buffer stride {} is shorter than a row of {} pixels", stride, width));
+        }
+        let needed = stride
+            .checked_mul((height as usize).saturating_sub(1))
+            .and_then(|n| n.checked_add(offset))
+            .and_then(|n| n.checked_add(row_bytes));
+        if height > 0 && needed.map(|n| data.len() < n).unwrap_or(true) {
+            return Err(format!("Buffer of {} bytes is too small for {}x{}", data.len(), width, height));
+        }
+
+        let mut pixels = Vec::with_capacity(count);
+        for y in 0..height as usize {
+            let row = &data[offset + y * stride..offset + y * stride + row_bytes];
+            pixels.extend(row.chunks_exact(4).map(|p| {
+                let pixel = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+                match format {
+                    PixelFormat::Argb8888 => pixel,
+                    PixelFormat::Xrgb8888 => pixel | 0xff00_0000,
+                }
+            }));
+        }
+        Ok(Self { width, height, pixels })
+    }
+
+    pub fn fill(&mut self, color: u32) {
+        self.pixels.iter_mut().for_each(|p| *p = color);
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Draw `src` with its top left corner at (x, y), clipped to this image.
+    /// Positions come from clients, so the edges are computed in i64 and can't overflow
+    pub fn draw(&mut self, src: &Image, x: i32, y: i32) {
+        let (x, y) = (i64::from(x), i64::from(y));
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + i64::from(src.width)).min(i64::from(self.width));
+        let y1 = (y + i64::from(src.height)).min(i64::from(self.height));
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        for dy in y0..y1 {
+            let src_row = (dy - y) as usize * src.width as usize;
+            let dst_row = dy as usize * self.width as usize;
+            for dx in x0..x1 {
+                let s = src.pixels[src_row + (dx - x) as usize];
+                let d = &mut self.pixels[dst_row + dx as usize];
+                *d = over(s, *d);
+            }
+        }
+    }
+
+    /// RGBA PNG with straight alpha; PNG has no empty images
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        let rgba: Vec<u8> = self.pixels.iter().flat_map(|&p| unpremultiply(p)).collect();
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&rgba))
+            .map_err(|e| format!("Failed to encode a {}x{} PNG: {}", self.width, self.height, e))?;
+        Ok(png)
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_png()?).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+}
+
+/// Pixels of a `width` x `height` image, refusing sizes beyond MAX_IMAGE_SIZE
+fn pixel_count(width: u32, height: u32) -> Result<usize, String> {
+    if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+        return Err(format!("Image of {}x{} is larger than {}x{}", width, height, MAX_IMAGE_SIZE, MAX_IMAGE_SIZE));
+    }
+    (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| format!("Image of {}x{} is too large", width, height))
+}
+
+/// Porter-Duff source over destination, both premultiplied
+fn over(src: u32, dst: u32) -> u32 {
+    let alpha = src >> 24;
+    if alpha == 0xff {
+        return src;
+    }
+    if alpha == 0 {
+        return dst;
+    }
+    let inv = 255 - alpha;
+    let mut out = 0u32;
+    for shift in [0, 8, 16, 24] {
+        let s = (src >> shift) & 0xff;
+        let d = (dst >> shift) & 0xff;
+        // d * inv / 255, rounded
+        let t = d * inv + 128;
+        let blended = s + ((t + (t >> 8)) >> 8);
+        out |= blended.min(255) << shift;
+    }
+    out
+}
+
+fn unpremultiply(pixel: u32) -> [u8; 4] {
+    let a = pixel >> 24;
+    let channel = |shift: u32| -> u8 {
+        let c = (pixel >> shift) & 0xff;
+        match a {
+            0 => 0,
+            255 => c as u8,
+            a => ((c * 255 + a / 2) / a).min(255) as u8,
+        }
+    };
+    [channel(16), channel(8), channel(0), a as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trips_with_straight_alpha() {
+        let mut image = Image::new(3, 2, 0xff10_2030).unwrap();
+        image.pixels[1] = 0x8040_2010;
+        image.pixels[5] = 0;
+        let png = image.to_png().unwrap();
+
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut raw = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut raw).unwrap();
+        assert_eq!((frame.width, frame.height), (3, 2));
+        assert_eq!((frame.color_type, frame.bit_depth), (png::ColorType::Rgba, png::BitDepth::Eight));
+        assert_eq!(
+            raw[..frame.buffer_size()],
+            [
+                0x10, 0x20, 0x30, 0xff, 0x80, 0x40, 0x20, 0x80, 0x10, 0x20, 0x30, 0xff,
+                0x10, 0x20, 0x30, 0xff, 0x10, 0x20, 0x30, 0xff, 0, 0, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn over_blends_premultiplied_colors() {
+        assert_eq!(over(0xff11_2233, 0xff44_5566), 0xff11_2233);
+        assert_eq!(over(0x0000_0000, 0xff44_5566), 0xff44_5566);
+        // Half transparent white over opaque black
+        assert_eq!(over(0x8080_8080, 0xff00_0000), 0xff80_8080);
+        // Two half transparent layers
+        assert_eq!(over(0x8000_0080, 0x8080_0000), 0xc040_0080);
+    }
+
+    #[test]
+    fn draw_clips_to_the_image() {
+        let mut dst = Image::new(3, 3, 0xff00_0000).unwrap();
+        dst.draw(&Image::new(2, 2, 0xffff_ffff).unwrap(), -1, -1);
+        dst.draw(&Image::new(2, 2, 0xffff_ffff).unwrap(), 3, 0);
+        assert_eq!(dst.pixel(0, 0), Some(0xffff_ffff));
+        assert_eq!(dst.pixels.iter().filter(|p| **p == 0xffff_ffff).count(), 1);
+        assert_eq!(dst.pixel(3, 0), None);
+
+        // Edges past i32::MAX or below i32::MIN are clipped, not wrapped
+        let big = Image::new(4, 4, 0xffff_ffff).unwrap();
+        for (x, y) in [(i32::MAX, 0), (0, i32::MAX - 1), (i32::MIN, 0), (i32::MIN, i32::MIN)] {
+            dst.draw(&big, x, y);
+        }
+        assert_eq!(dst.pixels.iter().filter(|p| **p == 0xffff_ffff).count(), 1);
+    }
+
+    #[test]
+    fn sizes_are_checked() {
+        assert!(Image::new(MAX_IMAGE_SIZE + 1, 1, 0).is_err());
+        assert!(Image::new(1, u32::MAX, 0).is_err());
+        assert_eq!(pixel_count(MAX_IMAGE_SIZE, MAX_IMAGE_SIZE), Ok(1 << 28));
+        assert!(Image::new(0, 0, 0).unwrap().to_png().is_err());
+
+        // A negative wl_shm width turns into a huge u32
+        assert!(Image::from_shm(&[0; 16], 0, -1i32 as u32, 1, 16, PixelFormat::Argb8888).is_err());
+        assert!(Image::from_shm(&[0; 16], usize::MAX, 1, 2, 4, PixelFormat::Argb8888).is_err());
+        let image = Image::from_shm(&[1, 2, 3, 0, 9, 9, 4, 5, 6, 0], 0, 1, 2, 6, PixelFormat::Xrgb8888).unwrap();
+        assert_eq!(image.pixels, [0xff03_0201, 0xff06_0504]);
+    }
+}
@@ -0,0 +1,347 @@
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Duration;
+
+/// Waits for a line on fd 3 before exec'ing the session in place, the pid stays the same
+const GATE_SCRIPT: &str = r#"read -r go <&3; exec 3<&-; [ "$go" = go ] || exit 1; exec "$@""#;
+
+/// `$0` of the gate shell, shows up in /proc/<pid>/cmdline while the session is held
+pub const GATE_NAME: &str = "hyprdm-scope-gate";
+
+/// The parts of systemd's D-Bus manager API used to contain sessions
+pub trait SystemdBus: Debug + Send + Sync {
+    /// Create a transient `.scope` unit and move `pids` into it
+    fn start_transient_scope(
+        &self,
+        unit: &str,
+        description: &str,
+        pids: &[u32],
+        stop_timeout: Duration,
+    ) -> Result<(), String>;
+
+    /// Stop a unit, systemd signals every process in its cgroup
+    fn stop_unit(&self, unit: &str) -> Result<(), String>;
+}
+
+/// Talks to org.freedesktop.systemd1 through `busctl`
+#[derive(Debug, Clone, Default)]
+pub struct BusctlBus {
+    /// Use the user's bus instead of the system bus
+    pub user: bool,
+    /// Explicit bus address, e.g. `unix:path=/tmp/fake-bus` for a fake endpoint in tests
+    pub address: Option<String>,
+    /// Run busctl as this uid and gid, the user bus only accepts its owner
+    pub run_as: Option<(u32, u32)>,
+}
+
+impl BusctlBus {
+    pub fn system() -> Self {
+        Self { user: false, address: None, run_as: None }
+    }
+
+    /// The caller's own user bus
+    pub fn user() -> Self {
+        Self { user: true, address: None, run_as: None }
+    }
+
+    /// The user bus of another account, reached as that account
+    pub fn for_user(uid: u32, gid: u32) -> Self {
+        Self {
+            user: true,
+            address: Some(format!("unix:path=/run/user/{}/bus", uid)),
+            run_as: Some((uid, gid)),
+        }
+    }
+
+    pub fn with_address(address: &str) -> Self {
+        Self { user: false, address: Some(address.to_string()), run_as: None }
+    }
+
+    fn command(&self, method: &str, signature: &str, args: &[String]) -> Command {
+        let mut cmd = Command::new("busctl");
+        if let Some(address) = &self.address {
+            cmd.arg(format!("--address={}", address));
+        } else if self.user {
+            cmd.arg("--user");
+        }
+        if let Some((uid, gid)) = self.run_as {
+            cmd.uid(uid).gid(gid);
+        }
+        cmd.args([
+            "call",
+            "org.freedesktop.systemd1",
+            "/org/freedesktop/systemd1",
+            "org.freedesktop.systemd1.Manager",
+            method,
+            signature,
+        ]);
+        cmd.args(args);
+        cmd
+    }
+
+    fn call(&self, method: &str, signature: &str, args: &[String]) -> Result<String, String> {
+        let output = self
+            .command(method, signature, args)
+            .output()
+            .map_err(|e| format!("busctl {} failed: {}", method, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "busctl {} error: {}",
+                method,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+impl SystemdBus for BusctlBus {
+    fn start_transient_scope(
+        &self,
+        unit: &str,
+        description: &str,
+        pids: &[u32],
+        stop_timeout: Duration,
+    ) -> Result<(), String> {
+        let args = scope_args(unit, description, pids, stop_timeout);
+        self.call("StartTransientUnit", "ssa(sv)a(sa(sv))", &args).map(|_| ())
+    }
+
+    fn stop_unit(&self, unit: &str) -> Result<(), String> {
+        self.call("StopUnit", "ss", &[unit.to_string(), "replace".to_string()])
+            .map(|_| ())
+    }
+}
+
+/// busctl arguments of StartTransientUnit for a scope holding `pids`
+fn scope_args(unit: &str, description: &str, pids: &[u32], stop_timeout: Duration) -> Vec<String> {
+    let mut args = vec![
+        unit.to_string(),
+        "fail".to_string(),
+        // properties: PIDs, Description, TimeoutStopUSec
+        "3".to_string(),
+        "PIDs".to_string(),
+        "au".to_string(),
+        pids.len().to_string(),
+    ];
+    args.extend(pids.iter().map(|p| p.to_string()));
+    args.extend([
+        "Description".to_string(),
+        "s".to_string(),
+        description.to_string(),
+        "TimeoutStopUSec".to_string(),
+        "t".to_string(),
+        stop_timeout.as_micros().to_string(),
+        // no auxiliary units
+        "0".to_string(),
+    ]);
+    args
+}
+
+/// Holds a freshly spawned session before exec until it has been moved into its scope,
+/// so nothing it forks can escape the scope. Dropped without `release`, the session exits.
+#[derive(Debug)]
+pub struct ScopeGate {
+    read: OwnedFd,
+    write: File,
+}
+
+impl ScopeGate {
+    pub fn new() -> Result<Self, String> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(format!("Failed to create scope gate: {}", io::Error::last_os_error()));
+        }
+        unsafe {
+            Ok(Self {
+                read: OwnedFd::from_raw_fd(fds[0]),
+                write: File::from_raw_fd(fds[1]),
+            })
+        }
+    }
+
+    /// `argv` run behind the gate: a shell waits on fd 3, then execs `argv` in its place
+    pub fn command(&self, argv: &[String]) -> Command {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-c").arg(GATE_SCRIPT).arg(GATE_NAME).args(argv);
+
+        let fd = self.read.as_raw_fd();
+        unsafe {
+            cmd.pre_exec(move || {
+                // dup2 onto itself keeps close-on-exec, clear it instead
+                let ret = if fd == 3 {
+                    libc::fcntl(fd, libc::F_SETFD, 0)
+                } else {
+                    libc::dup2(fd, 3)
+                };
+                if ret < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        cmd
+    }
+
+    /// Let the held session exec
+    pub fn release(mut self) {
+        if let Err(e) = self.write.write_all(b"go\n") {
+            eprintln!("[HyprDM] Failed to release session: {}", e);
+        }
+    }
+}
+
+/// Unit name for a session, e.g. `hyprdm-session-alice-Hyprland.scope`
+pub fn scope_unit_name(user: &str, session: &str) -> String {
+    format!("hyprdm-session-{}-{}.scope", escape_unit(user), escape_unit(session))
+}
+
+/// Escape a string the way `systemd-escape` does for unit name components
+fn escape_unit(s: &str) -> String {
+    let mut out = String::new();
+    for (i, b) in s.bytes().enumerate() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b':' | b'_' => out.push(b as char),
+            b'.' if i > 0 => out.push('.'),
+            b'/' => out.push('-'),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out
+}
+
+/// In-memory systemd for tests: records calls and stops a scope by terminating the
+/// process groups of its pids, like systemd does with the cgroup
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    pub(crate) struct FakeSystemd {
+        pub calls: Mutex<Vec<String>>,
+        /// /proc/<pid>/cmdline of each pid at the time its scope was created
+        pub cmdlines: Mutex<Vec<String>>,
+        pub scopes: Mutex<BTreeMap<String, Vec<u32>>>,
+        pub fail: Option<&'static str>,
+    }
+
+    impl SystemdBus for FakeSystemd {
+        fn start_transient_scope(
+            &self,
+            unit: &str,
+            description: &str,
+            pids: &[u32],
+            stop_timeout: Duration,
+        ) -> Result<(), String> {
+            self.calls.lock().unwrap().push(format!(
+                "start {} {:?} {:?} {}",
+                unit,
+                description,
+                pids,
+                stop_timeout.as_secs()
+            ));
+            if self.fail == Some("start") {
+                return Err("fake start failure".into());
+            }
+            for pid in pids {
+                let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+                self.cmdlines.lock().unwrap().push(String::from_utf8_lossy(&cmdline).replace('\0', " "));
+            }
+            self.scopes.lock().unwrap().insert(unit.to_string(), pids.to_vec());
+            Ok(())
+        }
+
+        fn stop_unit(&self, unit: &str) -> Result<(), String> {
+            self.calls.lock().unwrap().push(format!("stop {}", unit));
+            if self.fail == Some("stop") {
+                return Err("fake stop failure".into());
+            }
+            let pids = self.scopes.lock().unwrap().remove(unit)
+                .ok_or_else(|| format!("Unit {} not loaded", unit))?;
+            for pid in pids {
+                crate::process_group::signal(pid, libc::SIGTERM)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::process::Stdio;
+
+    #[test]
+    fn unit_names_are_escaped_like_systemd_escape() {
+        assert_eq!(scope_unit_name("alice", "Hyprland"), "hyprdm-session-alice-Hyprland.scope");
+        assert_eq!(
+            scope_unit_name("bob", "Sway (uwsm)"),
+            "hyprdm-session-bob-Sway\\x20\\x28uwsm\\x29.scope"
+        );
+        assert_eq!(escape_unit(".hidden/dir-x"), "\\x2ehidden-dir\\x2dx");
+        assert_eq!(escape_unit("a.b"), "a.b");
+    }
+
+    #[test]
+    fn scope_properties_carry_pids_description_and_timeout() {
+        let args = scope_args("u.scope", "HyprDM session x", &[10, 11], Duration::from_secs(5));
+        assert_eq!(
+            args,
+            [
+                "u.scope", "fail", "3", "PIDs", "au", "2", "10", "11", "Description", "s",
+                "HyprDM session x", "TimeoutStopUSec", "t", "5000000", "0",
+            ]
+        );
+    }
+
+    #[test]
+    fn user_bus_of_another_account_is_called_as_that_account() {
+        let bus = BusctlBus::for_user(1000, 100);
+        assert_eq!(bus.run_as, Some((1000, 100)));
+        let cmd = bus.command("StopUnit", "ss", &["u.scope".into(), "replace".into()]);
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(args[0], "--address=unix:path=/run/user/1000/bus");
+        assert_eq!(&args[args.len() - 4..], ["StopUnit", "ss", "u.scope", "replace"]);
+
+        let system = BusctlBus::system().command("StopUnit", "ss", &[]);
+        assert_eq!(system.get_args().next().unwrap(), "call");
+    }
+
+    #[test]
+    fn gate_holds_the_process_until_released() {
+        let gate = ScopeGate::new().unwrap();
+        let argv = ["/bin/echo".to_string(), "started".to_string()];
+        let mut child = gate.command(&argv).stdout(Stdio::piped()).spawn().unwrap();
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(child.try_wait().unwrap().is_none());
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", child.id())).unwrap();
+        assert!(String::from_utf8_lossy(&cmdline).contains(GATE_NAME));
+
+        gate.release();
+        let mut out = String::new();
+        child.stdout.take().unwrap().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "started\n");
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn dropping_the_gate_never_runs_the_session() {
+        let gate = ScopeGate::new().unwrap();
+        let argv = ["/bin/echo".to_string(), "started".to_string()];
+        let mut child = gate.command(&argv).stdout(Stdio::piped()).spawn().unwrap();
+        drop(gate);
+
+        let mut out = String::new();
+        child.stdout.take().unwrap().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "");
+        assert!(!child.wait().unwrap().success());
+    }
+}
@@ -2,7 +2,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
 use config::{HDMConfig, save_config};
-use crate::ipc::HyprlandIPC;
+use crate::ipc::{HyprCommand, HyprlandIPC};
 
 #[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub enum ThemeType {
@@ -155,10 +155,20 @@ impl ThemeManager {
 
         if let Some(bg) = json.get("background") {
             let bg_str = bg.as_str().ok_or("Background value is not a string")?;
-            let ipc = HyprlandIPC;
-            ipc.send_command(&format!("setv window:background {}", bg_str))?;
+            let ipc = HyprlandIPC::new();
+            ipc.execute(&HyprCommand::keyword("misc:background_color", &hypr_color(bg_str)))?;
         }
 
         Ok(())
     }
-} 
+}
+
+/// `#rrggbb` / `#rrggbbaa` to Hyprland's `rgb(...)` / `rgba(...)`, other forms are passed through
+fn hypr_color(color: &str) -> String {
+    let color = color.trim();
+    match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => format!("rgb({})", hex),
+        Some(hex) if hex.len() == 8 => format!("rgba({})", hex),
+        _ => color.to_string(),
+    }
+}
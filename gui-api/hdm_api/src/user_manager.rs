@@ -1,5 +1,5 @@
 use libc;
-use pam_client::{Context, Flag};
+use pam_client::{Context, Flag, SessionToken};
 use pam_client::conv_mock::Conversation;
 use otpauth::{TOTP, HOTP};
 use std::path::Path;
@@ -7,12 +7,95 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use std::env;
 use std::ffi::OsString;
 
+/// PAM service of the greeter's own logind session. It never authenticates, e.g.
+/// `auth required pam_permit.so`, `account required pam_permit.so`,
+/// `session optional pam_systemd.so`
+pub const GREETER_PAM_SERVICE: &str = "hyprdm-greeter";
+
 pub enum TwoFactorMethod {
     TOTP,
     HOTP { counter: u64 },
     None,
 }
 
+/// An authenticated PAM context, kept alive for as long as the user's session runs
+pub struct PamHandle {
+    pub username: String,
+    context: Context<Conversation>,
+    /// Set once `open_session` succeeded, closed again on drop
+    session: Option<SessionToken>,
+    env: Vec<(OsString, OsString)>,
+}
+
+impl PamHandle {
+    /// Context for the greeter account under GREETER_PAM_SERVICE, without a password;
+    /// open it with `open_session("greeter", vt)`
+    pub fn for_greeter(username: &str) -> Result<Self, String> {
+        let context = Context::new(GREETER_PAM_SERVICE, Some(username), Conversation::new())
+            .map_err(|e| format!("Failed to initialize PAM context for {}: {}", username, e))?;
+        Ok(PamHandle {
+            username: username.to_string(),
+            context,
+            session: None,
+            env: Vec::new(),
+        })
+    }
+
+    pub fn context_mut(&mut self) -> &mut Context<Conversation> {
+        &mut self.context
+    }
+
+    /// Establish credentials and open the PAM session, which registers it with logind
+    /// through pam_systemd. `class` is "user" or "greeter"
+    pub fn open_session(&mut self, class: &str, vt: Option<u32>) -> Result<(), String> {
+        if self.session.is_some() {
+            return Err(format!("A PAM session for {} is already open", self.username));
+        }
+
+        let mut vars = vec![
+            format!("XDG_SESSION_CLASS={}", class),
+            "XDG_SESSION_TYPE=wayland".to_string(),
+            "XDG_SEAT=seat0".to_string(),
+        ];
+        if let Some(vt) = vt {
+            vars.push(format!("XDG_VTNR={}", vt));
+            self.context
+                .set_tty(Some(&format!("tty{}", vt)))
+                .map_err(|e| format!("Failed to set PAM_TTY for {}: {}", self.username, e))?;
+        }
+        for var in &vars {
+            self.context
+                .putenv(var)
+                .map_err(|e| format!("Failed to set {} for {}: {}", var, self.username, e))?;
+        }
+
+        let session = self.context
+            .open_session(Flag::NONE)
+            .map_err(|e| format!("Failed to open a PAM session for {}: {}", self.username, e))?;
+        self.env = session
+            .envlist()
+            .iter_tuples()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        self.session = Some(session.leak());
+        Ok(())
+    }
+
+    /// What the PAM modules exported for the session, e.g. XDG_RUNTIME_DIR and XDG_SESSION_ID
+    pub fn env(&self) -> &[(OsString, OsString)] {
+        &self.env
+    }
+}
+
+impl Drop for PamHandle {
+    fn drop(&mut self) {
+        // Dropping the session closes it and deletes the credentials
+        if let Some(token) = self.session.take() {
+            drop(self.context.unleak_session(token));
+        }
+    }
+}
+
 pub struct User {
     pub username: String,
     pub pam_service: String,
@@ -101,6 +184,31 @@ impl User {
         context.authenticate(Flag::NONE).is_ok()
     }
 
+    /// Authenticate, check the account and keep the PAM context for the session
+    pub fn open_pam_handle(&self, password: &str) -> Result<PamHandle, String> {
+        let mut context = Context::new(
+            &self.pam_service,
+            Some(&self.username),
+            Conversation::with_credentials(&self.username, password),
+        )
+        .map_err(|e| format!("Failed to initialize PAM context for {}: {}", self.username, e))?;
+
+        context
+            .authenticate(Flag::NONE)
+            .map_err(|e| format!("Authentication failed for {}: {}", self.username, e))?;
+        // A right password is not enough for an expired or locked account
+        context
+            .acct_mgmt(Flag::NONE)
+            .map_err(|e| format!("Account check failed for {}: {}", self.username, e))?;
+
+        Ok(PamHandle {
+            username: self.username.clone(),
+            context,
+            session: None,
+            env: Vec::new(),
+        })
+    }
+
     pub fn verify_2fa(&mut self, code: &str, _config_path: &Path) -> bool {
         match &mut self.twofactor_method {
             TwoFactorMethod::TOTP => {
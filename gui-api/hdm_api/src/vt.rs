@@ -0,0 +1,414 @@
+use std::ffi::CString;
+use std::os::unix::io::RawFd;
+
+// linux/vt.h, linux/kd.h
+const VT_OPENQRY: u64 = 0x5600;
+const VT_SETMODE: u64 = 0x5602;
+const VT_GETSTATE: u64 = 0x5603;
+const VT_ACTIVATE: u64 = 0x5606;
+const VT_WAITACTIVE: u64 = 0x5607;
+const KDSETMODE: u64 = 0x4B3A;
+
+#[repr(C)]
+struct RawVtMode {
+    mode: libc::c_char,
+    waitv: libc::c_char,
+    relsig: libc::c_short,
+    acqsig: libc::c_short,
+    frsig: libc::c_short,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct RawVtStat {
+    v_active: libc::c_ushort,
+    v_signal: libc::c_ushort,
+    v_state: libc::c_ushort,
+}
+
+/// Console mode of a VT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KdMode {
+    Text = 0,
+    Graphics = 1,
+}
+
+/// Who handles VT switching
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VtMode {
+    /// The kernel switches on its own
+    Auto = 0,
+    /// The process owning the VT acknowledges switches
+    Process = 1,
+}
+
+/// The console operations used for VT handling, behind a trait so the
+/// logic can be exercised without a real console
+pub trait VtBackend {
+    /// First VT that nobody has open
+    fn find_free(&self) -> Result<u32, String>;
+    /// Currently active VT
+    fn active(&self) -> Result<u32, String>;
+    fn open(&self, vt: u32) -> Result<RawFd, String>;
+    fn close(&self, fd: RawFd);
+    fn set_kd_mode(&self, fd: RawFd, mode: KdMode) -> Result<(), String>;
+    fn set_vt_mode(&self, fd: RawFd, mode: VtMode) -> Result<(), String>;
+    fn activate(&self, vt: u32) -> Result<(), String>;
+    /// Block until `vt` is the active VT
+    fn wait_active(&self, vt: u32) -> Result<(), String>;
+}
+
+/// Real console backend using the Linux VT ioctls
+#[derive(Debug, Clone, Default)]
+pub struct LinuxConsole;
+
+impl LinuxConsole {
+    fn with_tty0<T>(&self, f: impl FnOnce(RawFd) -> Result<T, String>) -> Result<T, String> {
+        let fd = open_tty("/dev/tty0")?;
+        let result = f(fd);
+        unsafe { libc::close(fd) };
+        result
+    }
+}
+
+fn open_tty(path: &str) -> Result<RawFd, String> {
+    let c_path = CString::new(path).map_err(|e| e.to_string())?;
+    let fd = unsafe {
+        libc::open(c_path.as_ptr(), libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC)
+    };
+    if fd < 0 {
+        return Err(format!("Failed to open {}: {}", path, std::io::Error::last_os_error()));
+    }
+    Ok(fd)
+}
+
+fn check(ret: libc::c_int, what: &str) -> Result<(), String> {
+    if ret < 0 {
+        return Err(format!("{} failed: {}", what, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+impl VtBackend for LinuxConsole {
+    fn find_free(&self) -> Result<u32, String> {
+        self.with_tty0(|fd| {
+            let mut vt: libc::c_int = -1;
+            check(unsafe { libc::ioctl(fd, VT_OPENQRY as _, &mut vt) }, "VT_OPENQRY")?;
+            if vt <= 0 {
+                return Err("No free VT available".into());
+            }
+            Ok(vt as u32)
+        })
+    }
+
+    fn active(&self) -> Result<u32, String> {
+        self.with_tty0(|fd| {
+            let mut stat = RawVtStat::default();
+            check(unsafe { libc::ioctl(fd, VT_GETSTATE as _, &mut stat) }, "VT_GETSTATE")?;
+            Ok(stat.v_active as u32)
+        })
+    }
+
+    fn open(&self, vt: u32) -> Result<RawFd, String> {
+        open_tty(&format!("/dev/tty{}", vt))
+    }
+
+    fn close(&self, fd: RawFd) {
+        unsafe { libc::close(fd) };
+    }
+
+    fn set_kd_mode(&self, fd: RawFd, mode: KdMode) -> Result<(), String> {
+        check(unsafe { libc::ioctl(fd, KDSETMODE as _, mode as libc::c_int) }, "KDSETMODE")
+    }
+
+    fn set_vt_mode(&self, fd: RawFd, mode: VtMode) -> Result<(), String> {
+        let raw = RawVtMode {
+            mode: mode as libc::c_char,
+            waitv: 0,
+            relsig: 0,
+            acqsig: 0,
+            frsig: 0,
+        };
+        check(unsafe { libc::ioctl(fd, VT_SETMODE as _, &raw) }, "VT_SETMODE")
+    }
+
+    fn activate(&self, vt: u32) -> Result<(), String> {
+        self.with_tty0(|fd| {
+            check(unsafe { libc::ioctl(fd, VT_ACTIVATE as _, vt as libc::c_int) }, "VT_ACTIVATE")
+        })
+    }
+
+    fn wait_active(&self, vt: u32) -> Result<(), String> {
+        self.with_tty0(|fd| loop {
+            let ret = unsafe { libc::ioctl(fd, VT_WAITACTIVE as _, vt as libc::c_int) };
+            if ret < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                continue;
+            }
+            return check(ret, "VT_WAITACTIVE");
+        })
+    }
+}
+
+/// A VT opened and prepared for a graphical session
+#[derive(Debug)]
+pub struct VirtualTerminal {
+    pub number: u32,
+    fd: RawFd,
+}
+
+impl VirtualTerminal {
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for VirtualTerminal {
+    /// A VT dropped without `release` still gives its descriptor back
+    fn drop(&mut self) {
+        if self.fd >= 0 {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+/// Allocates, prepares and switches VTs
+pub struct VtManager<B: VtBackend = LinuxConsole> {
+    pub backend: B,
+}
+
+impl VtManager<LinuxConsole> {
+    pub fn new() -> Self {
+        Self::with_backend(LinuxConsole)
+    }
+}
+
+impl Default for VtManager<LinuxConsole> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: VtBackend> VtManager<B> {
+    pub fn with_backend(backend: B) -> Self {
+        Self { backend }
+    }
+
+    /// Find a free VT, put it in graphics mode and make it active
+    pub fn allocate(&self) -> Result<VirtualTerminal, String> {
+        let number = self.backend.find_free()?;
+        self.prepare(number)
+    }
+
+    /// Open `number`, set KD_GRAPHICS and automatic switching, then activate it
+    pub fn prepare(&self, number: u32) -> Result<VirtualTerminal, String> {
+        let fd = self.backend.open(number)?;
+        let vt = VirtualTerminal { number, fd };
+
+        let setup = self.backend
+            .set_kd_mode(fd, KdMode::Graphics)
+            .and_then(|_| self.backend.set_vt_mode(fd, VtMode::Auto))
+            .and_then(|_| self.switch_to(number));
+
+        match setup {
+            Ok(()) => Ok(vt),
+            Err(e) => {
+                self.release(vt);
+                Err(e)
+            }
+        }
+    }
+
+    /// Activate `number` and wait until the kernel has switched to it
+    pub fn switch_to(&self, number: u32) -> Result<(), String> {
+        if self.backend.active()? == number {
+            return Ok(());
+        }
+        self.backend.activate(number)?;
+        self.backend.wait_active(number)
+    }
+
+    /// Restore text mode and close the VT
+    pub fn release(&self, mut vt: VirtualTerminal) {
+        if let Err(e) = self.backend.set_kd_mode(vt.fd, KdMode::Text) {
+            eprintln!("Failed to restore text mode on VT {}: {}", vt.number, e);
+        }
+        let _ = self.backend.set_vt_mode(vt.fd, VtMode::Auto);
+        self.backend.close(vt.fd);
+        vt.fd = -1;
+    }
+}
+
+/// In-memory console for tests; descriptors are real (/dev/null) so closing them is safe
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default)]
+    pub(crate) struct FakeState {
+        pub active: u32,
+        pub next_free: u32,
+        /// Open descriptor to VT number
+        pub open: BTreeMap<RawFd, u32>,
+        pub kd_modes: BTreeMap<u32, KdMode>,
+        pub vt_modes: BTreeMap<u32, VtMode>,
+        pub calls: Vec<String>,
+        /// Name of the call that should fail, e.g. "set_kd_mode"
+        pub fail: Option<&'static str>,
+    }
+
+    #[derive(Debug, Default)]
+    pub(crate) struct FakeConsole {
+        pub state: RefCell<FakeState>,
+    }
+
+    impl FakeConsole {
+        pub fn new(active: u32, next_free: u32) -> Self {
+            let console = Self::default();
+            {
+                let mut state = console.state.borrow_mut();
+                state.active = active;
+                state.next_free = next_free;
+            }
+            console
+        }
+
+        fn call(&self, name: &'static str, detail: String) -> Result<(), String> {
+            let mut state = self.state.borrow_mut();
+            state.calls.push(format!("{} {}", name, detail).trim_end().to_string());
+            if state.fail == Some(name) {
+                return Err(format!("{} failed", name));
+            }
+            Ok(())
+        }
+
+        fn vt_of(&self, fd: RawFd) -> u32 {
+            self.state.borrow().open.get(&fd).copied().expect("descriptor not opened by the fake")
+        }
+    }
+
+    impl VtBackend for FakeConsole {
+        fn find_free(&self) -> Result<u32, String> {
+            self.call("find_free", String::new())?;
+            let mut state = self.state.borrow_mut();
+            let vt = state.next_free;
+            state.next_free += 1;
+            Ok(vt)
+        }
+
+        fn active(&self) -> Result<u32, String> {
+            self.call("active", String::new())?;
+            Ok(self.state.borrow().active)
+        }
+
+        fn open(&self, vt: u32) -> Result<RawFd, String> {
+            self.call("open", vt.to_string())?;
+            let fd = open_tty("/dev/null")?;
+            self.state.borrow_mut().open.insert(fd, vt);
+            Ok(fd)
+        }
+
+        fn close(&self, fd: RawFd) {
+            let vt = self.vt_of(fd);
+            let _ = self.call("close", vt.to_string());
+            self.state.borrow_mut().open.remove(&fd);
+            unsafe { libc::close(fd) };
+        }
+
+        fn set_kd_mode(&self, fd: RawFd, mode: KdMode) -> Result<(), String> {
+            let vt = self.vt_of(fd);
+            self.call("set_kd_mode", format!("{} {:?}", vt, mode))?;
+            self.state.borrow_mut().kd_modes.insert(vt, mode);
+            Ok(())
+        }
+
+        fn set_vt_mode(&self, fd: RawFd, mode: VtMode) -> Result<(), String> {
+            let vt = self.vt_of(fd);
+            self.call("set_vt_mode", format!("{} {:?}", vt, mode))?;
+            self.state.borrow_mut().vt_modes.insert(vt, mode);
+            Ok(())
+        }
+
+        fn activate(&self, vt: u32) -> Result<(), String> {
+            self.call("activate", vt.to_string())?;
+            self.state.borrow_mut().active = vt;
+            Ok(())
+        }
+
+        fn wait_active(&self, vt: u32) -> Result<(), String> {
+            self.call("wait_active", vt.to_string())?;
+            if self.state.borrow().active != vt {
+                return Err(format!("VT {} never became active", vt));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::FakeConsole;
+    use super::*;
+
+    fn fd_is_open(fd: RawFd) -> bool {
+        (unsafe { libc::fcntl(fd, libc::F_GETFD) }) >= 0
+    }
+
+    #[test]
+    fn allocate_prepares_and_activates_a_free_vt() {
+        let vts = VtManager::with_backend(FakeConsole::new(1, 7));
+        let vt = vts.allocate().unwrap();
+        assert_eq!(vt.number, 7);
+
+        let state = vts.backend.state.borrow();
+        assert_eq!(state.active, 7);
+        assert_eq!(state.kd_modes[&7], KdMode::Graphics);
+        assert_eq!(state.vt_modes[&7], VtMode::Auto);
+        assert_eq!(
+            state.calls,
+            ["find_free", "open 7", "set_kd_mode 7 Graphics", "set_vt_mode 7 Auto", "active", "activate 7", "wait_active 7"]
+        );
+    }
+
+    #[test]
+    fn switching_to_the_active_vt_does_nothing() {
+        let vts = VtManager::with_backend(FakeConsole::new(3, 7));
+        vts.switch_to(3).unwrap();
+        assert_eq!(vts.backend.state.borrow().calls, ["active"]);
+    }
+
+    #[test]
+    fn release_restores_text_mode_and_closes() {
+        let vts = VtManager::with_backend(FakeConsole::new(1, 2));
+        let vt = vts.allocate().unwrap();
+        let fd = vt.fd();
+        vts.release(vt);
+
+        let state = vts.backend.state.borrow();
+        assert_eq!(state.kd_modes[&2], KdMode::Text);
+        assert!(state.open.is_empty());
+        assert!(!fd_is_open(fd));
+    }
+
+    #[test]
+    fn failed_setup_releases_the_vt() {
+        let vts = VtManager::with_backend(FakeConsole::new(1, 4));
+        vts.backend.state.borrow_mut().fail = Some("activate");
+        assert!(vts.allocate().is_err());
+
+        let state = vts.backend.state.borrow();
+        assert!(state.open.is_empty());
+        assert_eq!(state.kd_modes[&4], KdMode::Text);
+    }
+
+    #[test]
+    fn dropping_an_unreleased_vt_closes_its_descriptor() {
+        let vts = VtManager::with_backend(FakeConsole::new(1, 5));
+        let vt = vts.allocate().unwrap();
+        let fd = vt.fd();
+        assert!(fd_is_open(fd));
+        drop(vt);
+        assert!(!fd_is_open(fd));
+    }
+}
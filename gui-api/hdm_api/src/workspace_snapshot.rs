@@ -0,0 +1,438 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::lookup_user;
+use crate::ipc::{list_instances, BatchReport, CommandBatch, HyprCommand, HyprlandEvent, HyprlandIPC, HyprlandInstance};
+use crate::session_manager::{shell_quote, split_exec};
+use crate::session_policy::{user_home, FsUser};
+
+/// Events are recorded once the layout has been quiet this long
+const RECORD_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Active workspace of one monitor
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct MonitorSnapshot {
+    pub name: String,
+    pub workspace_id: i32,
+    pub workspace_name: String,
+    pub focused: bool,
+}
+
+/// One mapped window
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct WindowSnapshot {
+    pub class: String,
+    pub initial_class: String,
+    pub title: String,
+    pub workspace_id: i32,
+    pub workspace_name: String,
+    pub floating: bool,
+    pub at: [i32; 2],
+    pub size: [i32; 2],
+}
+
+/// Which applications were open where, saved when a session stops
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LayoutSnapshot {
+    /// Unix time in seconds
+    pub saved_at: u64,
+    pub monitors: Vec<MonitorSnapshot>,
+    pub windows: Vec<WindowSnapshot>,
+}
+
+/// `~/.local/state/hyprdm/workspaces.json`
+pub fn snapshot_path(home: &Path) -> PathBuf {
+    home.join(".local/state/hyprdm/workspaces.json")
+}
+
+impl LayoutSnapshot {
+    pub fn capture(ipc: &HyprlandIPC) -> Result<Self, String> {
+        let monitors = ipc
+            .monitors()?
+            .into_iter()
+            .filter(|m| !m.disabled)
+            .map(|m| MonitorSnapshot {
+                name: m.name,
+                workspace_id: m.active_workspace.id,
+                workspace_name: m.active_workspace.name,
+                focused: m.focused,
+            })
+            .collect();
+
+        let mut windows: Vec<WindowSnapshot> = ipc
+            .clients()?
+            .into_iter()
+            .filter(|c| c.mapped && c.workspace.id != 0 && !(c.class.is_empty() && c.initial_class.is_empty()))
+            .map(|c| WindowSnapshot {
+                class: c.class,
+                initial_class: c.initial_class,
+                title: c.title,
+                workspace_id: c.workspace.id,
+                workspace_name: c.workspace.name,
+                floating: c.floating,
+                at: c.at,
+                size: c.size,
+            })
+            .collect();
+        // Tiled windows come back in the order they are launched, so keep them left to right
+        windows.sort_by_key(|w| (w.workspace_id, w.at[0], w.at[1]));
+
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(Self { saved_at, monitors, windows })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid layout snapshot {:?}: {}", path, e))
+    }
+
+    /// Write through a temporary file so a crash never leaves half a snapshot
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = path.with_extension("json.tmp");
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&tmp)
+            .map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+        file.write_all(json.as_bytes())
+            .map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// Relaunch every window whose application has a desktop entry, then bring back the
+    /// active workspaces. Windows without a match are skipped and listed in the report.
+    pub fn restore(&self, ipc: &HyprlandIPC, home: &Path) -> Result<BatchReport, String> {
+        let apps = desktop_apps(home);
+        let mut report = BatchReport { results: Vec::new() };
+
+        for window in &self.windows {
+            let app = match find_app(&apps, window) {
+                Some(app) => app,
+                None => {
+                    let command = HyprCommand::dispatch("exec", &window.class);
+                    let error = format!("No desktop entry for window class {:?}", window.class);
+                    report.results.push((command, Err(error)));
+                    continue;
+                }
+            };
+
+            let mut rules = vec![format!(
+                "workspace {} silent",
+                workspace_selector(window.workspace_id, &window.workspace_name)
+            )];
+            if window.floating {
+                rules.push("float".to_string());
+                rules.push(format!("move {} {}", window.at[0], window.at[1]));
+                rules.push(format!("size {} {}", window.size[0], window.size[1]));
+            }
+
+            // Rules are separated by ';', which a batch would split on, so send them one by one
+            let command = HyprCommand::dispatch("exec", &format!("[{}] {}", rules.join(";"), app.command_line()));
+            let result = ipc.execute(&command);
+            report.results.push((command, result));
+        }
+
+        // The focused monitor goes last so it keeps the focus; special workspaces
+        // can only be toggled, not made a monitor's active one
+        let mut monitors: Vec<&MonitorSnapshot> = self
+            .monitors
+            .iter()
+            .filter(|m| !m.workspace_name.starts_with("special"))
+            .collect();
+        monitors.sort_by_key(|m| m.focused);
+        let mut batch = CommandBatch::new();
+        for monitor in monitors {
+            batch = batch
+                .dispatch("focusmonitor", &monitor.name)
+                .dispatch("workspace", &workspace_selector(monitor.workspace_id, &monitor.workspace_name));
+        }
+        report.results.extend(batch.send(ipc)?.results);
+
+        Ok(report)
+    }
+}
+
+/// `workspace` argument for a workspace reference: id, `name:<name>` or `special:<name>`
+fn workspace_selector(id: i32, name: &str) -> String {
+    if id > 0 {
+        id.to_string()
+    } else if name.starts_with("special") || name.starts_with("name:") {
+        name.to_string()
+    } else {
+        format!("name:{}", name)
+    }
+}
+
+/// An application that can be launched to bring a window back
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopApp {
+    /// Desktop file name without `.desktop`
+    pub id: String,
+    pub exec: Vec<String>,
+    pub wm_class: Option<String>,
+}
+
+impl DesktopApp {
+    /// Exec as a shell command line; Hyprland runs `exec` through `sh -c`
+    pub fn command_line(&self) -> String {
+        self.exec.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Application directories, the user's own first so their overrides win
+fn application_dirs(home: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![
+        home.join(".local/share/applications"),
+        home.join(".local/share/flatpak/exports/share/applications"),
+    ];
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(data_dirs.split(':').filter(|d| !d.is_empty()).map(|d| Path::new(d).join("applications")));
+    dirs.push(PathBuf::from("/var/lib/flatpak/exports/share/applications"));
+    dirs
+}
+
+/// Launchable desktop entries, first one per desktop ID
+pub fn desktop_apps(home: &Path) -> Vec<DesktopApp> {
+    let mut apps = Vec::new();
+    let mut seen = HashSet::new();
+
+    for dir in application_dirs(home) {
+        let mut files: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        files.sort();
+
+        for path in files {
+            if path.extension().and_then(|s| s.to_str()) != Some("desktop") {
+                continue;
+            }
+            let id = match path.file_stem() {
+                Some(stem) => stem.to_string_lossy().to_string(),
+                None => continue,
+            };
+            // A hidden entry in the user's directory still shadows the system one
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            if let Some(app) = fs::read_to_string(&path).ok().and_then(|c| parse_desktop_entry(&id, &c)) {
+                apps.push(app);
+            }
+        }
+    }
+    apps
+}
+
+fn parse_desktop_entry(id: &str, content: &str) -> Option<DesktopApp> {
+    let mut in_entry = false;
+    let mut exec = None;
+    let mut wm_class = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Exec=") {
+            exec = Some(split_exec(value));
+        } else if let Some(value) = line.strip_prefix("StartupWMClass=") {
+            wm_class = Some(value.to_string());
+        } else if (line.starts_with("Type=") && line != "Type=Application") || line == "Hidden=true" {
+            return None;
+        }
+    }
+
+    let exec = exec.filter(|e| !e.is_empty())?;
+    Some(DesktopApp { id: id.to_string(), exec, wm_class })
+}
+
+/// StartupWMClass first, then the desktop ID (or its last reverse-DNS part), then the Exec binary
+pub fn find_app<'a>(apps: &'a [DesktopApp], window: &WindowSnapshot) -> Option<&'a DesktopApp> {
+    let classes: Vec<String> = [&window.initial_class, &window.class]
+        .iter()
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_lowercase())
+        .collect();
+    let matches = |name: &str| classes.iter().any(|c| *c == name.to_lowercase());
+
+    apps.iter()
+        .find(|a| a.wm_class.as_deref().map(matches).unwrap_or(false))
+        .or_else(|| apps.iter().find(|a| matches(&a.id) || a.id.rsplit('.').next().map(matches).unwrap_or(false)))
+        .or_else(|| {
+            apps.iter().find(|a| {
+                Path::new(&a.exec[0])
+                    .file_name()
+                    .map(|n| matches(&n.to_string_lossy()))
+                    .unwrap_or(false)
+            })
+        })
+}
+
+/// Hyprland instance of `uid`, if one is running
+pub fn user_instance(uid: u32) -> Option<HyprlandInstance> {
+    list_instances().into_iter().find(|i| i.uid == uid)
+}
+
+/// Snapshot the running Hyprland of `user` into their state dir
+pub fn save_for_user(user: &str) -> Result<PathBuf, String> {
+    let (uid, gid) = lookup_user(user)?;
+    let home = user_home(user).ok_or_else(|| format!("User {} has no home directory", user))?;
+    let instance = user_instance(uid).ok_or_else(|| format!("No Hyprland running for {}", user))?;
+
+    let snapshot = LayoutSnapshot::capture(&instance.ipc())?;
+    let path = snapshot_path(&home);
+    let _as_user = FsUser::switch(uid, gid);
+    snapshot.save(&path)?;
+    Ok(path)
+}
+
+/// Wait up to `wait` for the Hyprland of `user` to answer, then replay their saved layout
+pub fn restore_for_user(user: &str, wait: Duration) -> Result<BatchReport, String> {
+    let (uid, gid) = lookup_user(user)?;
+    let home = user_home(user).ok_or_else(|| format!("User {} has no home directory", user))?;
+    let snapshot = {
+        let _as_user = FsUser::switch(uid, gid);
+        LayoutSnapshot::load(&snapshot_path(&home))?
+    };
+
+    let deadline = Instant::now() + wait;
+    let instance = loop {
+        if let Some(instance) = user_instance(uid) {
+            break instance;
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("Hyprland of {} did not come up within {:?}", user, wait));
+        }
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    let _as_user = FsUser::switch(uid, gid);
+    snapshot.restore(&instance.ipc(), &home)
+}
+
+/// Keeps the snapshot of a user's session current while it runs, so a logout
+/// through Hyprland's own `exit` still leaves the last layout behind
+pub struct LayoutRecorder {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LayoutRecorder {
+    /// Wait up to `wait` for the user's Hyprland, then record until it exits
+    pub fn spawn(user: &str, wait: Duration) -> Result<Self, String> {
+        let (uid, gid) = lookup_user(user)?;
+        let home = user_home(user).ok_or_else(|| format!("User {} has no home directory", user))?;
+        let running = Arc::new(AtomicBool::new(true));
+        let user = user.to_string();
+
+        let flag = running.clone();
+        let handle = thread::spawn(move || {
+            let deadline = Instant::now() + wait;
+            let instance = loop {
+                if !flag.load(Ordering::SeqCst) {
+                    return;
+                }
+                if let Some(instance) = user_instance(uid) {
+                    break instance;
+                }
+                if Instant::now() >= deadline {
+                    eprintln!("[HyprDM] No Hyprland for {}, layout is not recorded", user);
+                    return;
+                }
+                thread::sleep(Duration::from_millis(500));
+            };
+            record(&instance, &snapshot_path(&home), (uid, gid), &flag);
+        });
+
+        Ok(Self { running, handle: Some(handle) })
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for LayoutRecorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn record(instance: &HyprlandInstance, path: &Path, (uid, gid): (u32, u32), running: &AtomicBool) {
+    let mut events = instance.events();
+    let rx = match events.spawn() {
+        Ok(rx) => rx,
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            return;
+        }
+    };
+    let ipc = instance.ipc();
+    let alive = |pid: Option<u32>| pid.map(|p| Path::new(&format!("/proc/{}", p)).exists()).unwrap_or(true);
+    let mut changed: Option<Instant> = None;
+
+    while running.load(Ordering::SeqCst) && alive(instance.pid) {
+        match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(
+                HyprlandEvent::OpenWindow { .. }
+                | HyprlandEvent::CloseWindow { .. }
+                | HyprlandEvent::MoveWindow { .. }
+                | HyprlandEvent::WorkspaceV2 { .. }
+                | HyprlandEvent::FocusedMonitor { .. },
+            ) => changed = Some(Instant::now()),
+            Err(RecvTimeoutError::Disconnected) => break,
+            _ => {}
+        }
+
+        if changed.map(|t| t.elapsed() >= RECORD_DEBOUNCE).unwrap_or(false) {
+            changed = None;
+            match LayoutSnapshot::capture(&ipc) {
+                // Windows closing on the way out of the session would wipe the snapshot
+                Ok(snapshot) if snapshot.windows.is_empty() => {}
+                Ok(snapshot) => {
+                    let _as_user = FsUser::switch(uid, gid);
+                    if let Err(e) = snapshot.save(path) {
+                        eprintln!("[HyprDM] {}", e);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    events.stop();
+}
@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use config::{load_config, HDMConfig};
+use hdm_api::compositor::Compositor;
+use hdm_api::daemon::Daemon;
+use hdm_api::event_loop::StopHandle;
+use hdm_api::greeter::{prepare_runtime_dir, GreeterCompositor, GreeterLauncher};
+use hdm_api::headless::preview_interface;
+use hdm_api::monitor_config::greeter_monitor_rules;
+
+const CONFIG_PATH: &str = "/etc/hyprdm/hyprdm.conf";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Previews need no privileges, so they come before the root check
+    if args.first().map(String::as_str) == Some("preview") {
+        if let Err(e) = preview(&args[1..]) {
+            eprintln!("[HyprDM] {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("hyprdmd must run as root");
+        process::exit(1);
+    }
+
+    let config = load_config(Path::new(CONFIG_PATH)).unwrap_or_else(|e| {
+        eprintln!("[HyprDM] {}, using defaults", e);
+        HDMConfig::default()
+    });
+
+    if let Err(e) = run(config) {
+        eprintln!("[HyprDM] {}", e);
+        process::exit(1);
+    }
+}
+
+/// Greeter -> login -> session -> greeter, forever
+fn run(config: HDMConfig) -> Result<(), String> {
+    let mut daemon = Daemon::from_config(config.clone())?;
+    let listener = daemon.bind()?;
+    println!("[HyprDM] Listening on {:?}", daemon.socket_path);
+
+    loop {
+        let vt = daemon.registry().open_greeter()?;
+        let mut greeter = GreeterLauncher::from_config(&config)?.with_vt(vt);
+        // The builtin greeter connects to our own compositor, it has to listen first
+        let compositor = match greeter.compositor {
+            GreeterCompositor::Builtin => Some(start_compositor(&config, &greeter)?),
+            GreeterCompositor::Hyprland(_) => None,
+        };
+        let events = greeter.spawn()?;
+        thread::spawn(move || {
+            for event in events {
+                println!("[HyprDM] Greeter: {:?}", event);
+            }
+        });
+
+        let id = daemon.run(&listener, |event| println!("[HyprDM] {:?}", event))?;
+        greeter.stop();
+        if let Some((stop, handle)) = compositor {
+            stop.stop();
+            let _ = handle.join();
+        }
+        daemon.registry().close_greeter();
+
+        while daemon.registry().get(id).is_some() {
+            thread::sleep(Duration::from_secs(1));
+            daemon.registry().reap();
+        }
+        println!("[HyprDM] Session {} ended", id);
+    }
+}
+
+/// Run hyprdm's compositor on the greeter's WAYLAND_DISPLAY in its runtime dir. The
+/// compositor is not Send, so it is built on the thread that runs it
+fn start_compositor(config: &HDMConfig, greeter: &GreeterLauncher) -> Result<(StopHandle, JoinHandle<()>), String> {
+    let owner = greeter.user.as_ref().map(|u| (u.uid, u.gid));
+    prepare_runtime_dir(&greeter.runtime_dir, owner)?;
+    let socket = greeter.runtime_dir.join(&greeter.wayland_display);
+    let rules = greeter_monitor_rules(config).unwrap_or_else(|e| {
+        eprintln!("[HyprDM] Using the default monitor layout: {}", e);
+        Vec::new()
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut compositor = match Compositor::new().and_then(|mut c| c.listen_on(&socket, owner).map(|_| c)) {
+            Ok(c) => c,
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        };
+        compositor.apply_monitor_rules(&rules);
+        let _ = tx.send(Ok(compositor.stop_handle()));
+        if let Err(e) = compositor.run_with_ipc(None) {
+            eprintln!("[HyprDM] Greeter compositor stopped: {}", e);
+        }
+    });
+
+    let stop = rx.recv().map_err(|_| "Greeter compositor failed to start".to_string())??;
+    println!("[HyprDM] Greeter compositor listening on {:?}", greeter.runtime_dir.join(&greeter.wayland_display));
+    Ok((stop, handle))
+}
+
+/// `hyprdmd preview [interface|main.qml] [--size WxH[,WxH...]] [--output file.png] [--timeout secs]`
+/// renders an interface on virtual outputs without a GPU and saves a PNG per output
+fn preview(args: &[String]) -> Result<(), String> {
+    let mut config = load_config(Path::new(CONFIG_PATH)).unwrap_or_default();
+    let mut interface = None;
+    let mut sizes = vec![(1920, 1080)];
+    let mut output = PathBuf::from("preview.png");
+    let mut timeout = Duration::from_secs(20);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().cloned().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--size" => sizes = parse_sizes(&value("--size")?)?,
+            "--output" => output = PathBuf::from(value("--output")?),
+            "--timeout" => {
+                let secs = value("--timeout")?;
+                timeout = Duration::from_secs(secs.parse().map_err(|_| format!("Invalid timeout {:?}", secs))?);
+            }
+            a if a.starts_with("--") => return Err(format!("Unknown option {}", a)),
+            a => interface = Some(a.to_string()),
+        }
+    }
+
+    let ui = match interface {
+        None => config.resolve_quickshell_ui()?,
+        // A path to an interface directory or its main.qml
+        Some(path) if path.contains('/') => {
+            let path = PathBuf::from(path);
+            if path.is_dir() { path.join("main.qml") } else { path }
+        }
+        Some(name) => {
+            config.greeter_interface = name.clone();
+            config.greeter_interface_fallbacks.clear();
+            config.find_quickshell_ui().ok_or_else(|| format!("No interface called {}", name))?
+        }
+    };
+    if !ui.is_file() {
+        return Err(format!("{:?} does not exist", ui));
+    }
+
+    for path in preview_interface("quickshell", &ui, &sizes, &output, timeout)? {
+        println!("[HyprDM] Saved {:?}", path);
+    }
+    Ok(())
+}
+
+/// `1920x1080` or a comma separated list, one virtual output each
+fn parse_sizes(value: &str) -> Result<Vec<(u32, u32)>, String> {
+    value
+        .split(',')
+        .map(|size| {
+            size.trim()
+                .split_once('x')
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                .filter(|(w, h): &(u32, u32)| *w > 0 && *h > 0 && *w <= 16384 && *h <= 16384)
+                .ok_or_else(|| format!("Invalid size {:?}, expected WIDTHxHEIGHT", size))
+        })
+        .collect()
+}
@@ -108,6 +108,7 @@ function updateTwoFA() {
     }
 
     Component.onCompleted: {
+        console.log("hyprdm:ready")
         if (currentUser && currentUser.length > 0)
             usernameField.text = currentUser
         updateTwoFA()
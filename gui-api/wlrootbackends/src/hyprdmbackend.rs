@@ -1,17 +1,25 @@
 // -------------------- FFI FULL --------------------
-use std::os::raw::{c_char, c_int};
+use std::os::raw::{c_char, c_int, c_void};
 use std::ffi::{CStr, CString};
 use std::sync::Arc;
 use std::ptr;
 use std::path::Path;
 
 use hdm_api::compositor::Compositor;
-use hdm_api::ipc::HyprlandIPC;
+use hdm_api::daemon::{DaemonClient, Request, Response};
+use hdm_api::event_loop::StopHandle;
+use hdm_api::greeter::{GreeterEvent, GreeterLauncher};
+use hdm_api::ipc::{EventSubscriber, HyprCommand, HyprlandIPC};
 use hdm_api::layout_manager::{LayoutManager, Layout, Panel};
+use hdm_api::monitor_config::{greeter_monitor_rules, parse_monitor_rules};
 use hdm_api::session_manager::Session;
+use hdm_api::session_registry::SessionRegistry;
+use hdm_api::session_policy;
+use hdm_api::session_supervisor::{SessionEvent, SessionSupervisor, CrashPolicy};
 use hdm_api::theme_manager::ThemeManager;
 use hdm_api::unidata::{UnidataGenerator, PlatformType};
 use hdm_api::user_manager::{User, TwoFactorMethod};
+use hdm_api::vt::{VtBackend, VtManager};
 
 // -------------------- Compositor FFI --------------------
 #[no_mangle]
@@ -26,15 +34,16 @@ pub extern "C" fn compositor_new() -> *mut Compositor {
 pub extern "C" fn compositor_run_with_ipc(c: *mut Compositor, ipc: *mut HyprlandIPC) -> c_int {
     if c.is_null() { return -1; }
     let compositor = unsafe { &mut *c };
+    // ipc stays owned by the caller (ipc_free), the compositor gets its own copy
     let ipc_arc = if ipc.is_null() {
         None
     } else {
-        Some(unsafe { Arc::from_raw(ipc) })
+        Some(Arc::new(unsafe { &*ipc }.clone()))
     };
-    let result = compositor.run_with_ipc(ipc_arc).is_ok() as c_int;
-    result
+    compositor.run_with_ipc(ipc_arc).is_ok() as c_int
 }
 
+/// Only from the thread that runs the compositor, other threads use compositor_stop_handle
 #[no_mangle]
 pub extern "C" fn compositor_stop(c: *mut Compositor) {
     if !c.is_null() {
@@ -43,10 +52,97 @@ pub extern "C" fn compositor_stop(c: *mut Compositor) {
     }
 }
 
+/// Take this before compositor_run_with_ipc; stop_handle_stop works from any thread
+#[no_mangle]
+pub extern "C" fn compositor_stop_handle(c: *mut Compositor) -> *mut StopHandle {
+    if c.is_null() { return ptr::null_mut(); }
+    let compositor = unsafe { &*c };
+    Box::into_raw(Box::new(compositor.stop_handle()))
+}
+
+#[no_mangle]
+pub extern "C" fn stop_handle_stop(h: *mut StopHandle) {
+    if !h.is_null() {
+        unsafe { &*h }.stop();
+    }
+}
+
+/// Stop on SIGINT/SIGTERM while running
+#[no_mangle]
+pub extern "C" fn compositor_handle_signals(c: *mut Compositor, enable: c_int) {
+    if !c.is_null() {
+        unsafe { &mut *c }.event_loop.handle_signals = enable != 0;
+    }
+}
+
+/// Called on the compositor thread for every event of the Hyprland passed to compositor_run_with_ipc
+#[no_mangle]
+pub extern "C" fn compositor_on_hyprland_event(
+    c: *mut Compositor,
+    cb: Option<HyprlandEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    if c.is_null() { return -1; }
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return -1,
+    };
+    let compositor = unsafe { &mut *c };
+    let data = CallbackData(user_data);
+    compositor.on_hyprland_event(move |event| {
+        let (name, value) = event.raw();
+        let name = CString::new(name).unwrap_or_default();
+        let value = CString::new(value).unwrap_or_default();
+        cb(name.as_ptr(), value.as_ptr(), data.get());
+    });
+    0
+}
+
+/// Apply a monitor layout: NULL follows `greeter_monitors` from the config, otherwise
+/// the hyprland.conf at `path`. Returns the number of rules read, or -1
+#[no_mangle]
+pub extern "C" fn compositor_import_monitors(c: *mut Compositor, path: *const c_char) -> c_int {
+    if c.is_null() { return -1; }
+    let compositor = unsafe { &mut *c };
+    let rules = if path.is_null() {
+        let config = config::load_config(Path::new("/etc/hyprdm/hyprdm.conf")).unwrap_or_default();
+        greeter_monitor_rules(&config)
+    } else {
+        let path = unsafe { CStr::from_ptr(path) }.to_string_lossy().to_string();
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/".into());
+        parse_monitor_rules(Path::new(&path), Path::new(&home))
+    };
+    match rules {
+        Ok(rules) => {
+            compositor.apply_monitor_rules(&rules);
+            rules.len() as c_int
+        }
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            -1
+        }
+    }
+}
+
+/// Outputs the compositor announces, one per enabled monitor rule after compositor_import_monitors
+#[no_mangle]
+pub extern "C" fn compositor_output_count(c: *mut Compositor) -> c_int {
+    if c.is_null() { return -1; }
+    unsafe { &*c }.outputs.len() as c_int
+}
+
 // -------------------- IPC FFI --------------------
 #[no_mangle]
 pub extern "C" fn ipc_new() -> *mut HyprlandIPC {
-    Box::into_raw(Box::new(HyprlandIPC))
+    Box::into_raw(Box::new(HyprlandIPC::new()))
+}
+
+/// Talk to a specific request socket instead of the current instance
+#[no_mangle]
+pub extern "C" fn ipc_new_with_socket(path: *const c_char) -> *mut HyprlandIPC {
+    if path.is_null() { return ptr::null_mut(); }
+    let path_str = unsafe { CStr::from_ptr(path) }.to_string_lossy().to_string();
+    Box::into_raw(Box::new(HyprlandIPC::with_socket(Path::new(&path_str))))
 }
 
 #[no_mangle]
@@ -68,6 +164,143 @@ pub extern "C" fn ipc_get_status(ipc: *mut HyprlandIPC) -> *mut c_char {
     }
 }
 
+/// Talk to the instance with this HYPRLAND_INSTANCE_SIGNATURE, NULL if it is not running
+#[no_mangle]
+pub extern "C" fn ipc_new_for_instance(signature: *const c_char) -> *mut HyprlandIPC {
+    if signature.is_null() { return ptr::null_mut(); }
+    let signature = unsafe { CStr::from_ptr(signature) }.to_string_lossy().to_string();
+    match HyprlandIPC::from_instance(&signature) {
+        Ok(ipc) => Box::into_raw(Box::new(ipc)),
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// `dispatch <dispatcher> <args>`, args may be NULL
+#[no_mangle]
+pub extern "C" fn ipc_dispatch(ipc: *mut HyprlandIPC, dispatcher: *const c_char, args: *const c_char) -> c_int {
+    if ipc.is_null() || dispatcher.is_null() { return -1; }
+    let ipc_ref = unsafe { &*ipc };
+    let dispatcher = unsafe { CStr::from_ptr(dispatcher) }.to_string_lossy().to_string();
+    let args = if args.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(args) }.to_string_lossy().to_string()
+    };
+    match ipc_ref.execute(&HyprCommand::dispatch(&dispatcher, &args)) {
+        Ok(()) => 1,
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            0
+        }
+    }
+}
+
+/// `keyword <key> <value>`
+#[no_mangle]
+pub extern "C" fn ipc_keyword(ipc: *mut HyprlandIPC, key: *const c_char, value: *const c_char) -> c_int {
+    if ipc.is_null() || key.is_null() || value.is_null() { return -1; }
+    let ipc_ref = unsafe { &*ipc };
+    let key = unsafe { CStr::from_ptr(key) }.to_string_lossy().to_string();
+    let value = unsafe { CStr::from_ptr(value) }.to_string_lossy().to_string();
+    match ipc_ref.execute(&HyprCommand::keyword(&key, &value)) {
+        Ok(()) => 1,
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            0
+        }
+    }
+}
+
+/// Geometry of one monitor as reported by `j/monitors`
+#[repr(C)]
+pub struct MonitorInfo {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    pub transform: u32,
+    pub focused: c_int,
+}
+
+/// Fills `out` with up to `max` monitors, returns how many there are or -1
+#[no_mangle]
+pub extern "C" fn ipc_get_monitors(ipc: *mut HyprlandIPC, out: *mut MonitorInfo, max: u32) -> c_int {
+    if ipc.is_null() { return -1; }
+    let ipc_ref = unsafe { &*ipc };
+    let monitors = match ipc_ref.monitors() {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            return -1;
+        }
+    };
+    if !out.is_null() {
+        for (i, m) in monitors.iter().take(max as usize).enumerate() {
+            let info = MonitorInfo {
+                x: m.x,
+                y: m.y,
+                width: m.width,
+                height: m.height,
+                scale: m.scale,
+                transform: m.transform as u32,
+                focused: m.focused as c_int,
+            };
+            unsafe { *out.add(i) = info; }
+        }
+    }
+    monitors.len() as c_int
+}
+
+// -------------------- Hyprland events FFI --------------------
+/// `name` and `data` are the two halves of an `EVENT>>DATA` line, valid only during the call
+pub type HyprlandEventCallback = extern "C" fn(name: *const c_char, data: *const c_char, user_data: *mut c_void);
+
+/// path NULL follows HYPRLAND_INSTANCE_SIGNATURE
+#[no_mangle]
+pub extern "C" fn ipc_events_new(path: *const c_char) -> *mut EventSubscriber {
+    let subscriber = if path.is_null() {
+        EventSubscriber::new()
+    } else {
+        let path_str = unsafe { CStr::from_ptr(path) }.to_string_lossy().to_string();
+        EventSubscriber::with_socket(Path::new(&path_str))
+    };
+    Box::into_raw(Box::new(subscriber))
+}
+
+#[no_mangle]
+pub extern "C" fn ipc_events_start(
+    sub: *mut EventSubscriber,
+    cb: Option<HyprlandEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    if sub.is_null() { return -1; }
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return 0,
+    };
+    let sub_ref = unsafe { &mut *sub };
+    let data = CallbackData(user_data);
+    sub_ref.on_event(move |event| {
+        let (name, value) = event.raw();
+        let name = CString::new(name).unwrap_or_default();
+        let value = CString::new(value).unwrap_or_default();
+        cb(name.as_ptr(), value.as_ptr(), data.get());
+    });
+    // Events go to the callback, the channel is not needed
+    sub_ref.spawn().is_ok() as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn ipc_events_stop(sub: *mut EventSubscriber) {
+    if sub.is_null() { return; }
+    let sub_ref = unsafe { &mut *sub };
+    sub_ref.stop();
+}
+
 // -------------------- LayoutManager FFI --------------------
 
 #[no_mangle]
@@ -141,17 +374,62 @@ pub extern "C" fn string_free(s: *mut c_char) {
 }
 
 // -------------------- Session FFI --------------------
+/// The policy needs the config; without it nothing is allowed
+fn policy_config() -> Result<config::HDMConfig, String> {
+    config::load_config(Path::new("/etc/hyprdm/hyprdm.conf"))
+        .map_err(|e| format!("Session policy unavailable, refusing: {}", e))
+}
+
+/// Session policy for the user the session belongs to; a session without one is refused
+fn session_allowed(session: &Session) -> bool {
+    let checked = match &session.user {
+        Some(user) => policy_config().and_then(|config| session_policy::check_session(&config, user, session)),
+        None => Err(format!("Session '{}' has no user", session.name)),
+    };
+    match checked {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            false
+        }
+    }
+}
+
+/// The installed session `name` (desktop ID, name or alias) as `user` may start it.
+/// What runs always comes from the desktop entry, never from the caller
+fn find_session(user: &str, name: &str) -> Result<Session, String> {
+    let config = policy_config()?;
+    let session = session_policy::find_session_for_user(&config, user, name)?;
+    let home = session_policy::user_home(user).unwrap_or_else(|| Path::new("/home").join(user));
+    Ok(session.with_config(&config, &home))
+}
+
+fn c_str_arg(value: *const c_char) -> Option<String> {
+    if value.is_null() { return None; }
+    Some(unsafe { CStr::from_ptr(value) }.to_string_lossy().to_string())
+}
+
+/// Session `desktop_id` for `user`, the one logging in; NULL when the policy does not
+/// allow it or the config can't be read
 #[no_mangle]
-pub extern "C" fn session_new(name: *const c_char, exec: *const c_char) -> *mut Session {
-    let name_str = unsafe { CStr::from_ptr(name).to_string_lossy() }.to_string();
-    let exec_str = unsafe { CStr::from_ptr(exec).to_string_lossy() }.to_string();
-    Box::into_raw(Box::new(Session::new(&name_str, &exec_str)))
+pub extern "C" fn session_new(user: *const c_char, desktop_id: *const c_char) -> *mut Session {
+    let (Some(user_str), Some(id_str)) = (c_str_arg(user), c_str_arg(desktop_id)) else {
+        return ptr::null_mut();
+    };
+    match find_session(&user_str, &id_str) {
+        Ok(session) => Box::into_raw(Box::new(session)),
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            ptr::null_mut()
+        }
+    }
 }
 
 #[no_mangle]
 pub extern "C" fn session_start(s: *mut Session) -> c_int {
     if s.is_null() { return -1; }
     let session = unsafe { &mut *s };
+    if !session_allowed(session) { return 0; }
     session.start().is_ok() as c_int
 }
 
@@ -162,20 +440,406 @@ pub extern "C" fn session_stop(s: *mut Session) -> c_int {
     session.stop().is_ok() as c_int
 }
 
+#[repr(C)]
+pub struct SessionStopCounts {
+    pub terminated: u32,
+    pub killed: u32,
+    pub survivors: u32,
+}
+
+#[no_mangle]
+pub extern "C" fn session_stop_graceful(s: *mut Session, grace_ms: u32, out: *mut SessionStopCounts) -> c_int {
+    if s.is_null() { return -1; }
+    let session = unsafe { &mut *s };
+    match session.stop_graceful(std::time::Duration::from_millis(grace_ms as u64)) {
+        Ok(report) => {
+            if !out.is_null() {
+                unsafe {
+                    *out = SessionStopCounts {
+                        terminated: report.terminated.len() as u32,
+                        killed: report.killed.len() as u32,
+                        survivors: report.survivors.len() as u32,
+                    };
+                }
+            }
+            1
+        }
+        Err(_) => 0,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn session_restart(s: *mut Session) -> c_int {
     if s.is_null() { return -1; }
     let s_ref = unsafe { &mut *s };
+    if !session_allowed(s_ref) { return 0; }
     s_ref.restart().is_ok() as c_int
 }
 
+/// Stop `s` and start the session `desktop_id` of the same user in its place
 #[no_mangle]
-pub extern "C" fn session_switch(s: *mut Session, new_name: *const c_char, new_exec: *const c_char) -> c_int {
+pub extern "C" fn session_switch(s: *mut Session, desktop_id: *const c_char) -> c_int {
     if s.is_null() { return -1; }
     let s_ref = unsafe { &mut *s };
-    let name_str = unsafe { CStr::from_ptr(new_name).to_string_lossy().to_string() };
-    let exec_str = unsafe { CStr::from_ptr(new_exec).to_string_lossy().to_string() };
-    s_ref.switch_session(&exec_str, &name_str).is_ok() as c_int
+    let (Some(user), Some(id_str)) = (s_ref.user.clone(), c_str_arg(desktop_id)) else { return 0; };
+    let next = match find_session(&user, &id_str) {
+        Ok(next) => next,
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            return 0;
+        }
+    };
+    if s_ref.stop().is_err() { return 0; }
+    s_ref.set_program(next.program());
+    s_ref.start().is_ok() as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn session_set_vt(s: *mut Session, vt: u32) {
+    if s.is_null() { return; }
+    let s_ref = unsafe { &mut *s };
+    s_ref.vt = if vt == 0 { None } else { Some(vt) };
+}
+
+// -------------------- VT FFI --------------------
+/// Returns the first free VT number, or -1
+#[no_mangle]
+pub extern "C" fn vt_find_free() -> c_int {
+    VtManager::new().backend.find_free().map(|n| n as c_int).unwrap_or(-1)
+}
+
+/// Activates `vt` and waits for the switch, returns 1 on success
+#[no_mangle]
+pub extern "C" fn vt_switch_to(vt: u32) -> c_int {
+    VtManager::new().switch_to(vt).is_ok() as c_int
+}
+
+/// Session `name` (desktop ID, name or alias) for `user`, NULL if missing or not allowed
+#[no_mangle]
+pub extern "C" fn session_find_for_user(user: *const c_char, name: *const c_char) -> *mut Session {
+    session_new(user, name)
+}
+
+/// Number of sessions visible to `user`
+#[no_mangle]
+pub extern "C" fn session_available_count(user: *const c_char) -> c_int {
+    if user.is_null() { return 0; }
+    let user_str = unsafe { CStr::from_ptr(user) }.to_string_lossy().to_string();
+    let config = config::load_config(Path::new("/etc/hyprdm/hyprdm.conf")).unwrap_or_default();
+    session_policy::sessions_for_user(&config, &user_str).len() as c_int
+}
+
+/// Display name of the visible session at `index`, free with string_free
+#[no_mangle]
+pub extern "C" fn session_available_name_at(user: *const c_char, index: u32) -> *mut c_char {
+    if user.is_null() { return ptr::null_mut(); }
+    let user_str = unsafe { CStr::from_ptr(user) }.to_string_lossy().to_string();
+    let config = config::load_config(Path::new("/etc/hyprdm/hyprdm.conf")).unwrap_or_default();
+    match session_policy::sessions_for_user(&config, &user_str).get(index as usize) {
+        Some(s) => CString::new(s.name.clone()).map(|c| c.into_raw()).unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    }
+}
+
+// -------------------- SessionSupervisor FFI --------------------
+/// kind: 0 exited, 1 exited with code, 2 killed by signal, 3 crashed,
+/// 4 restarted, 5 fell back to default, 6 return to greeter
+pub type SessionEventCallback = extern "C" fn(kind: c_int, value: c_int, user_data: *mut c_void);
+
+struct CallbackData(*mut c_void);
+unsafe impl Send for CallbackData {}
+
+impl CallbackData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Takes ownership of `s`, the caller must not use or free it afterwards.
+/// policy: 0 return to greeter, 1 retry with backoff, 2 fall back to default session
+#[no_mangle]
+pub extern "C" fn session_supervisor_new(
+    s: *mut Session,
+    policy: c_int,
+    crash_window_secs: u32,
+    max_retries: u32,
+) -> *mut SessionSupervisor {
+    if s.is_null() { return ptr::null_mut(); }
+    let session = unsafe { *Box::from_raw(s) };
+    let config_path = Path::new("/etc/hyprdm/hyprdm.conf");
+    let config = config::load_config(config_path).unwrap_or_default();
+    let policy = match policy {
+        1 => CrashPolicy::RetryWithBackoff {
+            max_retries,
+            initial_delay: std::time::Duration::from_secs(1),
+        },
+        2 => CrashPolicy::from_config(&config::HDMConfig {
+            crash_policy: "fallback".into(),
+            ..config
+        }),
+        _ => CrashPolicy::ReturnToGreeter,
+    };
+    let supervisor = SessionSupervisor::new(
+        session,
+        policy,
+        std::time::Duration::from_secs(crash_window_secs as u64),
+    );
+    Box::into_raw(Box::new(supervisor))
+}
+
+#[no_mangle]
+pub extern "C" fn session_supervisor_start(
+    sv: *mut SessionSupervisor,
+    cb: Option<SessionEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    if sv.is_null() { return -1; }
+    let sv_ref = unsafe { &mut *sv };
+    if let Some(cb) = cb {
+        let data = CallbackData(user_data);
+        sv_ref.on_event(move |event| {
+            let (kind, value) = match event {
+                SessionEvent::Exited => (0, 0),
+                SessionEvent::ExitedWithCode(code) => (1, *code),
+                SessionEvent::KilledBySignal(sig) => (2, *sig),
+                SessionEvent::Crashed { code, signal, .. } => (3, code.or(*signal).unwrap_or(-1)),
+                SessionEvent::Restarted { attempt } => (4, *attempt as c_int),
+                SessionEvent::FellBackToDefault { .. } => (5, 0),
+                SessionEvent::ReturnToGreeter => (6, 0),
+            };
+            cb(kind, value, data.get());
+        });
+    }
+    let started = sv_ref.session.lock().map(|mut s| {
+        s.child.is_some() || s.start().is_ok()
+    }).unwrap_or(false);
+    (started && sv_ref.spawn().is_ok()) as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn session_supervisor_stop(sv: *mut SessionSupervisor) -> c_int {
+    if sv.is_null() { return -1; }
+    let sv_ref = unsafe { &mut *sv };
+    sv_ref.stop();
+    sv_ref.session.lock().map(|mut s| s.stop().is_ok()).unwrap_or(false) as c_int
+}
+
+// -------------------- GreeterLauncher FFI --------------------
+/// kind: 0 started (value = pid), 1 ready, 2 crashed (value = code or signal),
+/// 3 fell back to built-in, 4 gave up, 5 exited, 6 stopped
+pub type GreeterEventCallback = extern "C" fn(kind: c_int, value: c_int, user_data: *mut c_void);
+
+/// Reads /etc/hyprdm/hyprdm.conf; vt 0 leaves XDG_VTNR unset
+#[no_mangle]
+pub extern "C" fn greeter_launcher_new(vt: u32) -> *mut GreeterLauncher {
+    let config = config::load_config(Path::new("/etc/hyprdm/hyprdm.conf")).unwrap_or_default();
+    match GreeterLauncher::from_config(&config) {
+        Ok(launcher) if vt > 0 => Box::into_raw(Box::new(launcher.with_vt(vt))),
+        Ok(launcher) => Box::into_raw(Box::new(launcher)),
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn greeter_launcher_start(
+    gl: *mut GreeterLauncher,
+    cb: Option<GreeterEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    if gl.is_null() { return -1; }
+    let gl_ref = unsafe { &mut *gl };
+    if let Some(cb) = cb {
+        let data = CallbackData(user_data);
+        gl_ref.on_event(move |event| {
+            let (kind, value) = match event {
+                GreeterEvent::Started { pid, .. } => (0, *pid as c_int),
+                GreeterEvent::Ready => (1, 0),
+                GreeterEvent::Crashed { code, signal } => (2, code.or(*signal).unwrap_or(-1)),
+                GreeterEvent::FellBackToBuiltin => (3, 0),
+                GreeterEvent::GaveUp => (4, 0),
+                GreeterEvent::Exited => (5, 0),
+                GreeterEvent::Stopped => (6, 0),
+            };
+            cb(kind, value, data.get());
+        });
+    }
+    gl_ref.spawn().is_ok() as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn greeter_launcher_stop(gl: *mut GreeterLauncher) {
+    if gl.is_null() { return; }
+    let gl_ref = unsafe { &mut *gl };
+    gl_ref.stop();
+}
+
+// -------------------- DaemonClient FFI --------------------
+/// Connect to the root daemon; NULL uses $HYPRDM_SOCKET, then /run/hyprdm/greeter.sock
+#[no_mangle]
+pub extern "C" fn daemon_client_connect(path: *const c_char) -> *mut DaemonClient {
+    let path = if path.is_null() {
+        std::env::var("HYPRDM_SOCKET").unwrap_or_else(|_| "/run/hyprdm/greeter.sock".into())
+    } else {
+        unsafe { CStr::from_ptr(path) }.to_string_lossy().to_string()
+    };
+    match DaemonClient::connect(Path::new(&path)) {
+        Ok(client) => Box::into_raw(Box::new(client)),
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn daemon_request(c: *mut DaemonClient, request: Request) -> Option<Response> {
+    if c.is_null() { return None; }
+    let c_ref = unsafe { &mut *c };
+    match c_ref.request(&request) {
+        Ok(Response::Error { message }) => {
+            eprintln!("[HyprDM] {}", message);
+            Some(Response::Error { message })
+        }
+        Ok(response) => Some(response),
+        Err(e) => {
+            eprintln!("[HyprDM] {}", e);
+            None
+        }
+    }
+}
+
+/// 0 failed, 1 authenticated, 2 a two-factor code is required
+#[no_mangle]
+pub extern "C" fn daemon_client_authenticate(
+    c: *mut DaemonClient,
+    username: *const c_char,
+    password: *const c_char,
+) -> c_int {
+    if username.is_null() || password.is_null() { return 0; }
+    let username = unsafe { CStr::from_ptr(username) }.to_string_lossy().to_string();
+    let password = unsafe { CStr::from_ptr(password) }.to_string_lossy().to_string();
+    match daemon_request(c, Request::Authenticate { username, password }) {
+        Some(Response::Ok) => 1,
+        Some(Response::TwoFactorRequired) => 2,
+        _ => 0,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn daemon_client_verify_2fa(c: *mut DaemonClient, code: *const c_char) -> c_int {
+    if code.is_null() { return 0; }
+    let code = unsafe { CStr::from_ptr(code) }.to_string_lossy().to_string();
+    matches!(daemon_request(c, Request::VerifyTwoFactor { code }), Some(Response::Ok)) as c_int
+}
+
+/// Returns the new session id, or -1; `restore_layout` != 0 replays the saved workspace layout
+#[no_mangle]
+pub extern "C" fn daemon_client_start_session(c: *mut DaemonClient, session: *const c_char, restore_layout: c_int) -> c_int {
+    if session.is_null() { return -1; }
+    let session = unsafe { CStr::from_ptr(session) }.to_string_lossy().to_string();
+    match daemon_request(c, Request::StartSession { session, restore_layout: restore_layout != 0 }) {
+        Some(Response::Started { id }) => id as c_int,
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn daemon_client_cancel(c: *mut DaemonClient) -> c_int {
+    matches!(daemon_request(c, Request::Cancel), Some(Response::Ok)) as c_int
+}
+
+// -------------------- SessionRegistry FFI --------------------
+#[no_mangle]
+pub extern "C" fn session_registry_new() -> *mut SessionRegistry {
+    Box::into_raw(Box::new(SessionRegistry::new()))
+}
+
+/// Takes ownership of `s`. Returns the new session id, or -1
+#[no_mangle]
+pub extern "C" fn session_registry_start(
+    reg: *mut SessionRegistry,
+    u: *const User,
+    password: *const c_char,
+    s: *mut Session,
+) -> c_int {
+    if reg.is_null() || u.is_null() || password.is_null() || s.is_null() { return -1; }
+    let reg_ref = unsafe { &mut *reg };
+    let u_ref = unsafe { &*u };
+    let session = unsafe { *Box::from_raw(s) };
+    let password_str = unsafe { CStr::from_ptr(password) }.to_string_lossy().to_string();
+    match u_ref.open_pam_handle(&password_str).and_then(|pam| reg_ref.start(pam, session)) {
+        Ok(id) => id as c_int,
+        Err(e) => {
+            eprintln!("[HyprDM] Failed to start session: {}", e);
+            -1
+        }
+    }
+}
+
+/// Returns the greeter's VT number, or -1
+#[no_mangle]
+pub extern "C" fn session_registry_open_greeter(reg: *mut SessionRegistry) -> c_int {
+    if reg.is_null() { return -1; }
+    let reg_ref = unsafe { &mut *reg };
+    reg_ref.open_greeter().map(|vt| vt as c_int).unwrap_or(-1)
+}
+
+/// Returns the id of the session switched to, or -1
+#[no_mangle]
+pub extern "C" fn session_registry_switch_to(
+    reg: *mut SessionRegistry,
+    u: *const User,
+    password: *const c_char,
+) -> c_int {
+    if reg.is_null() || u.is_null() || password.is_null() { return -1; }
+    let reg_ref = unsafe { &mut *reg };
+    let u_ref = unsafe { &*u };
+    let password_str = unsafe { CStr::from_ptr(password) }.to_string_lossy().to_string();
+    u_ref
+        .open_pam_handle(&password_str)
+        .and_then(|pam| reg_ref.switch_to(&pam))
+        .map(|id| id as c_int)
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub extern "C" fn session_registry_stop(reg: *mut SessionRegistry, id: u32) -> c_int {
+    if reg.is_null() { return -1; }
+    let reg_ref = unsafe { &mut *reg };
+    reg_ref.stop(id).is_ok() as c_int
+}
+
+#[no_mangle]
+pub extern "C" fn session_registry_count(reg: *mut SessionRegistry) -> c_int {
+    if reg.is_null() { return 0; }
+    let reg_ref = unsafe { &mut *reg };
+    reg_ref.reap();
+    reg_ref.list().len() as c_int
+}
+
+/// Username of the session at `index`, free with string_free
+#[no_mangle]
+pub extern "C" fn session_registry_user_at(reg: *const SessionRegistry, index: u32) -> *mut c_char {
+    if reg.is_null() { return ptr::null_mut(); }
+    let reg_ref = unsafe { &*reg };
+    match reg_ref.list().get(index as usize) {
+        Some(info) => CString::new(info.user.clone()).map(|c| c.into_raw()).unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// VT of the session at `index`, or -1
+#[no_mangle]
+pub extern "C" fn session_registry_vt_at(reg: *const SessionRegistry, index: u32) -> c_int {
+    if reg.is_null() { return -1; }
+    let reg_ref = unsafe { &*reg };
+    reg_ref.list()
+        .get(index as usize)
+        .and_then(|info| info.vt)
+        .map(|vt| vt as c_int)
+        .unwrap_or(-1)
 }
 
 // -------------------- ThemeManager FFI --------------------
@@ -183,13 +847,8 @@ pub extern "C" fn session_switch(s: *mut Session, new_name: *const c_char, new_e
 pub extern "C" fn theme_manager_new() -> *mut ThemeManager {
     let dummy_config = config::HDMConfig {
         theme: "".to_string(),
-        tiling: true,
-        default_session: "Hyprland".to_string(),
         autologin: false,
-        two_factor_enabled: false,
-        two_factor_method: None,
-        two_factor_secret: None,
-        systemctl_usedefine: false,
+        ..Default::default()
     };
     Box::into_raw(Box::new(ThemeManager::new(dummy_config, Default::default())))
 }
@@ -339,6 +998,13 @@ pub extern "C" fn compositor_free(c: *mut Compositor) {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn stop_handle_free(h: *mut StopHandle) {
+    if !h.is_null() {
+        unsafe { let _ = Box::from_raw(h); }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn ipc_free(ipc: *mut HyprlandIPC) {
     if !ipc.is_null() {
@@ -346,6 +1012,13 @@ pub extern "C" fn ipc_free(ipc: *mut HyprlandIPC) {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn ipc_events_free(sub: *mut EventSubscriber) {
+    if !sub.is_null() {
+        unsafe { let _ = Box::from_raw(sub); }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn layout_manager_free(lm: *mut LayoutManager) {
     if !lm.is_null() {
@@ -360,6 +1033,34 @@ pub extern "C" fn session_free(s: *mut Session) {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn session_supervisor_free(sv: *mut SessionSupervisor) {
+    if !sv.is_null() {
+        unsafe { let _ = Box::from_raw(sv); }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn daemon_client_free(c: *mut DaemonClient) {
+    if !c.is_null() {
+        unsafe { let _ = Box::from_raw(c); }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn greeter_launcher_free(gl: *mut GreeterLauncher) {
+    if !gl.is_null() {
+        unsafe { let _ = Box::from_raw(gl); }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn session_registry_free(reg: *mut SessionRegistry) {
+    if !reg.is_null() {
+        unsafe { let _ = Box::from_raw(reg); }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn theme_manager_free(tm: *mut ThemeManager) {
     if !tm.is_null() {